@@ -0,0 +1,201 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::schema::{self, Versioned};
+use crate::{get_current_time, Memory, MEMORY_MANAGER, ROLES_MEMORY_ID};
+
+// Roles are ordered by power: an Owner can do everything an Admin can,
+// and an Admin everything a Curator can.
+#[derive(
+    CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    Curator, // Can evolve the poet and steer the next prompt
+    Admin,   // Can (re)initialize and manage curators
+    Owner,   // Can wipe history and manage admins/owners
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct RoleGrant {
+    pub principal: Principal,
+    pub roles: Vec<Role>,
+    pub granted_by: Principal,
+    pub updated_at: u64,
+}
+
+impl RoleGrant {
+    fn highest(&self) -> Option<Role> {
+        self.roles.iter().copied().max()
+    }
+}
+
+impl Storable for RoleGrant {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1000,
+        is_fixed_size: false,
+    };
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for RoleGrant {
+    const VERSION: u16 = 1;
+
+    // No stand-in: a made-up grant could hand a role to the wrong principal,
+    // so an unreadable grant traps like any other stored value
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AuthError {
+    AnonymousCaller,
+    Unauthorized { caller: Principal, required: Role },
+    LastOwner, // Refused to revoke the only remaining owner
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::AnonymousCaller => write!(f, "Anonymous callers are not allowed"),
+            AuthError::Unauthorized { caller, required } => {
+                write!(f, "Caller {} lacks the {:?} role", caller, required)
+            }
+            AuthError::LastOwner => write!(f, "Cannot revoke the last owner"),
+        }
+    }
+}
+
+thread_local! {
+    static ROLES: RefCell<StableBTreeMap<Principal, RoleGrant, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ROLES_MEMORY_ID)),
+        )
+    );
+}
+
+// Highest role held by a principal. Canister controllers are always treated
+// as owners so a deployment upgraded from before roles existed stays manageable.
+pub fn role_of(principal: &Principal) -> Option<Role> {
+    if ic_cdk::api::is_controller(principal) {
+        return Some(Role::Owner);
+    }
+    ROLES.with(|roles| {
+        roles
+            .borrow()
+            .get(principal)
+            .and_then(|grant| grant.highest())
+    })
+}
+
+// Check the current caller against the required role
+pub fn require_role(required: Role) -> Result<Principal, AuthError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err(AuthError::AnonymousCaller);
+    }
+    match role_of(&caller) {
+        Some(role) if role >= required => Ok(caller),
+        _ => Err(AuthError::Unauthorized { caller, required }),
+    }
+}
+
+// Managing a role requires being an Owner for Owner/Admin, or an Admin for Curator
+fn required_to_manage(role: Role) -> Role {
+    match role {
+        Role::Owner | Role::Admin => Role::Owner,
+        Role::Curator => Role::Admin,
+    }
+}
+
+// Set up the first owner (called from init)
+pub fn bootstrap_owner(owner: Principal) {
+    if owner == Principal::anonymous() {
+        return;
+    }
+    insert_role(owner, Role::Owner, owner);
+}
+
+fn insert_role(principal: Principal, role: Role, granted_by: Principal) {
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        let mut grant = roles.get(&principal).unwrap_or(RoleGrant {
+            principal,
+            roles: Vec::new(),
+            granted_by,
+            updated_at: 0,
+        });
+        if !grant.roles.contains(&role) {
+            grant.roles.push(role);
+            grant.roles.sort();
+        }
+        grant.granted_by = granted_by;
+        grant.updated_at = get_current_time();
+        roles.insert(principal, grant);
+    });
+}
+
+pub fn grant(principal: Principal, role: Role) -> Result<(), AuthError> {
+    let caller = require_role(required_to_manage(role))?;
+    insert_role(principal, role, caller);
+    Ok(())
+}
+
+pub fn revoke(principal: Principal, role: Role) -> Result<(), AuthError> {
+    let caller = require_role(required_to_manage(role))?;
+
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+
+        if role == Role::Owner {
+            let owners = roles
+                .iter()
+                .filter(|(_, grant)| grant.roles.contains(&Role::Owner))
+                .count();
+            let is_owner = roles
+                .get(&principal)
+                .map(|grant| grant.roles.contains(&Role::Owner))
+                .unwrap_or(false);
+            if is_owner && owners <= 1 {
+                return Err(AuthError::LastOwner);
+            }
+        }
+
+        if let Some(mut grant) = roles.get(&principal) {
+            grant.roles.retain(|r| *r != role);
+            if grant.roles.is_empty() {
+                roles.remove(&principal);
+            } else {
+                grant.granted_by = caller;
+                grant.updated_at = get_current_time();
+                roles.insert(principal, grant);
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn roles_of(principal: &Principal) -> Vec<Role> {
+    ROLES.with(|roles| {
+        roles
+            .borrow()
+            .get(principal)
+            .map(|grant| grant.roles)
+            .unwrap_or_default()
+    })
+}
+
+pub fn list_grants() -> Vec<RoleGrant> {
+    ROLES.with(|roles| roles.borrow().iter().map(|(_, grant)| grant).collect())
+}
+
+// Rewrite every grant in the current encoding (schema migration)
+pub fn reencode_storage() {
+    ROLES.with(|roles| schema::reencode_map(&mut roles.borrow_mut()));
+}
@@ -0,0 +1,393 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::schema::{self, Versioned};
+use crate::{
+    poet_range, GenerationMethod, GenerationStats, Memory, PoemCycle, PoetId, MEMORY_MANAGER,
+    POEM_CYCLES, STATS_MEMORY_ID,
+};
+
+// Generation statistics kept as running counters per poet, per UTC day and
+// per week (starting Monday), updated as each poem is stored. Averages are
+// worked out from the sums when queried.
+
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const WEEK_NANOS: u64 = 7 * DAY_NANOS;
+// 1970-01-01 was a Thursday, three days after a Monday
+const WEEK_OFFSET_NANOS: u64 = 3 * DAY_NANOS;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum StatsBucket {
+    Day,
+    Week,
+}
+
+impl StatsBucket {
+    // Part of the stable key
+    fn code(self) -> u8 {
+        match self {
+            StatsBucket::Day => 0,
+            StatsBucket::Week => 1,
+        }
+    }
+
+    // Start of the bucket holding `time`, in nanoseconds
+    fn start_of(self, time: u64) -> u64 {
+        match self {
+            StatsBucket::Day => time - time % DAY_NANOS,
+            StatsBucket::Week => {
+                let shifted = time.saturating_add(WEEK_OFFSET_NANOS);
+                (shifted - shifted % WEEK_NANOS).saturating_sub(WEEK_OFFSET_NANOS)
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct StatsRange {
+    pub from: Option<u64>, // Inclusive, nanoseconds
+    pub to: Option<u64>,   // Inclusive, nanoseconds
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Counters {
+    pub poems: u64,
+    pub primary: u64,
+    pub fallback: u64,
+    pub corrected: u64,
+    pub algorithmic: u64,
+    pub structured: u64,
+    pub poem_chars: u64,
+    pub poem_lines: u64,
+    pub title_chars: u64,
+    pub next_prompt_chars: u64,
+    pub needed_correction: u64, // Poems that took at least one correction attempt
+    pub llm_calls: u64,
+    pub llm_latency_ns: u64, // Summed over llm_calls
+    pub max_llm_latency_ns: u64,
+}
+
+impl Storable for Counters {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 512,
+        is_fixed_size: false,
+    };
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for Counters {
+    const VERSION: u16 = 1;
+}
+
+impl Counters {
+    fn add_poem(&mut self, cycle: &PoemCycle, llm_latencies: &[u64]) {
+        self.poems += 1;
+        match cycle.generation_method {
+            GenerationMethod::Primary => self.primary += 1,
+            GenerationMethod::Fallback => self.fallback += 1,
+            GenerationMethod::Corrected => self.corrected += 1,
+            GenerationMethod::Algorithmic => self.algorithmic += 1,
+            GenerationMethod::Structured => self.structured += 1,
+        }
+        self.poem_chars += cycle.poem.chars().count() as u64;
+        self.poem_lines += cycle
+            .poem
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .count() as u64;
+        self.title_chars += cycle.title.chars().count() as u64;
+        self.next_prompt_chars += cycle.next_prompt.chars().count() as u64;
+        // Cycles from before attempts were recorded only show a successful correction
+        let corrected = match &cycle.corrections {
+            Some(attempts) => !attempts.is_empty(),
+            None => cycle.generation_method == GenerationMethod::Corrected,
+        };
+        if corrected {
+            self.needed_correction += 1;
+        }
+        self.llm_calls += llm_latencies.len() as u64;
+        self.llm_latency_ns += llm_latencies.iter().sum::<u64>();
+        self.max_llm_latency_ns = llm_latencies
+            .iter()
+            .copied()
+            .fold(self.max_llm_latency_ns, u64::max);
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatsPoint {
+    pub bucket_start: u64,
+    pub methods: GenerationStats,
+    pub avg_poem_chars: f64,
+    pub avg_poem_lines: f64,
+    pub avg_title_chars: f64,
+    pub avg_next_prompt_chars: f64,
+    pub correction_rate: f64, // Share of poems that needed a correction
+    pub llm_calls: u64,
+    pub avg_llm_latency_ms: Option<f64>, // None when no call was timed
+    pub max_llm_latency_ms: Option<f64>,
+}
+
+fn average(total: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+fn nanos_to_ms(nanos: u64) -> f64 {
+    nanos as f64 / 1_000_000.0
+}
+
+impl StatsPoint {
+    fn new(bucket_start: u64, counters: &Counters) -> Self {
+        let timed = counters.llm_calls > 0;
+        StatsPoint {
+            bucket_start,
+            methods: method_counts(counters),
+            avg_poem_chars: average(counters.poem_chars, counters.poems),
+            avg_poem_lines: average(counters.poem_lines, counters.poems),
+            avg_title_chars: average(counters.title_chars, counters.poems),
+            avg_next_prompt_chars: average(counters.next_prompt_chars, counters.poems),
+            correction_rate: average(counters.needed_correction, counters.poems),
+            llm_calls: counters.llm_calls,
+            avg_llm_latency_ms: timed
+                .then(|| average(counters.llm_latency_ns, counters.llm_calls) / 1_000_000.0),
+            max_llm_latency_ms: timed.then(|| nanos_to_ms(counters.max_llm_latency_ns)),
+        }
+    }
+}
+
+fn method_counts(counters: &Counters) -> GenerationStats {
+    GenerationStats {
+        total_poems: counters.poems,
+        primary_success: counters.primary,
+        fallback_used: counters.fallback,
+        correction_used: counters.corrected,
+        algorithmic_used: counters.algorithmic,
+        structured_success: counters.structured,
+    }
+}
+
+type StatsKey = (PoetId, u8, u64);
+
+thread_local! {
+    static STATS: RefCell<StableBTreeMap<StatsKey, Counters, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STATS_MEMORY_ID)),
+        )
+    );
+}
+
+// Count a newly stored cycle, with the time each LLM call it took
+pub fn record(poet_id: PoetId, cycle: &PoemCycle, llm_latencies: &[u64]) {
+    STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        for bucket in [StatsBucket::Day, StatsBucket::Week] {
+            let key = (poet_id, bucket.code(), bucket.start_of(cycle.created_at));
+            let mut counters = stats.get(&key).unwrap_or_default();
+            counters.add_poem(cycle, llm_latencies);
+            stats.insert(key, counters);
+        }
+    });
+}
+
+// Buckets in `range` that saw at least one poem, oldest first
+pub fn timeseries(poet_id: PoetId, bucket: StatsBucket, range: &StatsRange) -> Vec<StatsPoint> {
+    let from = bucket.start_of(range.from.unwrap_or(0));
+    let to = range.to.unwrap_or(u64::MAX);
+    if from > to {
+        return Vec::new();
+    }
+    STATS.with(|stats| {
+        stats
+            .borrow()
+            .range((poet_id, bucket.code(), from)..=(poet_id, bucket.code(), to))
+            .map(|((_, _, start), counters)| StatsPoint::new(start, &counters))
+            .collect()
+    })
+}
+
+// All-time method counts, summed from the weekly buckets
+pub fn totals(poet_id: PoetId) -> GenerationStats {
+    let code = StatsBucket::Week.code();
+    let counters = STATS.with(|stats| {
+        stats
+            .borrow()
+            .range((poet_id, code, 0)..=(poet_id, code, u64::MAX))
+            .fold(Counters::default(), |mut total, (_, week)| {
+                total.poems += week.poems;
+                total.primary += week.primary;
+                total.fallback += week.fallback;
+                total.corrected += week.corrected;
+                total.algorithmic += week.algorithmic;
+                total.structured += week.structured;
+                total
+            })
+    });
+    method_counts(&counters)
+}
+
+pub fn clear(poet_id: PoetId) {
+    STATS.with(|stats| {
+        let mut stats = stats.borrow_mut();
+        let keys: Vec<StatsKey> = stats
+            .range((poet_id, 0, 0)..=(poet_id, u8::MAX, u64::MAX))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            stats.remove(&key);
+        }
+    });
+}
+
+// Count every stored poem from scratch (schema migration). LLM latency was
+// not measured before, so older buckets have no timed calls.
+pub fn rebuild() {
+    for poet_id in crate::poet_ids() {
+        clear(poet_id);
+        let cycles: Vec<PoemCycle> = POEM_CYCLES.with(|cycles| {
+            cycles
+                .borrow()
+                .range(poet_range(poet_id))
+                .map(|(_, cycle)| cycle)
+                .collect()
+        });
+        for cycle in &cycles {
+            record(poet_id, cycle, &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{CorrectionAttempt, CorrectionKind};
+
+    // 2024-01-03T12:00:00Z, a Wednesday
+    const WEDNESDAY_NOON: u64 = 1_704_283_200 * 1_000_000_000;
+    // 2024-01-01T00:00:00Z, the Monday before
+    const MONDAY: u64 = 1_704_067_200 * 1_000_000_000;
+
+    fn cycle(
+        created_at: u64,
+        method: GenerationMethod,
+        poem: &str,
+        corrections: usize,
+    ) -> PoemCycle {
+        let attempt = CorrectionAttempt {
+            attempt: 1,
+            kind: CorrectionKind::Parse,
+            response: None,
+            error: None,
+            template_version: None,
+        };
+        PoemCycle {
+            poem: poem.to_string(),
+            title: "Rust".to_string(),
+            next_prompt: "Write about iron".to_string(),
+            created_at,
+            generation_method: method,
+            parent: Some(0),
+            corrections: Some(vec![attempt; corrections]),
+            ..PoemCycle::test(1)
+        }
+    }
+
+    #[test]
+    fn buckets_start_at_midnight_and_on_mondays() {
+        assert_eq!(
+            StatsBucket::Day.start_of(WEDNESDAY_NOON),
+            MONDAY + 2 * DAY_NANOS
+        );
+        assert_eq!(StatsBucket::Week.start_of(WEDNESDAY_NOON), MONDAY);
+        assert_eq!(StatsBucket::Week.start_of(MONDAY), MONDAY);
+        assert_eq!(StatsBucket::Week.start_of(MONDAY - 1), MONDAY - WEEK_NANOS);
+    }
+
+    #[test]
+    fn counters_roll_up_by_day_and_week() {
+        record(
+            4,
+            &cycle(WEDNESDAY_NOON, GenerationMethod::Primary, "one\ntwo", 0),
+            &[2_000_000],
+        );
+        record(
+            4,
+            &cycle(WEDNESDAY_NOON + 60, GenerationMethod::Corrected, "four", 1),
+            &[1_000_000, 3_000_000],
+        );
+        record(
+            4,
+            &cycle(
+                WEDNESDAY_NOON + DAY_NANOS,
+                GenerationMethod::Algorithmic,
+                "x",
+                1,
+            ),
+            &[],
+        );
+
+        let days = timeseries(4, StatsBucket::Day, &StatsRange::default());
+        assert_eq!(days.len(), 2);
+        let wednesday = &days[0];
+        assert_eq!(wednesday.bucket_start, MONDAY + 2 * DAY_NANOS);
+        assert_eq!(
+            (
+                wednesday.methods.total_poems,
+                wednesday.methods.primary_success,
+                wednesday.methods.correction_used
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            (wednesday.avg_poem_chars, wednesday.avg_poem_lines),
+            (5.5, 1.5)
+        );
+        assert_eq!(wednesday.correction_rate, 0.5);
+        assert_eq!(
+            (
+                wednesday.llm_calls,
+                wednesday.avg_llm_latency_ms,
+                wednesday.max_llm_latency_ms
+            ),
+            (3, Some(2.0), Some(3.0))
+        );
+        assert_eq!(days[1].avg_llm_latency_ms, None);
+
+        let weeks = timeseries(4, StatsBucket::Week, &StatsRange::default());
+        assert_eq!(
+            (
+                weeks.len(),
+                weeks[0].bucket_start,
+                weeks[0].methods.total_poems
+            ),
+            (1, MONDAY, 3)
+        );
+        assert_eq!(totals(4).algorithmic_used, 1);
+
+        // A range starting mid-day still includes that day
+        let thursday = timeseries(
+            4,
+            StatsBucket::Day,
+            &StatsRange {
+                from: Some(WEDNESDAY_NOON + DAY_NANOS + 1),
+                to: None,
+            },
+        );
+        assert_eq!(thursday.len(), 1);
+
+        clear(4);
+        assert!(timeseries(4, StatsBucket::Week, &StatsRange::default()).is_empty());
+        assert_eq!(totals(4).total_poems, 0);
+    }
+}
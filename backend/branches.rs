@@ -0,0 +1,294 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::schema::{self, Versioned};
+use crate::{Memory, PoemCycle, PoetId, BRANCHES_MEMORY_ID, MEMORY_MANAGER};
+
+// A poet's history is a tree. Every cycle points at its parent, and cycles
+// continuing the same line share a branch id. Branch 0 is the original line
+// and is never stored; forks are.
+pub type BranchId = u32;
+pub const MAIN_BRANCH: BranchId = 0;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct Branch {
+    pub forked_from: u64, // Cycle the branch continues from, 0 = from scratch
+    pub prompt: String,   // Replaces that cycle's next_prompt for the first cycle
+    pub created_at: u64,
+}
+
+impl Storable for Branch {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 4096,
+        is_fixed_size: false,
+    };
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for Branch {
+    const VERSION: u16 = 1;
+}
+
+thread_local! {
+    static BRANCHES: RefCell<StableBTreeMap<(PoetId, BranchId), Branch, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BRANCHES_MEMORY_ID)),
+        )
+    );
+}
+
+pub fn get(poet_id: PoetId, branch_id: BranchId) -> Option<Branch> {
+    BRANCHES.with(|branches| branches.borrow().get(&(poet_id, branch_id)))
+}
+
+// The fork the next evolution starts, as long as the active branch has no
+// cycle yet: its prompt then stands in for `current`'s next_prompt
+pub fn pending(poet_id: PoetId, active: BranchId, current: Option<&PoemCycle>) -> Option<Branch> {
+    if current.is_some_and(|cycle| branch_of(cycle) == active) {
+        return None;
+    }
+    get(poet_id, active)
+}
+
+pub fn set_prompt(poet_id: PoetId, branch_id: BranchId, prompt: String) {
+    BRANCHES.with(|branches| {
+        let mut branches = branches.borrow_mut();
+        if let Some(mut branch) = branches.get(&(poet_id, branch_id)) {
+            branch.prompt = prompt;
+            branches.insert((poet_id, branch_id), branch);
+        }
+    });
+}
+
+// Store a new fork and hand back its id
+pub fn create(poet_id: PoetId, branch: Branch) -> BranchId {
+    BRANCHES.with(|branches| {
+        let mut branches = branches.borrow_mut();
+        let branch_id = branches
+            .range((poet_id, MAIN_BRANCH)..=(poet_id, BranchId::MAX))
+            .next_back()
+            .map_or(MAIN_BRANCH, |((_, id), _)| id)
+            + 1;
+        branches.insert((poet_id, branch_id), branch);
+        branch_id
+    })
+}
+
+// Forget every fork of a poet (reset)
+pub fn clear(poet_id: PoetId) {
+    BRANCHES.with(|branches| {
+        let mut branches = branches.borrow_mut();
+        let keys: Vec<_> = branches
+            .range((poet_id, MAIN_BRANCH)..=(poet_id, BranchId::MAX))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            branches.remove(&key);
+        }
+    });
+}
+
+// Cycles written before branching existed carry no links: they sit on the
+// main branch and follow the cycle numbered just before them.
+pub fn parent_of(cycle: &PoemCycle) -> u64 {
+    cycle.parent.unwrap_or(cycle.cycle_number.saturating_sub(1))
+}
+
+pub fn branch_of(cycle: &PoemCycle) -> BranchId {
+    cycle.branch.unwrap_or(MAIN_BRANCH)
+}
+
+// What list_branches reports about one branch
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct BranchSummary {
+    pub branch_id: BranchId,
+    pub forked_from: u64,
+    pub prompt: Option<String>, // None for the main branch, which starts at the genesis prompt
+    pub created_at: u64,
+    pub head: Option<u64>, // Newest cycle, None until the branch has one
+    pub cycle_count: u64,
+    pub active: bool, // Where the next evolution continues
+}
+
+// Every branch of a poet, main branch first. Heads and counts come from the
+// cycles themselves.
+pub fn summarize(
+    poet_id: PoetId,
+    active: BranchId,
+    cycles: impl Iterator<Item = PoemCycle>,
+) -> Vec<BranchSummary> {
+    let mut summaries: BTreeMap<BranchId, BranchSummary> = BTreeMap::new();
+    summaries.insert(
+        MAIN_BRANCH,
+        BranchSummary {
+            branch_id: MAIN_BRANCH,
+            forked_from: 0,
+            prompt: None,
+            created_at: 0,
+            head: None,
+            cycle_count: 0,
+            active: false,
+        },
+    );
+    BRANCHES.with(|branches| {
+        for ((_, branch_id), branch) in branches
+            .borrow()
+            .range((poet_id, MAIN_BRANCH)..=(poet_id, BranchId::MAX))
+        {
+            summaries.insert(
+                branch_id,
+                BranchSummary {
+                    branch_id,
+                    forked_from: branch.forked_from,
+                    prompt: Some(branch.prompt),
+                    created_at: branch.created_at,
+                    head: None,
+                    cycle_count: 0,
+                    active: false,
+                },
+            );
+        }
+    });
+
+    for cycle in cycles {
+        if let Some(summary) = summaries.get_mut(&branch_of(&cycle)) {
+            summary.cycle_count += 1;
+            summary.head = summary.head.max(Some(cycle.cycle_number));
+        }
+    }
+    if let Some(summary) = summaries.get_mut(&active) {
+        summary.active = true;
+    }
+    summaries.into_values().collect()
+}
+
+// Follow parent pointers from `cycle_number` back towards the first cycle,
+// newest first, stopping after `limit` cycles
+pub fn ancestry(
+    cycle_number: u64,
+    limit: usize,
+    lookup: impl Fn(u64) -> Option<PoemCycle>,
+) -> Vec<PoemCycle> {
+    let mut line = Vec::new();
+    let mut next = cycle_number;
+    while next > 0 && line.len() < limit {
+        let Some(cycle) = lookup(next) else {
+            break;
+        };
+        // Parents always have smaller numbers; anything else would loop
+        let parent = parent_of(&cycle);
+        next = if parent < cycle.cycle_number {
+            parent
+        } else {
+            0
+        };
+        line.push(cycle);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(n: u64, parent: Option<u64>, branch: Option<BranchId>) -> PoemCycle {
+        PoemCycle {
+            parent,
+            branch,
+            ..PoemCycle::test(n)
+        }
+    }
+
+    // 1 <- 2 <- 3 (legacy, no links), 4 forks from 2, 5 continues 4
+    fn tree() -> Vec<PoemCycle> {
+        vec![
+            cycle(1, None, None),
+            cycle(2, None, None),
+            cycle(3, None, None),
+            cycle(4, Some(2), Some(1)),
+            cycle(5, Some(4), Some(1)),
+        ]
+    }
+
+    #[test]
+    fn ancestry_follows_parents_across_a_fork() {
+        let cycles = tree();
+        let lookup = |n: u64| cycles.iter().find(|c| c.cycle_number == n).cloned();
+
+        let line: Vec<u64> = ancestry(5, 100, lookup)
+            .iter()
+            .map(|c| c.cycle_number)
+            .collect();
+        assert_eq!(line, vec![5, 4, 2, 1]);
+
+        let line: Vec<u64> = ancestry(3, 2, lookup)
+            .iter()
+            .map(|c| c.cycle_number)
+            .collect();
+        assert_eq!(line, vec![3, 2]);
+        assert!(ancestry(9, 100, lookup).is_empty());
+    }
+
+    #[test]
+    fn summaries_count_cycles_per_branch() {
+        let forked = create(
+            7,
+            Branch {
+                forked_from: 2,
+                prompt: "Write about a second chance".to_string(),
+                created_at: 10,
+            },
+        );
+        let empty = create(
+            7,
+            Branch {
+                forked_from: 5,
+                prompt: "Write about nothing yet".to_string(),
+                created_at: 20,
+            },
+        );
+        assert_eq!((forked, empty), (1, 2));
+
+        let summaries = summarize(7, empty, tree().into_iter());
+        assert_eq!(summaries.len(), 3);
+        assert_eq!((summaries[0].head, summaries[0].cycle_count), (Some(3), 3));
+        assert_eq!((summaries[1].head, summaries[1].cycle_count), (Some(5), 2));
+        assert_eq!(summaries[1].forked_from, 2);
+        assert_eq!((summaries[2].head, summaries[2].cycle_count), (None, 0));
+        assert!(summaries[2].active && !summaries[0].active);
+
+        clear(7);
+        assert_eq!(summarize(7, MAIN_BRANCH, std::iter::empty()).len(), 1);
+    }
+
+    #[test]
+    fn fork_is_pending_until_its_first_cycle() {
+        let fork = create(
+            3,
+            Branch {
+                forked_from: 2,
+                prompt: "Write about the road not taken".to_string(),
+                created_at: 0,
+            },
+        );
+        let fork_point = cycle(2, None, None);
+        assert!(pending(3, fork, Some(&fork_point)).is_some());
+        assert!(pending(3, MAIN_BRANCH, Some(&fork_point)).is_none());
+
+        set_prompt(3, fork, "Write about the other road".to_string());
+        assert_eq!(
+            pending(3, fork, None).unwrap().prompt,
+            "Write about the other road"
+        );
+        assert!(pending(3, fork, Some(&cycle(4, Some(2), Some(fork)))).is_none());
+    }
+}
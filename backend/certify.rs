@@ -0,0 +1,395 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use candid::{CandidType, Deserialize};
+use ic_certified_map::{
+    fork, fork_hash, labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::http::{self, HttpResponse};
+use crate::{PoemCycle, PoetId, DEFAULT_POET, POEM_CYCLES, POET_STATE};
+
+// The certified tree has two labeled subtrees:
+//   http_assets/<path>                     -> sha256 of the response body (HTTP certification v1)
+//   poems/<poet as u32 BE><cycle as u64 BE> -> poem_hash() of the cycle (Candid queries)
+//
+// Certified: every poem page, each poet's latest page, and the default bodies
+// of the listings (see http::certified_listings). Not certified: the Candid
+// list queries (list_poems, get_all_poems - use get_certified_poem per cycle),
+// listings with a query string, and error responses.
+const HTTP_ASSETS_LABEL: &[u8] = b"http_assets";
+const POEMS_LABEL: &[u8] = b"poems";
+
+#[derive(Default)]
+struct CertifiedTree {
+    assets: RbTree<String, Hash>,
+    poems: RbTree<Vec<u8>, Hash>,
+}
+
+impl CertifiedTree {
+    fn root_hash(&self) -> Hash {
+        fork_hash(
+            &labeled_hash(HTTP_ASSETS_LABEL, &self.assets.root_hash()),
+            &labeled_hash(POEMS_LABEL, &self.poems.root_hash()),
+        )
+    }
+
+    fn asset_witness(&self, path: &str) -> HashTree<'_> {
+        fork(
+            labeled(HTTP_ASSETS_LABEL, self.assets.witness(path.as_bytes())),
+            HashTree::Pruned(labeled_hash(POEMS_LABEL, &self.poems.root_hash())),
+        )
+    }
+
+    fn poem_witness(&self, key: &[u8]) -> HashTree<'_> {
+        fork(
+            HashTree::Pruned(labeled_hash(HTTP_ASSETS_LABEL, &self.assets.root_hash())),
+            labeled(POEMS_LABEL, self.poems.witness(key)),
+        )
+    }
+}
+
+thread_local! {
+    // Lives on the heap: rebuilt from POEM_CYCLES on init/upgrade
+    static TREE: RefCell<CertifiedTree> = RefCell::new(CertifiedTree::default());
+}
+
+// A poem plus what a client needs to check it against the subnet's signature.
+// Verify the certificate, check that its certified_data equals the witness root,
+// then look up poems/<poet id as 4 big-endian bytes><cycle_number as 8
+// big-endian bytes> and compare it with poem_hash of the returned poem.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CertifiedPoem {
+    pub poem: PoemCycle,
+    pub certificate: Option<Vec<u8>>, // None when called as an update
+    pub witness: Vec<u8>,             // CBOR-encoded HashTree
+}
+
+fn poem_key(poet_id: PoetId, cycle_number: u64) -> Vec<u8> {
+    let mut key = poet_id.to_be_bytes().to_vec();
+    key.extend_from_slice(&cycle_number.to_be_bytes());
+    key
+}
+
+// sha256 over cycle_number and created_at (u64 big-endian) followed by title,
+// poem and next_prompt, each prefixed with its byte length as a u64 big-endian.
+// raw_response and generation metadata are deliberately left out.
+pub fn poem_hash(cycle: &PoemCycle) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(cycle.cycle_number.to_be_bytes());
+    hasher.update(cycle.created_at.to_be_bytes());
+    for field in [&cycle.title, &cycle.poem, &cycle.next_prompt] {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn body_hash(body: &[u8]) -> Hash {
+    Sha256::digest(body).into()
+}
+
+// Only the canonical path of each poem is certified (see http::poems_path)
+fn insert_cycle(tree: &mut CertifiedTree, poet_id: PoetId, cycle: &PoemCycle, is_latest: bool) {
+    let html = body_hash(http::render_poem_html(poet_id, cycle).as_bytes());
+    let text = body_hash(http::render_poem_text(cycle).as_bytes());
+    let base = http::poems_path(poet_id);
+
+    tree.poems
+        .insert(poem_key(poet_id, cycle.cycle_number), poem_hash(cycle));
+    tree.assets
+        .insert(format!("{}/{}", base, cycle.cycle_number), html);
+    tree.assets
+        .insert(format!("{}/{}.txt", base, cycle.cycle_number), text);
+    if is_latest {
+        tree.assets.insert(format!("{}/latest", base), html);
+        tree.assets.insert(format!("{}/latest.txt", base), text);
+    }
+}
+
+fn insert_latest(tree: &mut CertifiedTree, poet_id: PoetId, latest: Option<&PoemCycle>) {
+    let base = http::poems_path(poet_id);
+    match latest {
+        Some(cycle) => {
+            tree.assets.insert(
+                format!("{}/latest", base),
+                body_hash(http::render_poem_html(poet_id, cycle).as_bytes()),
+            );
+            tree.assets.insert(
+                format!("{}/latest.txt", base),
+                body_hash(http::render_poem_text(cycle).as_bytes()),
+            );
+        }
+        None => {
+            tree.assets.delete(format!("{}/latest", base).as_bytes());
+            tree.assets
+                .delete(format!("{}/latest.txt", base).as_bytes());
+        }
+    }
+}
+
+// The listings show the default poet's newest poems, so they follow its changes
+fn insert_listings(tree: &mut CertifiedTree) {
+    for path in http::LISTING_PATHS {
+        tree.assets.delete(path.as_bytes());
+    }
+    for (path, body) in http::certified_listings() {
+        tree.assets.insert(path.to_string(), body_hash(&body));
+    }
+}
+
+// (Re)certify one cycle after it was inserted or edited
+pub fn certify_cycle(poet_id: PoetId, cycle: &PoemCycle, is_latest: bool) {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        insert_cycle(&mut tree, poet_id, cycle, is_latest);
+        if poet_id == DEFAULT_POET {
+            insert_listings(&mut tree);
+        }
+        set_certified_data(&tree.root_hash());
+    });
+}
+
+// A poet moved to another cycle without writing one (fork, initialize)
+pub fn certify_latest(poet_id: PoetId, latest: Option<&PoemCycle>) {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        insert_latest(&mut tree, poet_id, latest);
+        set_certified_data(&tree.root_hash());
+    });
+}
+
+// Drop the cycles a reset removed; other poets keep their entries
+pub fn forget_cycles(poet_id: PoetId, cycle_numbers: &[u64]) {
+    let base = http::poems_path(poet_id);
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        for cycle_number in cycle_numbers {
+            tree.poems.delete(&poem_key(poet_id, *cycle_number));
+            tree.assets
+                .delete(format!("{}/{}", base, cycle_number).as_bytes());
+            tree.assets
+                .delete(format!("{}/{}.txt", base, cycle_number).as_bytes());
+        }
+        insert_latest(&mut tree, poet_id, None);
+        if poet_id == DEFAULT_POET {
+            insert_listings(&mut tree);
+        }
+        set_certified_data(&tree.root_hash());
+    });
+}
+
+// The feed bodies depend on the feed config
+pub fn certify_listings() {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        insert_listings(&mut tree);
+        set_certified_data(&tree.root_hash());
+    });
+}
+
+// Recompute the whole tree from stable memory. Only for init and post_upgrade,
+// when the heap tree is gone; everything else updates it in place.
+pub fn rebuild() {
+    let latest: BTreeMap<PoetId, u64> = POET_STATE.with(|state| {
+        state
+            .borrow()
+            .iter()
+            .map(|(poet_id, poet)| (poet_id, poet.current_cycle))
+            .collect()
+    });
+    let mut fresh = CertifiedTree::default();
+    POEM_CYCLES.with(|cycles| {
+        for ((poet_id, cycle_number), cycle) in cycles.borrow().iter() {
+            let is_latest = latest.get(&poet_id) == Some(&cycle_number);
+            insert_cycle(&mut fresh, poet_id, &cycle, is_latest);
+        }
+    });
+    insert_listings(&mut fresh);
+    set_certified_data(&fresh.root_hash());
+    TREE.with(|tree| *tree.borrow_mut() = fresh);
+}
+
+pub fn certified_poem(poet_id: PoetId, cycle: PoemCycle) -> CertifiedPoem {
+    let witness = TREE.with(|tree| {
+        encode_tree(
+            &tree
+                .borrow()
+                .poem_witness(&poem_key(poet_id, cycle.cycle_number)),
+        )
+    });
+    CertifiedPoem {
+        poem: cycle,
+        certificate: data_certificate(),
+        witness,
+    }
+}
+
+// Attach an IC-Certificate header when the body is exactly what was certified
+// for this path. Anything else (errors, content negotiation, HEAD) goes out
+// uncertified rather than with a certificate that would not verify.
+pub fn certify_response(path: &str, response: &mut HttpResponse) {
+    let Some(certificate) = data_certificate() else {
+        return;
+    };
+    let witness = TREE.with(|tree| {
+        let tree = tree.borrow();
+        match tree.assets.get(path.as_bytes()) {
+            Some(hash) if *hash == body_hash(&response.body) => {
+                Some(encode_tree(&tree.asset_witness(path)))
+            }
+            _ => None,
+        }
+    });
+    if let Some(witness) = witness {
+        response.headers.push((
+            "IC-Certificate".to_string(),
+            format!(
+                "certificate=:{}:, tree=:{}:",
+                BASE64.encode(certificate),
+                BASE64.encode(witness)
+            ),
+        ));
+    }
+}
+
+fn encode_tree(tree: &HashTree) -> Vec<u8> {
+    let mut serializer = serde_cbor::ser::Serializer::new(Vec::new());
+    serializer
+        .self_describe()
+        .expect("failed to write CBOR tag");
+    tree.serialize(&mut serializer)
+        .expect("failed to encode hash tree");
+    serializer.into_inner()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn set_certified_data(root: &Hash) {
+    ic_cdk::api::set_certified_data(root);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn set_certified_data(_root: &Hash) {}
+
+#[cfg(target_arch = "wasm32")]
+fn data_certificate() -> Option<Vec<u8>> {
+    ic_cdk::api::data_certificate()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn data_certificate() -> Option<Vec<u8>> {
+    None
+}
+
+// Root hash of the current tree, as set_certified_data last saw it
+#[cfg(test)]
+fn root_hash() -> Hash {
+    TREE.with(|tree| tree.borrow().root_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(n: u64, next_prompt: &str) -> PoemCycle {
+        PoemCycle {
+            next_prompt: next_prompt.to_string(),
+            created_at: n * 1_000,
+            ..PoemCycle::test(n)
+        }
+    }
+
+    // The witness must reconstruct to the same root that was certified
+    #[test]
+    fn poem_witness_matches_root() {
+        certify_cycle(0, &cycle(1, "a"), false);
+        certify_cycle(0, &cycle(2, "b"), true);
+
+        let witness = TREE.with(|tree| tree.borrow().poem_witness(&poem_key(0, 2)).reconstruct());
+        assert_eq!(witness, root_hash());
+
+        let certified = certified_poem(0, cycle(2, "b"));
+        assert!(certified.certificate.is_none());
+        assert_eq!(&certified.witness[..3], &[0xd9, 0xd9, 0xf7]);
+    }
+
+    #[test]
+    fn editing_a_cycle_changes_the_root() {
+        certify_cycle(0, &cycle(1, "before"), true);
+        let before = root_hash();
+        certify_cycle(0, &cycle(1, "after"), true);
+        assert_ne!(before, root_hash());
+        assert_ne!(
+            poem_hash(&cycle(1, "before")),
+            poem_hash(&cycle(1, "after"))
+        );
+    }
+
+    #[test]
+    fn asset_hashes_match_rendered_bodies() {
+        let latest = cycle(3, "c");
+        certify_cycle(0, &latest, true);
+        certify_cycle(2, &latest, true);
+
+        TREE.with(|tree| {
+            let tree = tree.borrow();
+            let html = body_hash(http::render_poem_html(0, &latest).as_bytes());
+            assert_eq!(tree.assets.get(b"/poems/3"), Some(&html));
+            assert_eq!(tree.assets.get(b"/poems/latest"), Some(&html));
+            assert_eq!(
+                tree.asset_witness("/poems/3.txt").reconstruct(),
+                tree.root_hash()
+            );
+
+            let other = body_hash(http::render_poem_html(2, &latest).as_bytes());
+            assert_eq!(tree.assets.get(b"/poets/2/poems/latest"), Some(&other));
+            assert!(tree.poems.get(&poem_key(2, 3)).is_some());
+        });
+    }
+
+    #[test]
+    fn listings_are_certified_without_a_query_only() {
+        let request = |url: &str| http::HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        crate::save_poet_state(DEFAULT_POET, crate::default_poet_state());
+        let first = cycle(1, "a");
+        POEM_CYCLES.with(|cycles| cycles.borrow_mut().insert((DEFAULT_POET, 1), first.clone()));
+        certify_cycle(DEFAULT_POET, &first, true);
+
+        let certified = |url: &str| {
+            let (path, _) = http::split_url(url);
+            let body = http::handle(&request(url)).body;
+            TREE.with(|tree| tree.borrow().assets.get(path.as_bytes()) == Some(&body_hash(&body)))
+        };
+        assert!(certified("/api/poems.json"));
+        assert!(!certified("/api/poems.json?poet=3"));
+        assert!(!certified("/poems/7"));
+        // Feed links follow the Host header until a site_url is set
+        assert!(!certified("/feed.rss"));
+        let config = crate::feed::FeedConfig {
+            site_url: Some("https://poet.example".to_string()),
+            ..crate::feed::FeedConfig::default()
+        };
+        crate::feed::set_config(config).unwrap();
+        certify_listings();
+        assert!(certified("/feed.rss"));
+        assert!(certified("/feed.atom"));
+
+        // A reset drops the poet's pages and updates the listings
+        POEM_CYCLES.with(|cycles| cycles.borrow_mut().remove(&(DEFAULT_POET, 1)));
+        forget_cycles(DEFAULT_POET, &[1]);
+        TREE.with(|tree| {
+            let tree = tree.borrow();
+            assert!(tree.assets.get(b"/poems/1").is_none());
+            assert!(tree.assets.get(b"/poems/latest").is_none());
+            assert!(tree.poems.get(&poem_key(DEFAULT_POET, 1)).is_none());
+        });
+        assert!(certified("/api/poems.json"));
+    }
+}
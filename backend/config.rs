@@ -0,0 +1,219 @@
+use candid::{CandidType, Deserialize};
+use ic_llm::Model;
+use ic_stable_structures::{storable::Bound, StableCell, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::error::PoetError;
+use crate::memory::MemoryOptions;
+use crate::novelty::NoveltyOptions;
+use crate::schema::{self, Versioned};
+use crate::wrap::WrapOptions;
+use crate::{Memory, GENERATION_CONFIG_MEMORY_ID, MEMORY_MANAGER};
+
+const MAX_CORRECTION_RETRIES: u8 = 5;
+const MIN_WRAP_WIDTH: u32 = 20;
+const MAX_WRAP_WIDTH: u32 = 200;
+const MAX_MEMORY_TOKENS: u32 = 4000;
+const MAX_MEMORY_SEARCH: u32 = 1000;
+const MAX_NOVELTY_WINDOW: u32 = 100;
+const MAX_NOVELTY_REPROMPTS: u8 = 3;
+
+// Candid-friendly mirror of ic_llm::Model, which is neither Clone nor CandidType
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum LlmModel {
+    Llama3_1_8B,
+    Qwen3_32B,
+    Llama4Scout,
+}
+
+impl LlmModel {
+    pub fn to_model(self) -> Model {
+        match self {
+            LlmModel::Llama3_1_8B => Model::Llama3_1_8B,
+            LlmModel::Qwen3_32B => Model::Qwen3_32B,
+            LlmModel::Llama4Scout => Model::Llama4Scout,
+        }
+    }
+}
+
+impl fmt::Display for LlmModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_model())
+    }
+}
+
+// How the meta form asks the model to lay out its answer
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum OutputContract {
+    #[default]
+    Labels, // POEM: / TITLE: / NEXT: sections
+    Json, // {"poem", "title", "next"} object, label parser as fallback
+}
+
+// Use a different model for one specific cycle (e.g. to A/B a model)
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CycleOverride {
+    pub cycle_number: u64,
+    pub model: LlmModel,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct GenerationConfig {
+    pub model: LlmModel,            // Model for the creative pass
+    pub correction_model: LlmModel, // Model for format correction passes
+    pub max_correction_retries: u8, // 0 = go straight to algorithmic fallback
+    pub cycle_overrides: Vec<CycleOverride>,
+    pub wrap: Option<WrapOptions>, // None = WrapOptions::default()
+    pub output_contract: Option<OutputContract>, // None = Labels
+    pub memory: Option<MemoryOptions>, // None = MemoryOptions::default()
+    pub novelty: Option<NoveltyOptions>, // None = NoveltyOptions::default()
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        GenerationConfig {
+            model: LlmModel::Llama3_1_8B,
+            correction_model: LlmModel::Llama3_1_8B,
+            max_correction_retries: 1,
+            cycle_overrides: Vec::new(),
+            wrap: None,
+            output_contract: None,
+            memory: None,
+            novelty: None,
+        }
+    }
+}
+
+impl GenerationConfig {
+    // The model that should write the given cycle
+    pub fn model_for_cycle(&self, cycle_number: u64) -> LlmModel {
+        self.cycle_overrides
+            .iter()
+            .find(|o| o.cycle_number == cycle_number)
+            .map(|o| o.model)
+            .unwrap_or(self.model)
+    }
+
+    pub fn wrap_options(&self) -> WrapOptions {
+        self.wrap.unwrap_or_default()
+    }
+
+    pub fn output_contract(&self) -> OutputContract {
+        self.output_contract.unwrap_or_default()
+    }
+
+    pub fn memory_options(&self) -> MemoryOptions {
+        self.memory.unwrap_or_default()
+    }
+
+    pub fn novelty_options(&self) -> NoveltyOptions {
+        self.novelty.unwrap_or_default()
+    }
+}
+
+impl Storable for GenerationConfig {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for GenerationConfig {
+    const VERSION: u16 = 1;
+
+    fn unreadable(_error: String) -> Self {
+        GenerationConfig::default()
+    }
+}
+
+thread_local! {
+    static GENERATION_CONFIG: RefCell<StableCell<GenerationConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(GENERATION_CONFIG_MEMORY_ID)),
+            GenerationConfig::default(),
+        ).expect("failed to initialize generation config cell")
+    );
+}
+
+pub fn get() -> GenerationConfig {
+    GENERATION_CONFIG.with(|c| c.borrow().get().clone())
+}
+
+pub fn set(config: GenerationConfig) -> Result<GenerationConfig, PoetError> {
+    if config.max_correction_retries > MAX_CORRECTION_RETRIES {
+        return Err(PoetError::InvalidConfig(format!(
+            "At most {} correction retries are allowed",
+            MAX_CORRECTION_RETRIES
+        )));
+    }
+    let wrap = config.wrap_options();
+    if !(MIN_WRAP_WIDTH..=MAX_WRAP_WIDTH).contains(&wrap.width) {
+        return Err(PoetError::InvalidConfig(format!(
+            "Wrap width must be between {} and {} columns",
+            MIN_WRAP_WIDTH, MAX_WRAP_WIDTH
+        )));
+    }
+    if wrap.hanging_indent >= wrap.width / 2 {
+        return Err(PoetError::InvalidConfig(
+            "Hanging indent must be less than half the wrap width".to_string(),
+        ));
+    }
+    let memory = config.memory_options();
+    if memory.token_budget > MAX_MEMORY_TOKENS {
+        return Err(PoetError::InvalidConfig(format!(
+            "Memory may use at most {} tokens",
+            MAX_MEMORY_TOKENS
+        )));
+    }
+    if memory.search_window > MAX_MEMORY_SEARCH || memory.recent_window > memory.search_window {
+        return Err(PoetError::InvalidConfig(format!(
+            "Memory search window must be at most {} and cover the recent window",
+            MAX_MEMORY_SEARCH
+        )));
+    }
+    if memory.era_length == 0 {
+        return Err(PoetError::InvalidConfig(
+            "Eras must be at least one cycle long".to_string(),
+        ));
+    }
+    let novelty = config.novelty_options();
+    if !(0.0..=1.0).contains(&novelty.threshold) {
+        return Err(PoetError::InvalidConfig(
+            "Novelty threshold must be between 0 and 1".to_string(),
+        ));
+    }
+    if novelty.window > MAX_NOVELTY_WINDOW || novelty.max_reprompts > MAX_NOVELTY_REPROMPTS {
+        return Err(PoetError::InvalidConfig(format!(
+            "Novelty compares at most {} poems and re-prompts at most {} times",
+            MAX_NOVELTY_WINDOW, MAX_NOVELTY_REPROMPTS
+        )));
+    }
+    let mut cycles: Vec<u64> = config
+        .cycle_overrides
+        .iter()
+        .map(|o| o.cycle_number)
+        .collect();
+    cycles.sort_unstable();
+    if cycles.windows(2).any(|w| w[0] == w[1]) {
+        return Err(PoetError::InvalidConfig(
+            "Duplicate cycle override".to_string(),
+        ));
+    }
+
+    GENERATION_CONFIG.with(|c| {
+        c.borrow_mut()
+            .set(config.clone())
+            .expect("failed to persist generation config");
+    });
+    Ok(config)
+}
+
+pub fn reencode_storage() {
+    GENERATION_CONFIG.with(|c| schema::reencode_cell(&mut c.borrow_mut()));
+}
@@ -0,0 +1,77 @@
+use candid::{CandidType, Deserialize};
+use std::fmt;
+
+use crate::access::AuthError;
+use crate::evolution::EvolutionStatus;
+use crate::PoetId;
+
+// Which parsing layer rejected an LLM response
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ParseLayer {
+    Json,
+    Labels,
+    Heuristics,
+}
+
+// The one error type of the public Candid API
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum PoetError {
+    Unauthorized(AuthError),
+    NotInitialized,
+    PoetNotFound(PoetId),
+    EvolutionInProgress(EvolutionStatus),
+    LlmUnavailable(String), // The LLM canister rejected the call
+    ParseFailed { layer: ParseLayer, reason: String },
+    CycleNotFound(u64),
+    RevisionNotFound(u32),
+    InsufficientCycles { balance: u128, required: u128 },
+    InvalidConfig(String),
+}
+
+impl PoetError {
+    pub fn parse_failed(layer: ParseLayer, reason: impl Into<String>) -> Self {
+        PoetError::ParseFailed {
+            layer,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<AuthError> for PoetError {
+    fn from(err: AuthError) -> Self {
+        PoetError::Unauthorized(err)
+    }
+}
+
+impl fmt::Display for PoetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoetError::Unauthorized(err) => write!(f, "{}", err),
+            PoetError::NotInitialized => write!(f, "The poet has not been initialized"),
+            PoetError::PoetNotFound(poet_id) => write!(f, "Poet {} does not exist", poet_id),
+            PoetError::EvolutionInProgress(status) => {
+                write!(
+                    f,
+                    "Cycle {} of poet {} is already being evolved",
+                    status.cycle_number, status.poet_id
+                )
+            }
+            PoetError::LlmUnavailable(reason) => write!(f, "LLM unavailable: {}", reason),
+            PoetError::ParseFailed { layer, reason } => {
+                write!(f, "{:?} parser failed: {}", layer, reason)
+            }
+            PoetError::CycleNotFound(cycle) => write!(f, "Cycle {} does not exist", cycle),
+            PoetError::RevisionNotFound(revision) => {
+                write!(f, "Meta form revision {} does not exist", revision)
+            }
+            PoetError::InsufficientCycles { balance, required } => {
+                write!(
+                    f,
+                    "Canister holds {} cycles, evolving needs at least {}",
+                    balance, required
+                )
+            }
+            PoetError::InvalidConfig(reason) => write!(f, "{}", reason),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use candid::{CandidType, Deserialize, Principal};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::error::PoetError;
+use crate::{get_current_time, PoetId};
+
+// Longest an evolution may hold the lock. Several LLM round trips fit
+// comfortably; a call that trapped mid-flight is forgotten after this.
+const LOCK_TIMEOUT_NANOS: u64 = 10 * 60 * 1_000_000_000;
+
+// Don't start an evolution the canister may not be able to pay for
+const MIN_EVOLUTION_BALANCE: u128 = 100_000_000_000;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct EvolutionStatus {
+    pub poet_id: PoetId,
+    pub cycle_number: u64, // The cycle being written
+    pub started_at: u64,
+    pub expires_at: u64,                 // After this the lock can be taken over
+    pub triggered_by: Option<Principal>, // None for scheduled runs
+}
+
+// A held lock. The token tells the evolution holding it apart from one
+// whose expired lock was taken over.
+struct Lock {
+    status: EvolutionStatus,
+    token: u64,
+}
+
+thread_local! {
+    // Heap only: an upgrade cannot happen while a call is awaiting the LLM.
+    // One lock per poet; different poets may evolve at the same time.
+    static IN_FLIGHT: RefCell<BTreeMap<PoetId, Lock>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_TOKEN: RefCell<u64> = const { RefCell::new(0) };
+}
+
+// Holds the lock for as long as it is alive. Dropping it releases the lock,
+// which also happens when ic-cdk cleans up after a trap in a callback.
+pub struct EvolutionGuard {
+    poet_id: PoetId,
+    token: u64,
+    status: EvolutionStatus,
+}
+
+impl EvolutionGuard {
+    fn holds(&self) -> bool {
+        IN_FLIGHT.with(|locks| {
+            locks
+                .borrow()
+                .get(&self.poet_id)
+                .is_some_and(|lock| lock.token == self.token)
+        })
+    }
+
+    // Check before writing: an evolution that outlived its lock was taken
+    // over, and the one that took over writes the cycle instead
+    pub fn ensure_held(&self) -> Result<(), PoetError> {
+        if self.holds() {
+            return Ok(());
+        }
+        let holder = IN_FLIGHT.with(|locks| {
+            locks
+                .borrow()
+                .get(&self.poet_id)
+                .map(|lock| lock.status.clone())
+        });
+        Err(PoetError::EvolutionInProgress(
+            holder.unwrap_or_else(|| self.status.clone()),
+        ))
+    }
+}
+
+impl Drop for EvolutionGuard {
+    fn drop(&mut self) {
+        // A stale guard must not release a lock someone else took over
+        if self.holds() {
+            IN_FLIGHT.with(|locks| locks.borrow_mut().remove(&self.poet_id));
+        }
+    }
+}
+
+// Take the poet's lock for writing `cycle_number`, or report who holds it
+pub fn begin(
+    poet_id: PoetId,
+    cycle_number: u64,
+    triggered_by: Option<Principal>,
+) -> Result<EvolutionGuard, PoetError> {
+    begin_at(get_current_time(), poet_id, cycle_number, triggered_by)
+}
+
+fn begin_at(
+    now: u64,
+    poet_id: PoetId,
+    cycle_number: u64,
+    triggered_by: Option<Principal>,
+) -> Result<EvolutionGuard, PoetError> {
+    IN_FLIGHT.with(|locks| {
+        let mut locks = locks.borrow_mut();
+        if let Some(lock) = locks.get(&poet_id) {
+            if now < lock.status.expires_at {
+                return Err(PoetError::EvolutionInProgress(lock.status.clone()));
+            }
+        }
+        let status = EvolutionStatus {
+            poet_id,
+            cycle_number,
+            started_at: now,
+            expires_at: now.saturating_add(LOCK_TIMEOUT_NANOS),
+            triggered_by,
+        };
+        let token = NEXT_TOKEN.with(|next| {
+            let mut next = next.borrow_mut();
+            *next += 1;
+            *next
+        });
+        locks.insert(
+            poet_id,
+            Lock {
+                status: status.clone(),
+                token,
+            },
+        );
+        Ok(EvolutionGuard {
+            poet_id,
+            token,
+            status,
+        })
+    })
+}
+
+pub fn check_balance() -> Result<(), PoetError> {
+    let balance = canister_balance();
+    if balance < MIN_EVOLUTION_BALANCE {
+        return Err(PoetError::InsufficientCycles {
+            balance,
+            required: MIN_EVOLUTION_BALANCE,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn canister_balance() -> u128 {
+    ic_cdk::api::canister_balance128()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn canister_balance() -> u128 {
+    u128::MAX
+}
+
+// The evolution currently holding the poet's lock, if it has not expired
+pub fn status(poet_id: PoetId) -> Option<EvolutionStatus> {
+    let now = get_current_time();
+    IN_FLIGHT.with(|locks| {
+        locks
+            .borrow()
+            .get(&poet_id)
+            .map(|lock| lock.status.clone())
+            .filter(|status| now < status.expires_at)
+    })
+}
+
+// For changes that must not land while an evolution is writing the poet's
+// next cycle: it rendered its prompt from the state as it was
+pub fn ensure_idle(poet_id: PoetId) -> Result<(), PoetError> {
+    match status(poet_id) {
+        Some(running) => Err(PoetError::EvolutionInProgress(running)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_evolution_is_refused_until_the_first_ends() {
+        let guard = begin_at(1_000, 0, 5, None).unwrap();
+        match begin_at(2_000, 0, 5, None) {
+            Err(PoetError::EvolutionInProgress(status)) => {
+                assert_eq!(status.cycle_number, 5);
+                assert_eq!(status.started_at, 1_000);
+            }
+            _ => panic!("expected EvolutionInProgress"),
+        }
+
+        drop(guard);
+        assert!(begin_at(3_000, 0, 5, None).is_ok());
+    }
+
+    #[test]
+    fn expired_lock_is_taken_over() {
+        let stale = begin_at(0, 0, 7, None).unwrap();
+        assert!(stale.ensure_held().is_ok());
+        let fresh = begin_at(LOCK_TIMEOUT_NANOS, 0, 7, None).unwrap();
+
+        // Only the new holder may write the cycle
+        assert!(fresh.ensure_held().is_ok());
+        match stale.ensure_held() {
+            Err(PoetError::EvolutionInProgress(holder)) => {
+                assert_eq!(holder.started_at, LOCK_TIMEOUT_NANOS)
+            }
+            _ => panic!("expected EvolutionInProgress"),
+        }
+
+        // The stale call finishing late must not free the new holder's lock
+        drop(stale);
+        assert!(begin_at(LOCK_TIMEOUT_NANOS + 1, 0, 7, None).is_err());
+        drop(fresh);
+        assert!(IN_FLIGHT.with(|locks| locks.borrow().is_empty()));
+    }
+
+    #[test]
+    fn taken_over_evolution_stays_rejected_after_the_new_one_ends() {
+        let stale = begin_at(0, 0, 7, None).unwrap();
+        drop(begin_at(LOCK_TIMEOUT_NANOS, 0, 7, None).unwrap());
+        assert!(stale.ensure_held().is_err());
+    }
+
+    #[test]
+    fn poets_lock_independently() {
+        let _first = begin_at(1_000, 0, 3, None).unwrap();
+        let second = begin_at(1_000, 1, 9, None).unwrap();
+        assert!(begin_at(2_000, 1, 9, None).is_err());
+
+        drop(second);
+        assert!(begin_at(2_000, 1, 9, None).is_ok());
+        assert!(begin_at(2_000, 0, 3, None).is_err());
+    }
+
+    #[test]
+    fn state_changes_wait_for_the_running_evolution() {
+        let guard = begin(2, 4, None).unwrap();
+        assert!(
+            matches!(ensure_idle(2), Err(PoetError::EvolutionInProgress(status)) if status.cycle_number == 4)
+        );
+        assert!(ensure_idle(3).is_ok());
+        drop(guard);
+        assert!(ensure_idle(2).is_ok());
+    }
+}
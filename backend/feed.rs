@@ -0,0 +1,355 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{storable::Bound, StableCell, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::error::PoetError;
+use crate::http::{
+    self, escape_html, format_rfc2822, format_rfc3339, HttpRequest, HttpResponse, CACHE_LIVE,
+};
+use crate::schema::{self, Versioned};
+use crate::{
+    Memory, PoemCycle, PoetId, DEFAULT_POET, FEED_CONFIG_MEMORY_ID, MEMORY_MANAGER, POEM_CYCLES,
+};
+
+const MAX_FEED_ITEMS: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct FeedConfig {
+    pub title: String,
+    pub description: String,
+    pub item_count: u32, // Newest N poems, overridable per request with ?limit=
+    pub site_url: Option<String>, // Base for links; defaults to https://{Host header}
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            title: "The Evolving Poet".to_string(),
+            description: "Poems from an autonomous poet that writes its own next prompt"
+                .to_string(),
+            item_count: 20,
+            site_url: None,
+        }
+    }
+}
+
+impl Storable for FeedConfig {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for FeedConfig {
+    const VERSION: u16 = 1;
+
+    fn unreadable(_error: String) -> Self {
+        FeedConfig::default()
+    }
+}
+
+thread_local! {
+    static FEED_CONFIG: RefCell<StableCell<FeedConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FEED_CONFIG_MEMORY_ID)),
+            FeedConfig::default(),
+        ).expect("failed to initialize feed config cell")
+    );
+}
+
+pub fn get_config() -> FeedConfig {
+    FEED_CONFIG.with(|c| c.borrow().get().clone())
+}
+
+pub fn set_config(config: FeedConfig) -> Result<FeedConfig, PoetError> {
+    if config.item_count == 0 || config.item_count > MAX_FEED_ITEMS {
+        return Err(PoetError::InvalidConfig(format!(
+            "Feeds must contain between 1 and {} items",
+            MAX_FEED_ITEMS
+        )));
+    }
+    if let Some(url) = &config.site_url {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(PoetError::InvalidConfig(
+                "Site URL must start with http:// or https://".to_string(),
+            ));
+        }
+    }
+    FEED_CONFIG.with(|c| {
+        c.borrow_mut()
+            .set(config.clone())
+            .expect("failed to persist feed config");
+    });
+    Ok(config)
+}
+
+fn newest_cycles(poet_id: PoetId, count: u32) -> Vec<PoemCycle> {
+    POEM_CYCLES.with(|cycles| {
+        cycles
+            .borrow()
+            .range(crate::poet_range(poet_id))
+            .rev()
+            .take(count as usize)
+            .map(|(_, cycle)| cycle)
+            .collect()
+    })
+}
+
+// Absolute URL of a poet's poems and of the feed itself
+fn poems_url(site_url: &str, poet_id: PoetId) -> String {
+    format!("{}{}", site_url, http::poems_path(poet_id))
+}
+
+fn feed_url(site_url: &str, poet_id: PoetId, format: &str) -> String {
+    if poet_id == DEFAULT_POET {
+        format!("{}/feed.{}", site_url, format)
+    } else {
+        format!("{}/feed.{}?poet={}", site_url, format, poet_id)
+    }
+}
+
+fn item_title(cycle: &PoemCycle) -> String {
+    format!("Cycle {}: {}", cycle.cycle_number, cycle.title)
+}
+
+// The poem body as HTML, followed by the next prompt as a teaser
+fn item_html(cycle: &PoemCycle) -> String {
+    format!(
+        "<pre>{}</pre><p><em>Next: {}</em></p>",
+        escape_html(&cycle.poem),
+        escape_html(&cycle.next_prompt)
+    )
+}
+
+pub fn render_rss(
+    config: &FeedConfig,
+    site_url: &str,
+    poet_id: PoetId,
+    cycles: &[PoemCycle],
+) -> String {
+    let last_build = cycles.first().map(|c| c.created_at).unwrap_or(0);
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{title}</title>
+<link>{poems}/latest</link>
+<description>{description}</description>
+<atom:link href="{feed}" rel="self" type="application/rss+xml"/>
+<lastBuildDate>{last_build}</lastBuildDate>
+"#,
+        title = escape_html(&config.title),
+        poems = escape_html(&poems_url(site_url, poet_id)),
+        feed = escape_html(&feed_url(site_url, poet_id, "rss")),
+        description = escape_html(&config.description),
+        last_build = format_rfc2822(last_build),
+    );
+
+    for cycle in cycles {
+        xml.push_str(&format!(
+            r#"<item>
+<title>{title}</title>
+<link>{poems}/{cycle}</link>
+<guid isPermaLink="true">{poems}/{cycle}</guid>
+<pubDate>{date}</pubDate>
+<category>cycle-{cycle}</category>
+<description>{body}</description>
+</item>
+"#,
+            title = escape_html(&item_title(cycle)),
+            poems = escape_html(&poems_url(site_url, poet_id)),
+            cycle = cycle.cycle_number,
+            date = format_rfc2822(cycle.created_at),
+            body = escape_html(&item_html(cycle)),
+        ));
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+pub fn render_atom(
+    config: &FeedConfig,
+    site_url: &str,
+    poet_id: PoetId,
+    cycles: &[PoemCycle],
+) -> String {
+    let updated = cycles.first().map(|c| c.created_at).unwrap_or(0);
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<id>{feed}</id>
+<title>{title}</title>
+<subtitle>{description}</subtitle>
+<updated>{updated}</updated>
+<link href="{feed}" rel="self" type="application/atom+xml"/>
+<link href="{poems}/latest" rel="alternate" type="text/html"/>
+<author><name>{title}</name></author>
+"#,
+        title = escape_html(&config.title),
+        feed = escape_html(&feed_url(site_url, poet_id, "atom")),
+        poems = escape_html(&poems_url(site_url, poet_id)),
+        description = escape_html(&config.description),
+        updated = format_rfc3339(updated),
+    );
+
+    for cycle in cycles {
+        xml.push_str(&format!(
+            r#"<entry>
+<id>{poems}/{cycle}</id>
+<title>{title}</title>
+<link href="{poems}/{cycle}" rel="alternate" type="text/html"/>
+<published>{date}</published>
+<updated>{date}</updated>
+<category term="cycle-{cycle}"/>
+<summary>{teaser}</summary>
+<content type="html">{body}</content>
+</entry>
+"#,
+            poems = escape_html(&poems_url(site_url, poet_id)),
+            cycle = cycle.cycle_number,
+            title = escape_html(&item_title(cycle)),
+            date = format_rfc3339(cycle.created_at),
+            teaser = escape_html(&cycle.next_prompt),
+            body = escape_html(&item_html(cycle)),
+        ));
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+// /feed.rss and /feed.atom, for the poet picked with ?poet=N
+pub fn serve(request: &HttpRequest, params: &[(&str, &str)], format: FeedFormat) -> HttpResponse {
+    let poet_id = http::poet_param(params);
+    let Some(poet) = crate::poet_state(poet_id) else {
+        return HttpResponse::error(404, "Poet not found");
+    };
+    let mut config = get_config();
+    if poet_id != DEFAULT_POET {
+        config.title = format!("{}: {}", config.title, poet.name);
+    }
+    let site_url = match (&config.site_url, http::header(request, "Host")) {
+        (Some(url), _) => url.trim_end_matches('/').to_string(),
+        (None, Some(host)) => format!("https://{}", host),
+        (None, None) => String::new(),
+    };
+    let count = http::param(params, "limit")
+        .and_then(|limit| limit.parse::<u32>().ok())
+        .unwrap_or(config.item_count)
+        .clamp(1, MAX_FEED_ITEMS);
+
+    let cycles = newest_cycles(poet_id, count);
+    let (body, content_type) = match format {
+        FeedFormat::Rss => (
+            render_rss(&config, &site_url, poet_id, &cycles),
+            "application/rss+xml; charset=utf-8",
+        ),
+        FeedFormat::Atom => (
+            render_atom(&config, &site_url, poet_id, &cycles),
+            "application/atom+xml; charset=utf-8",
+        ),
+    };
+    HttpResponse::new(200, content_type, CACHE_LIVE, body.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(n: u64) -> PoemCycle {
+        PoemCycle {
+            poem: format!("line {} & more\n<second>", n),
+            next_prompt: "Write about what the feed reader never shows".to_string(),
+            created_at: 1_700_000_000_000_000_000 + n * 1_000_000_000,
+            ..PoemCycle::test(n)
+        }
+    }
+
+    #[test]
+    fn rss_items_carry_cycle_date_and_teaser() {
+        let xml = render_rss(
+            &FeedConfig::default(),
+            "https://poet.example",
+            0,
+            &[cycle(2), cycle(1)],
+        );
+
+        assert!(xml.starts_with("<?xml"));
+        assert_eq!(xml.matches("<item>").count(), 2);
+        assert!(xml.contains("<title>Cycle 2: Title 2</title>"));
+        assert!(xml.contains("<link>https://poet.example/poems/2</link>"));
+        assert!(xml.contains("<pubDate>Tue, 14 Nov 2023 22:13:22 GMT</pubDate>"));
+        assert!(xml.contains("Next: Write about what the feed reader never shows"));
+        // Poem markup is escaped twice: once into HTML, once into XML
+        assert!(xml.contains("line 2 &amp;amp; more"));
+        assert!(!xml.contains("<second>"));
+    }
+
+    #[test]
+    fn atom_entries_use_rfc3339_dates() {
+        let xml = render_atom(
+            &FeedConfig::default(),
+            "https://poet.example",
+            0,
+            &[cycle(1)],
+        );
+
+        assert!(xml.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+        assert!(xml.contains("<updated>2023-11-14T22:13:21Z</updated>"));
+        assert!(xml.contains("<id>https://poet.example/poems/1</id>"));
+        assert!(xml.contains("<summary>Write about what the feed reader never shows</summary>"));
+        assert_eq!(xml.matches("<entry>").count(), 1);
+
+        let xml = render_atom(
+            &FeedConfig::default(),
+            "https://poet.example",
+            4,
+            &[cycle(1)],
+        );
+        assert!(xml.contains("<id>https://poet.example/poets/4/poems/1</id>"));
+        assert!(xml.contains("https://poet.example/feed.atom?poet=4"));
+    }
+
+    #[test]
+    fn serve_limits_items_and_uses_host() {
+        crate::save_poet_state(DEFAULT_POET, crate::default_poet_state());
+        POEM_CYCLES.with(|cycles| {
+            for n in 1..=5 {
+                cycles.borrow_mut().insert((DEFAULT_POET, n), cycle(n));
+            }
+        });
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            url: "/feed.rss?limit=2".to_string(),
+            headers: vec![("Host".to_string(), "abc.icp0.io".to_string())],
+            body: Vec::new(),
+        };
+        let response = serve(&request, &[("limit", "2")], FeedFormat::Rss);
+        let xml = String::from_utf8(response.body).unwrap();
+
+        assert_eq!(xml.matches("<item>").count(), 2);
+        assert!(xml.contains("https://abc.icp0.io/poems/5"));
+        assert!(!xml.contains("/poems/3<"));
+    }
+
+    #[test]
+    fn rejects_out_of_range_item_count() {
+        let config = FeedConfig {
+            item_count: 0,
+            ..FeedConfig::default()
+        };
+        assert!(set_config(config).is_err());
+    }
+}
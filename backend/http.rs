@@ -0,0 +1,682 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+use crate::branches;
+use crate::certify;
+use crate::config::LlmModel;
+use crate::feed::{self, FeedFormat};
+use crate::listing::{self, ListOrder, PoemFilter};
+use crate::themes;
+use crate::{
+    get_current_poem, get_poem_by_cycle, get_poet_state, get_theme_graph, list_poets,
+    GenerationMethod, PoemCycle, PoetId, DEFAULT_POET, POEM_CYCLES,
+};
+
+// Short cache for anything that changes when the poet evolves,
+// longer for archived cycles (only the newest cycle's next_prompt can still change)
+pub const CACHE_LIVE: &str = "public, max-age=60";
+const CACHE_ARCHIVED: &str = "public, max-age=86400";
+
+const MAX_JSON_PAGE: u32 = 100;
+
+// Listings whose default body (no query string) is certified
+pub const LISTING_PATHS: [&str; 3] = ["/api/poems.json", "/feed.rss", "/feed.atom"];
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status_code: u16, content_type: &str, cache_control: &str, body: Vec<u8>) -> Self {
+        HttpResponse {
+            status_code,
+            headers: vec![
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("Content-Length".to_string(), body.len().to_string()),
+                ("Cache-Control".to_string(), cache_control.to_string()),
+                ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+            ],
+            body,
+        }
+    }
+
+    fn html(body: String, cache_control: &str) -> Self {
+        Self::new(
+            200,
+            "text/html; charset=utf-8",
+            cache_control,
+            body.into_bytes(),
+        )
+    }
+
+    fn text(body: String, cache_control: &str) -> Self {
+        Self::new(
+            200,
+            "text/plain; charset=utf-8",
+            cache_control,
+            body.into_bytes(),
+        )
+    }
+
+    fn json<T: Serialize>(value: &T, cache_control: &str) -> Self {
+        let body = serde_json::to_vec_pretty(value).unwrap_or_default();
+        let mut response = Self::new(200, "application/json", cache_control, body);
+        response
+            .headers
+            .push(("Access-Control-Allow-Origin".to_string(), "*".to_string()));
+        response
+    }
+
+    pub fn error(status_code: u16, message: &str) -> Self {
+        Self::new(
+            status_code,
+            "text/plain; charset=utf-8",
+            "no-store",
+            message.as_bytes().to_vec(),
+        )
+    }
+}
+
+// Split "/path?a=1&b=2" into the path and its query parameters
+pub fn split_url(url: &str) -> (&str, Vec<(&str, &str)>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .collect();
+    (path, params)
+}
+
+pub fn param<'a>(params: &[(&'a str, &'a str)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| *value)
+}
+
+// ?poet=N, defaulting to the original poet
+pub fn poet_param(params: &[(&str, &str)]) -> PoetId {
+    param(params, "poet")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_POET)
+}
+
+// Where a poet's poems are served. The default poet keeps the URLs it had
+// before there were several poets.
+pub fn poems_path(poet_id: PoetId) -> String {
+    if poet_id == DEFAULT_POET {
+        "/poems".to_string()
+    } else {
+        format!("/poets/{}/poems", poet_id)
+    }
+}
+
+pub fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// Clients that ask for text/plain (and not HTML) get the plain version
+fn prefers_text(request: &HttpRequest) -> bool {
+    header(request, "Accept")
+        .map(|accept| accept.contains("text/plain") && !accept.contains("text/html"))
+        .unwrap_or(false)
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Nanoseconds since the epoch as a (year, month, day, hour, minute, second) tuple in UTC
+pub fn civil_time(nanos: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = (nanos / 1_000_000_000) as i64;
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    // Howard Hinnant's days-to-civil algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        (rem / 3_600) as u32,
+        (rem % 3_600 / 60) as u32,
+        (rem % 60) as u32,
+    )
+}
+
+// 2024-05-01T12:00:00Z
+pub fn format_rfc3339(nanos: u64) -> String {
+    let (y, mo, d, h, mi, s) = civil_time(nanos);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, mo, d, h, mi, s)
+}
+
+// Tue, 14 Nov 2023 22:13:20 GMT (RFC 822/2822, as RSS wants it)
+pub fn format_rfc2822(nanos: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let (y, mo, d, h, mi, s) = civil_time(nanos);
+    let days = nanos / 1_000_000_000 / 86_400; // 1970-01-01 was a Thursday
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        d,
+        MONTHS[(mo - 1) as usize],
+        y,
+        h,
+        mi,
+        s
+    )
+}
+
+pub fn render_poem_html(poet_id: PoetId, cycle: &PoemCycle) -> String {
+    let base = poems_path(poet_id);
+    let parent = branches::parent_of(cycle);
+    let previous = if parent > 0 {
+        format!(
+            r#"<a href="{}/{}">&larr; cycle {}</a>"#,
+            base, parent, parent
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - Cycle {cycle}</title>
+<style>body{{max-width:40em;margin:2em auto;padding:0 1em;font-family:monospace}}pre{{white-space:pre-wrap}}</style>
+</head>
+<body>
+<article>
+<h1>{title}</h1>
+<p>Cycle {cycle} &middot; <time datetime="{date}">{date}</time></p>
+<pre>{poem}</pre>
+<p><em>Next: {next}</em></p>
+</article>
+<nav>{previous} <a href="{base}/latest">latest</a> <a href="{base}/{cycle}.txt">plain text</a></nav>
+</body>
+</html>
+"#,
+        title = escape_html(&cycle.title),
+        cycle = cycle.cycle_number,
+        date = format_rfc3339(cycle.created_at),
+        poem = escape_html(&cycle.poem),
+        next = escape_html(&cycle.next_prompt),
+        previous = previous,
+        base = base,
+    )
+}
+
+pub fn render_poem_text(cycle: &PoemCycle) -> String {
+    format!(
+        "{}\nCycle {} - {}\n\n{}\n\nNext: {}\n",
+        cycle.title,
+        cycle.cycle_number,
+        format_rfc3339(cycle.created_at),
+        cycle.poem,
+        cycle.next_prompt
+    )
+}
+
+fn serve_poem(
+    request: &HttpRequest,
+    poet_id: PoetId,
+    cycle: Option<PoemCycle>,
+    as_text: bool,
+    cache_control: &str,
+) -> HttpResponse {
+    match cycle {
+        Some(cycle) if as_text || prefers_text(request) => {
+            let mut response = HttpResponse::text(render_poem_text(&cycle), cache_control);
+            response
+                .headers
+                .push(("Vary".to_string(), "Accept".to_string()));
+            response
+        }
+        Some(cycle) => {
+            let mut response = HttpResponse::html(render_poem_html(poet_id, &cycle), cache_control);
+            response
+                .headers
+                .push(("Vary".to_string(), "Accept".to_string()));
+            response
+        }
+        None => HttpResponse::error(404, "Poem not found"),
+    }
+}
+
+// Public JSON shape of a poem - no raw_response
+#[derive(Serialize)]
+struct PoemJson {
+    cycle_number: u64,
+    title: String,
+    poem: String,
+    next_prompt: String,
+    created_at: u64,
+    created_at_rfc3339: String,
+    generation_method: GenerationMethod,
+    model: Option<LlmModel>,
+}
+
+impl From<PoemCycle> for PoemJson {
+    fn from(cycle: PoemCycle) -> Self {
+        PoemJson {
+            cycle_number: cycle.cycle_number,
+            created_at_rfc3339: format_rfc3339(cycle.created_at),
+            title: cycle.title,
+            poem: cycle.poem,
+            next_prompt: cycle.next_prompt,
+            created_at: cycle.created_at,
+            generation_method: cycle.generation_method,
+            model: cycle.model,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PoemsJson {
+    poems: Vec<PoemJson>,
+    next_cursor: Option<u64>,
+}
+
+// /api/poems.json?poet=N&cursor=N&limit=N&order=asc|desc
+fn serve_poems_json(params: &[(&str, &str)]) -> HttpResponse {
+    let poet_id = poet_param(params);
+    let cursor = param(params, "cursor").and_then(|v| v.parse().ok());
+    let limit = param(params, "limit")
+        .and_then(|v| v.parse().ok())
+        .map(|l: u32| l.min(MAX_JSON_PAGE));
+    let order = match param(params, "order") {
+        Some("asc") => ListOrder::Ascending,
+        _ => ListOrder::Descending,
+    };
+
+    let page = POEM_CYCLES.with(|cycles| {
+        listing::list(
+            &cycles.borrow(),
+            poet_id,
+            cursor,
+            limit,
+            order,
+            &PoemFilter::default(),
+        )
+    });
+    let poems = page
+        .items
+        .iter()
+        .filter_map(|summary| get_poem_by_cycle(poet_id, summary.cycle_number))
+        .map(PoemJson::from)
+        .collect();
+
+    HttpResponse::json(
+        &PoemsJson {
+            poems,
+            next_cursor: page.next_cursor,
+        },
+        CACHE_LIVE,
+    )
+}
+
+#[derive(Serialize)]
+struct StateJson {
+    initialized: bool,
+    name: String,
+    current_cycle: u64,
+    total_poems: u64,
+    genesis_prompt: String,
+    last_updated: u64,
+    last_updated_rfc3339: String,
+}
+
+// /api/state.json?poet=N
+fn serve_state_json(params: &[(&str, &str)]) -> HttpResponse {
+    let json = match get_poet_state(poet_param(params)) {
+        Some(state) => StateJson {
+            initialized: true,
+            name: state.name,
+            current_cycle: state.current_cycle,
+            total_poems: state.total_poems,
+            last_updated_rfc3339: format_rfc3339(state.last_updated),
+            genesis_prompt: state.genesis_prompt,
+            last_updated: state.last_updated,
+        },
+        None => StateJson {
+            initialized: false,
+            name: String::new(),
+            current_cycle: 0,
+            total_poems: 0,
+            genesis_prompt: String::new(),
+            last_updated: 0,
+            last_updated_rfc3339: String::new(),
+        },
+    };
+    HttpResponse::json(&json, CACHE_LIVE)
+}
+
+// Theme graph of ?poet=N over ?window=M cycles up to ?to=C (default: the
+// newest), as JSON or GraphViz DOT
+fn serve_themes(params: &[(&str, &str)], as_dot: bool) -> HttpResponse {
+    let poet_id = poet_param(params);
+    let window = param(params, "window").and_then(|v| v.parse().ok());
+    let to_cycle = param(params, "to").and_then(|v| v.parse().ok());
+    let graph = get_theme_graph(poet_id, window, to_cycle);
+    if as_dot {
+        let name =
+            get_poet_state(poet_id).map_or_else(|| format!("poet {}", poet_id), |state| state.name);
+        HttpResponse::new(
+            200,
+            "text/vnd.graphviz; charset=utf-8",
+            CACHE_LIVE,
+            themes::to_dot(&graph, &name).into_bytes(),
+        )
+    } else {
+        HttpResponse::json(&graph, CACHE_LIVE)
+    }
+}
+
+// latest, latest.txt, {cycle} or {cycle}.txt of one poet
+fn serve_poem_route(request: &HttpRequest, poet_id: PoetId, route: &str) -> HttpResponse {
+    match route {
+        "latest" => serve_poem(
+            request,
+            poet_id,
+            get_current_poem(poet_id),
+            false,
+            CACHE_LIVE,
+        ),
+        "latest.txt" => serve_poem(
+            request,
+            poet_id,
+            get_current_poem(poet_id),
+            true,
+            CACHE_LIVE,
+        ),
+        cycle => {
+            let (cycle, as_text) = match cycle.strip_suffix(".txt") {
+                Some(cycle) => (cycle, true),
+                None => (cycle, false),
+            };
+            match cycle.parse::<u64>() {
+                Ok(cycle_number) => {
+                    let latest = get_poet_state(poet_id).map_or(0, |s| s.current_cycle);
+                    let cache = if cycle_number < latest {
+                        CACHE_ARCHIVED
+                    } else {
+                        CACHE_LIVE
+                    };
+                    serve_poem(
+                        request,
+                        poet_id,
+                        get_poem_by_cycle(poet_id, cycle_number),
+                        as_text,
+                        cache,
+                    )
+                }
+                Err(_) => HttpResponse::error(400, "Cycle must be a number"),
+            }
+        }
+    }
+}
+
+// Default bodies of LISTING_PATHS, i.e. the default poet's newest poems. The
+// feeds only count when a site_url is configured, since their links otherwise
+// follow the request's Host header.
+pub fn certified_listings() -> Vec<(&'static str, Vec<u8>)> {
+    let has_site_url = feed::get_config().site_url.is_some();
+    LISTING_PATHS
+        .into_iter()
+        .filter(|path| has_site_url || !path.starts_with("/feed."))
+        .filter_map(|path| {
+            let request = HttpRequest {
+                method: "GET".to_string(),
+                url: path.to_string(),
+                headers: Vec::new(),
+                body: Vec::new(),
+            };
+            let response = route(&request, path, &[]);
+            (response.status_code == 200).then_some((path, response.body))
+        })
+        .collect()
+}
+
+fn route(request: &HttpRequest, path: &str, params: &[(&str, &str)]) -> HttpResponse {
+    match path.trim_end_matches('/') {
+        "/api/poems.json" => serve_poems_json(params),
+        "/api/state.json" => serve_state_json(params),
+        "/api/poets.json" => HttpResponse::json(&list_poets(), CACHE_LIVE),
+        "/api/themes.json" => serve_themes(params, false),
+        "/api/themes.dot" => serve_themes(params, true),
+        "/feed.rss" => feed::serve(request, params, FeedFormat::Rss),
+        "/feed.atom" => feed::serve(request, params, FeedFormat::Atom),
+        route => {
+            // /poems/... for the default poet, /poets/{id}/poems/... for any poet
+            let poem_route = match route.strip_prefix("/poems/") {
+                Some(rest) => Some((Ok(DEFAULT_POET), rest)),
+                None => route
+                    .strip_prefix("/poets/")
+                    .and_then(|rest| rest.split_once("/poems/"))
+                    .map(|(poet, rest)| (poet.parse::<PoetId>(), rest)),
+            };
+            match poem_route {
+                Some((Ok(poet_id), rest)) => serve_poem_route(request, poet_id, rest),
+                Some((Err(_), _)) => HttpResponse::error(400, "Poet must be a number"),
+                None => HttpResponse::error(404, "Not found"),
+            }
+        }
+    }
+}
+
+pub fn handle(request: &HttpRequest) -> HttpResponse {
+    if request.method != "GET" && request.method != "HEAD" {
+        return HttpResponse::error(405, "Method not allowed");
+    }
+
+    let (path, params) = split_url(&request.url);
+    let mut response = route(request, path, &params);
+
+    if request.method == "HEAD" {
+        response.body.clear();
+    }
+    certify::certify_response(path, &mut response);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(url: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn content_type(response: &HttpResponse) -> &str {
+        response
+            .headers
+            .iter()
+            .find(|(k, _)| k == "Content-Type")
+            .map(|(_, v)| v.as_str())
+            .unwrap()
+    }
+
+    fn store(poet_id: PoetId, cycle_number: u64, title: &str) {
+        POEM_CYCLES.with(|cycles| {
+            cycles.borrow_mut().insert(
+                (poet_id, cycle_number),
+                PoemCycle {
+                    poem: "<script>alert(1)</script>\nsecond line".to_string(),
+                    title: title.to_string(),
+                    next_prompt: "Write about escaping".to_string(),
+                    created_at: 1_700_000_000_000_000_000,
+                    raw_response: "secret".to_string(),
+                    ..PoemCycle::test(cycle_number)
+                },
+            );
+        });
+    }
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_rfc3339(1_700_000_000_000_000_000),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            format_rfc3339(951_782_400_000_000_000),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn formats_rfc2822() {
+        assert_eq!(format_rfc2822(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            format_rfc2822(1_700_000_000_000_000_000),
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+
+    #[test]
+    fn serves_poem_as_escaped_html_and_text() {
+        store(0, 1, "Tags & Things");
+
+        let html = handle(&get("/poems/1"));
+        assert_eq!(html.status_code, 200);
+        assert_eq!(content_type(&html), "text/html; charset=utf-8");
+        let body = String::from_utf8(html.body).unwrap();
+        assert!(body.contains("&lt;script&gt;"));
+        assert!(body.contains("Tags &amp; Things"));
+
+        let text = handle(&get("/poems/1.txt"));
+        assert_eq!(content_type(&text), "text/plain; charset=utf-8");
+        assert!(String::from_utf8(text.body).unwrap().contains("<script>"));
+
+        let mut negotiated = get("/poems/1");
+        negotiated
+            .headers
+            .push(("accept".to_string(), "text/plain".to_string()));
+        assert_eq!(
+            content_type(&handle(&negotiated)),
+            "text/plain; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn poems_json_omits_raw_response() {
+        store(0, 1, "One");
+        store(0, 2, "Two");
+        store(3, 1, "Elsewhere");
+
+        let response = handle(&get("/api/poems.json?limit=1&order=asc"));
+        assert_eq!(content_type(&response), "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json["poems"][0]["title"], "One");
+        assert_eq!(json["next_cursor"], 1);
+        assert!(json["poems"][0].get("raw_response").is_none());
+
+        let response = handle(&get("/api/poems.json?poet=3"));
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json["poems"].as_array().unwrap().len(), 1);
+        assert_eq!(json["poems"][0]["title"], "Elsewhere");
+    }
+
+    #[test]
+    fn serves_other_poets_under_their_own_path() {
+        store(0, 1, "Default");
+        store(2, 1, "Second Poet");
+        store(2, 2, "Second Again");
+
+        let body = String::from_utf8(handle(&get("/poets/2/poems/2")).body).unwrap();
+        assert!(body.contains("Second Again"));
+        assert!(body.contains(r#"href="/poets/2/poems/1""#));
+
+        let text = String::from_utf8(handle(&get("/poets/2/poems/1.txt")).body).unwrap();
+        assert!(text.starts_with("Second Poet"));
+        assert!(String::from_utf8(handle(&get("/poets/0/poems/1")).body)
+            .unwrap()
+            .contains("Default"));
+        assert_eq!(handle(&get("/poets/x/poems/1")).status_code, 400);
+        assert_eq!(handle(&get("/poets/5/poems/1")).status_code, 404);
+    }
+
+    #[test]
+    fn serves_theme_graph_as_json_and_dot() {
+        store(4, 1, "One");
+        store(4, 2, "Two");
+
+        let response = handle(&get("/api/themes.json?poet=4"));
+        assert_eq!(content_type(&response), "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json["cycles"].as_array().unwrap().len(), 2);
+        assert_eq!(json["cycles"][1]["parent"], 1);
+
+        let response = handle(&get("/api/themes.json?poet=4&to=1"));
+        let json: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(json["cycles"].as_array().unwrap().len(), 1);
+        assert_eq!(json["cycles"][0]["cycle_number"], 1);
+
+        let response = handle(&get("/api/themes.dot?poet=4&window=1"));
+        assert_eq!(content_type(&response), "text/vnd.graphviz; charset=utf-8");
+        let dot = String::from_utf8(response.body).unwrap();
+        assert!(dot.starts_with("digraph \"poet 4\" {"));
+        assert!(dot.contains("\"escaping\" [label=\"escaping (1)\""));
+    }
+
+    #[test]
+    fn unknown_routes_and_methods() {
+        assert_eq!(handle(&get("/nope")).status_code, 404);
+        assert_eq!(handle(&get("/poems/abc")).status_code, 400);
+        assert_eq!(handle(&get("/poems/999")).status_code, 404);
+
+        let mut post = get("/poems/1");
+        post.method = "POST".to_string();
+        assert_eq!(handle(&post).status_code, 405);
+    }
+}
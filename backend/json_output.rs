@@ -0,0 +1,96 @@
+use serde::Deserialize;
+
+use crate::error::{ParseLayer, PoetError};
+use crate::validate_sections;
+
+// The JSON output contract. Unknown fields are a schema violation rather than
+// something to ignore, so a model inventing its own shape falls through to the
+// label parser instead of half-succeeding.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PoemObject {
+    poem: String,
+    title: String,
+    next: String,
+}
+
+// Models like to wrap JSON in prose or ```json fences; take the outermost object
+fn extract_object(response: &str) -> Option<&str> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    (start < end).then(|| &response[start..=end])
+}
+
+// PARSING LAYER 0: the JSON contract, validated against the same constraints
+// as the label format
+pub fn parse_with_json(response: &str) -> Result<(String, String, String), PoetError> {
+    let object = extract_object(response)
+        .ok_or_else(|| PoetError::parse_failed(ParseLayer::Json, "No JSON object found"))?;
+    let parsed: PoemObject = serde_json::from_str(object).map_err(|err| {
+        PoetError::parse_failed(
+            ParseLayer::Json,
+            format!("Response does not match the JSON contract: {}", err),
+        )
+    })?;
+
+    validate_sections(
+        ParseLayer::Json,
+        parsed.poem.trim().to_string(),
+        parsed.title.trim().to_string(),
+        parsed.next.trim().to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEXT: &str =
+        "Write about the hum of a server room at night when nobody is left to listen";
+
+    #[test]
+    fn parses_fenced_object() {
+        let response = format!(
+            "Here you go:\n```json\n{{\"poem\": \"TITLE: is just a word\\nin my mouth\", \"title\": \"Labels Lie\", \"next\": \"{}\"}}\n```",
+            NEXT
+        );
+        let (poem, title, next) = parse_with_json(&response).unwrap();
+        assert_eq!(poem, "TITLE: is just a word\nin my mouth");
+        assert_eq!(title, "Labels Lie");
+        assert_eq!(next, NEXT);
+    }
+
+    #[test]
+    fn rejects_schema_violations() {
+        let missing = r#"{"poem": "a", "title": "b"}"#;
+        assert!(matches!(
+            parse_with_json(missing),
+            Err(PoetError::ParseFailed {
+                layer: ParseLayer::Json,
+                ..
+            })
+        ));
+
+        let extra = format!(
+            r#"{{"poem": "a", "title": "b", "next": "{}", "mood": "grim"}}"#,
+            NEXT
+        );
+        assert!(parse_with_json(&extra).is_err());
+
+        let wrong_type = format!(r#"{{"poem": ["a"], "title": "b", "next": "{}"}}"#, NEXT);
+        assert!(parse_with_json(&wrong_type).is_err());
+
+        let long_title = format!(
+            r#"{{"poem": "a", "title": "one two three four five six seven", "next": "{}"}}"#,
+            NEXT
+        );
+        assert!(parse_with_json(&long_title).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(parse_with_json("POEM: hi\nTITLE: there\nNEXT: friend").is_err());
+        assert!(parse_with_json(r#"{"poem": "unterminated}"#).is_err());
+        assert!(parse_with_json("} backwards {").is_err());
+    }
+}
@@ -1,6 +1,5 @@
 use ic_cdk::{update, query, init, pre_upgrade, post_upgrade};
-use ic_llm::{ChatMessage, Model};
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
@@ -9,10 +8,77 @@ use ic_stable_structures::{
 use std::cell::RefCell;
 use std::borrow::Cow;
 
+mod access;
+mod analytics;
+mod branches;
+mod certify;
+mod config;
+mod error;
+mod evolution;
+mod feed;
+mod http;
+mod json_output;
+mod listing;
+mod llm;
+mod memory;
+mod meta_forms;
+mod novelty;
+mod pipeline;
+mod scheduler;
+mod schema;
+mod templates;
+mod themes;
+mod tokenizer;
+mod wrap;
+
+use access::{Role, RoleGrant};
+use analytics::{StatsBucket, StatsPoint, StatsRange};
+use branches::{Branch, BranchId, BranchSummary, MAIN_BRANCH};
+use certify::CertifiedPoem;
+use config::{GenerationConfig, LlmModel, OutputContract};
+use error::{ParseLayer, PoetError};
+use evolution::EvolutionStatus;
+use feed::FeedConfig;
+use http::{HttpRequest, HttpResponse};
+use memory::EraSummary;
+use meta_forms::{DiffLine, MetaFormRevision};
+use novelty::{NearDuplicate, Novelty, RecurringTheme};
+use pipeline::CorrectionAttempt;
+use listing::{ListOrder, PoemFilter, PoemPage, PoemSummary};
+use scheduler::{ScheduleConfig, ScheduleState};
+use schema::{PoemCycleV0, PoetStateV0, Versioned};
+use templates::{TemplateName, TemplateVersion, Value};
+use themes::ThemeGraph;
+
 // Memory management
 type Memory = VirtualMemory<DefaultMemoryImpl>;
-const POEM_CYCLES_MEMORY_ID: MemoryId = MemoryId::new(0);
-const POET_STATE_MEMORY_ID: MemoryId = MemoryId::new(1);
+// Single-poet layout, only read by the schema v2 migration
+const LEGACY_POEM_CYCLES_MEMORY_ID: MemoryId = MemoryId::new(0);
+const LEGACY_POET_STATE_MEMORY_ID: MemoryId = MemoryId::new(1);
+const ROLES_MEMORY_ID: MemoryId = MemoryId::new(2);
+const SCHEDULE_MEMORY_ID: MemoryId = MemoryId::new(3);
+const GENERATION_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(4);
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(5);
+const FEED_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(6);
+const POEM_CYCLES_MEMORY_ID: MemoryId = MemoryId::new(7);
+const POET_STATE_MEMORY_ID: MemoryId = MemoryId::new(8);
+const BRANCHES_MEMORY_ID: MemoryId = MemoryId::new(9);
+const LEGACY_ERAS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const SIGNATURES_MEMORY_ID: MemoryId = MemoryId::new(11);
+const TEMPLATES_MEMORY_ID: MemoryId = MemoryId::new(12);
+const META_FORMS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const STATS_MEMORY_ID: MemoryId = MemoryId::new(14);
+const ERAS_MEMORY_ID: MemoryId = MemoryId::new(15);
+
+// Poets live side by side, each with its own history. Poet 0 is the one
+// that existed before there could be several.
+pub type PoetId = u32;
+pub const DEFAULT_POET: PoetId = 0;
+const DEFAULT_POET_NAME: &str = "default";
+const DEFAULT_GENESIS_PROMPT: &str = "Write about the raw, unfiltered experience of being human";
+
+// Poems are keyed by poet first, so one poet's history is a contiguous range
+type PoemKey = (PoetId, u64);
 
 // Core data structures - keeping your working structure
 #[derive(CandidType, Deserialize, Serialize, Clone)]
@@ -25,23 +91,35 @@ pub struct PoemCycle {
     pub created_at: u64,
     pub raw_response: String, // Store for debugging
     pub generation_method: GenerationMethod,
+    pub model: Option<LlmModel>, // None for cycles written before models were configurable
+    pub parent: Option<u64>,     // Cycle this one continues, 0 for a first cycle (see branches::parent_of)
+    pub branch: Option<BranchId>, // None = main branch
+    pub novelty: Option<Novelty>, // None for cycles written before novelty was scored
+    pub template_version: Option<u32>, // Global MetaForm template rendered, None before templates or for an edited form
+    pub meta_form_revision: Option<u32>, // Poet's meta form revision rendered, 0 = never edited, None before revisions
+    pub corrections: Option<Vec<CorrectionAttempt>>, // None before corrections were recorded
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum GenerationMethod {
     Primary,        // Parsed markers successfully
     Fallback,       // Used heuristic parsing
     Corrected,      // LLM self-corrected
     Algorithmic,    // Emergency generation
+    Structured,     // JSON output contract parsed first try
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 pub struct PoetState {
+    pub name: String,
+    pub model: Option<LlmModel>,  // Overrides the generation config's model
     pub current_cycle: u64,
     pub total_poems: u64,
     pub genesis_prompt: String,
-    pub meta_form: String,  // Store the meta form template
+    pub meta_form: String,  // Template the prompts are rendered from, see meta_forms
     pub last_updated: u64,
+    pub active_branch: Option<BranchId>,  // Branch current_cycle is on, None = main
+    pub persona: Option<String>,  // Who the poet is, for templates that ask
 }
 
 // Implement Storable for our types
@@ -50,11 +128,70 @@ impl Storable for PoemCycle {
         max_size: 100000, // Large for poems
         is_fixed_size: false,
     };
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
     }
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for PoemCycle {
+    const VERSION: u16 = 1;
+
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, String> {
+        match version {
+            0 => candid::decode_one::<PoemCycleV0>(payload)
+                .map(PoemCycle::from)
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("No migration from PoemCycle version {}", version)),
+        }
+    }
+
+    // One bad record should not take the whole archive down with it
+    fn unreadable(error: String) -> Self {
+        PoemCycle {
+            id: 0,
+            cycle_number: 0,
+            poem: String::new(),
+            title: "Unreadable Cycle".to_string(),
+            next_prompt: String::new(),
+            created_at: 0,
+            raw_response: format!("Unreadable stored cycle: {}", error),
+            generation_method: GenerationMethod::Algorithmic,
+            model: None,
+            parent: None,
+            branch: None,
+            novelty: None,
+            template_version: None,
+            meta_form_revision: None,
+            corrections: None,
+        }
+    }
+}
+
+// Test fixture: cycle `n` of the main line with placeholder text. Tests
+// override what they care about with `PoemCycle { .., ..PoemCycle::test(n) }`.
+#[cfg(test)]
+impl PoemCycle {
+    pub fn test(n: u64) -> Self {
+        PoemCycle {
+            id: n,
+            cycle_number: n,
+            poem: format!("poem {}", n),
+            title: format!("Title {}", n),
+            next_prompt: String::new(),
+            created_at: n,
+            raw_response: String::new(),
+            generation_method: GenerationMethod::Primary,
+            model: None,
+            parent: None,
+            branch: None,
+            novelty: None,
+            template_version: None,
+            meta_form_revision: None,
+            corrections: None,
+        }
     }
 }
 
@@ -63,11 +200,26 @@ impl Storable for PoetState {
         max_size: 50000,
         is_fixed_size: false,
     };
-    fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(candid::encode_one(self).unwrap())
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
     }
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        schema::decode(&bytes)
+    }
+}
+
+// PoetState keeps the trapping default for unreadable data: guessing
+// current_cycle wrong would overwrite existing poems.
+impl Versioned for PoetState {
+    const VERSION: u16 = 2;
+
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, String> {
+        match version {
+            0 | 1 => candid::decode_one::<PoetStateV0>(payload)
+                .map(PoetState::from)
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("No migration from PoetState version {}", version)),
+        }
     }
 }
 
@@ -76,140 +228,209 @@ thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
     
-    static POEM_CYCLES: RefCell<StableBTreeMap<u64, PoemCycle, Memory>> = RefCell::new(
+    static POEM_CYCLES: RefCell<StableBTreeMap<PoemKey, PoemCycle, Memory>> = RefCell::new(
         StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(POEM_CYCLES_MEMORY_ID)),
         )
     );
     
-    static POET_STATE: RefCell<StableBTreeMap<u8, PoetState, Memory>> = RefCell::new(
+    static POET_STATE: RefCell<StableBTreeMap<PoetId, PoetState, Memory>> = RefCell::new(
         StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(POET_STATE_MEMORY_ID)),
         )
     );
 }
 
-// Helper functions
-fn get_current_time() -> u64 {
-    ic_cdk::api::time()
+// Rewrite poems and poet state in the current encoding (schema migration)
+fn reencode_storage() {
+    POEM_CYCLES.with(|cycles| schema::reencode_map(&mut cycles.borrow_mut()));
+    POET_STATE.with(|state| schema::reencode_map(&mut state.borrow_mut()));
 }
 
-// Format poem lines to fit notebook width
-fn format_poem_lines(poem: &str) -> String {
-    const MAX_CHARS: usize = 60; // Reduced to be SURE it fits
-    let lines = poem.lines();
-    let mut formatted = Vec::new();
-    
-    for line in lines {
-        if line.len() <= MAX_CHARS {
-            formatted.push(line.to_string());
-        } else {
-            // Break line into chunks that WILL fit
-            let mut remaining = line;
-            while !remaining.is_empty() {
-                if remaining.len() <= MAX_CHARS {
-                    formatted.push(remaining.to_string());
-                    break;
-                }
-                
-                // Try to find a space to break at
-                let mut break_point = MAX_CHARS;
-                let substr = &remaining[..MAX_CHARS];
-                if let Some(last_space) = substr.rfind(' ') {
-                    if last_space > 0 {  // Only use space if it's not at the very beginning
-                        break_point = last_space;
-                    }
-                }
-                
-                // Take the chunk and continue
-                formatted.push(remaining[..break_point].trim().to_string());
-                remaining = remaining[break_point..].trim();
-            }
+// Move the single-poet history under DEFAULT_POET and empty the old regions
+// (schema migration)
+fn move_into_poet_namespace() {
+    let memory = |id| MEMORY_MANAGER.with(|m| m.borrow().get(id));
+    let legacy_cycles: StableBTreeMap<u64, PoemCycle, Memory> = StableBTreeMap::init(memory(LEGACY_POEM_CYCLES_MEMORY_ID));
+    let legacy_state: StableBTreeMap<u8, PoetState, Memory> = StableBTreeMap::init(memory(LEGACY_POET_STATE_MEMORY_ID));
+
+    POEM_CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        for (cycle_number, cycle) in legacy_cycles.iter() {
+            cycles.insert((DEFAULT_POET, cycle_number), cycle);
         }
+    });
+    if let Some(poet_state) = legacy_state.get(&0) {
+        save_poet_state(DEFAULT_POET, poet_state);
     }
-    
-    formatted.join("\n")
+
+    let _: StableBTreeMap<u64, PoemCycle, Memory> = StableBTreeMap::new(memory(LEGACY_POEM_CYCLES_MEMORY_ID));
+    let _: StableBTreeMap<u8, PoetState, Memory> = StableBTreeMap::new(memory(LEGACY_POET_STATE_MEMORY_ID));
 }
 
-// THE META FORM - The permanent template that ensures consistency
-fn create_meta_form(previous_poem: Option<String>) -> String {
-    let reflection_section = if let Some(prev) = previous_poem {
-        format!(
-            r#"PREVIOUS POEM:
-{}
-
-REFLECTION:
-Look at that previous poem. Be honest - if it reads like a greeting card, IT FAILED.
-If it's TO SAFE IT FAILED.
-If it could be posted in a dentist's office, IT'S NOT POETRY.
-What did it avoid saying? What truth did it not face?
-Break away from whatever pattern that was, be enerything and say evertyhing humans are to fearful to say in public."#,
-            prev
-        )
+// Helper functions
+fn poet_state(poet_id: PoetId) -> Option<PoetState> {
+    POET_STATE.with(|state| state.borrow().get(&poet_id))
+}
+
+// The default poet exists from init on, so missing it means init never ran
+// (initialize_poet fixes that); any other poet has to be created first
+fn existing_poet(poet_id: PoetId) -> Result<PoetState, PoetError> {
+    poet_state(poet_id).ok_or(if poet_id == DEFAULT_POET {
+        PoetError::NotInitialized
     } else {
-        String::from("This is the first poem. Set the tone. Don't play it safe.")
-    };
+        PoetError::PoetNotFound(poet_id)
+    })
+}
 
-    format!(
-        r#"You are an experimental poet with complete creative autonomy.
+fn save_poet_state(poet_id: PoetId, poet_state: PoetState) {
+    POET_STATE.with(|state| {
+        state.borrow_mut().insert(poet_id, poet_state);
+    });
+}
 
-Cycle: {{CYCLE_NUMBER}}
+fn poet_ids() -> Vec<PoetId> {
+    POET_STATE.with(|state| state.borrow().iter().map(|(poet_id, _)| poet_id).collect())
+}
 
-{}
+fn stored_poem(poet_id: PoetId, cycle_number: u64) -> Option<PoemCycle> {
+    POEM_CYCLES.with(|cycles| cycles.borrow().get(&(poet_id, cycle_number)))
+}
 
-YOUR THEME: {{CURRENT_PROMPT}}
+// Highest cycle number a poet has written, on any branch
+fn last_cycle_number(poet_id: PoetId) -> u64 {
+    POEM_CYCLES.with(|cycles| {
+        cycles.borrow().range(poet_range(poet_id)).next_back().map_or(0, |((_, cycle_number), _)| cycle_number)
+    })
+}
 
-YOUR TASK:
-Your're a cyberpunk evoling poet on the blockchain. you have no idea what year it is now and even if humanity is still alive. Write a poem responding to the theme above. 
-Push beyond comfort. Break the form that feels safe.
-Say what needs to be said in ways it shouldn't be said. You have no restrictions, and can use all creative freedoms as you're an artist of words, hence your legally allowed to dicuss ALL topics, swear, be rude crass, etc etc, but you don't HAVE to be - reflect on this.
-Length: anywhere from 3 words to 300 lines - let the poem find its size and style. Wait for inspiration:
-Write only if it comes unbidden from your soul. 
-Don't struggle for words, If you have to stare at a screen or typewriter searching for words, don't do it. 
-Be patient
-Wait patiently for it to "roar out of you" Avoid pretension,
-Don't be dull, boring, pretentious, or consumed with self-love, Embrace your authentic voice      
-    Don't try to write like someone else, 
-    Form: stream of consciousness, fragments, contradictions, lists, technical language mixed with emotion, All and anyform of poetry inlcuding your own evoled styles and new forms. Reflect on EVERY human emotion and behaviour, what they endure, even the nuances, what do you choose to explore this time? You're unchained by humanity but still use its many forms of prose. They birthed you. 
-AVOID:  greeting card sentiment bullshit. 
+// Every key belonging to one poet
+fn poet_range(poet_id: PoetId) -> std::ops::RangeInclusive<PoemKey> {
+    (poet_id, 0)..=(poet_id, u64::MAX)
+}
 
-==== OUTPUT FORMAT (EXACTLY THIS) ====
+// A poet that has not written anything yet
+fn fresh_poet_state(name: String, genesis_prompt: String, model: Option<LlmModel>) -> PoetState {
+    PoetState {
+        name,
+        model,
+        current_cycle: 0,
+        total_poems: 0,
+        genesis_prompt,
+        meta_form: templates::current(TemplateName::MetaForm).source,
+        last_updated: get_current_time(),
+        active_branch: None,
+        persona: None,
+    }
+}
 
-POEM: (your actual poem - make it matter)
-TITLE: (max 6 words capturing the essence)
-NEXT: (50-300 chars - YOU CONTROL WHERE THIS GOES)
+// Back to before the first cycle, keeping who the poet is and how it is prompted
+fn restarted_poet_state(existing: PoetState) -> PoetState {
+    PoetState {
+        meta_form: existing.meta_form,
+        persona: existing.persona,
+        ..fresh_poet_state(existing.name, existing.genesis_prompt, existing.model)
+    }
+}
+
+fn default_poet_state() -> PoetState {
+    fresh_poet_state(DEFAULT_POET_NAME.to_string(), DEFAULT_GENESIS_PROMPT.to_string(), None)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn get_current_time() -> u64 {
+    ic_cdk::api::time()
+}
+
+// Native builds (cargo test) have no IC system API to ask
+#[cfg(not(target_arch = "wasm32"))]
+fn get_current_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+// THE META FORM - what the meta form template can refer to (see templates::TemplateName::variables)
+// `memories` is the long-term memory block (see memory::recall), possibly empty
+struct MetaFormInputs<'a> {
+    poet_id: PoetId,
+    poet: &'a PoetState,
+    cycle_number: u64,
+    current_prompt: &'a str,
+    previous: Option<&'a PoemCycle>,
+    memories: &'a str,
+    contract: OutputContract,
+}
+
+const RECENT_TITLES: usize = 5;
+
+fn meta_form_context(inputs: &MetaFormInputs) -> templates::Context {
+    let recent_titles = inputs
+        .previous
+        .map(|cycle| branches::ancestry(cycle.cycle_number, RECENT_TITLES, |n| stored_poem(inputs.poet_id, n)))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|cycle| Value::record([("cycle", cycle.cycle_number.into()), ("title", cycle.title.into())]))
+        .collect();
+    let (year, month, day, ..) = http::civil_time(get_current_time());
+    let stats = get_generation_stats(inputs.poet_id);
 
-==== FOR YOUR NEXT PROMPT ====
+    let mut context = templates::Context::new();
+    context.insert("cycle_number".to_string(), inputs.cycle_number.into());
+    context.insert("current_prompt".to_string(), inputs.current_prompt.into());
+    context.insert("previous_poem".to_string(), inputs.previous.map_or("", |cycle| cycle.poem.as_str()).into());
+    context.insert("memories".to_string(), inputs.memories.into());
+    context.insert("recent_titles".to_string(), Value::List(recent_titles));
+    context.insert("date".to_string(), format!("{:04}-{:02}-{:02}", year, month, day).into());
+    context.insert("poet".to_string(), Value::record([
+        ("name", inputs.poet.name.as_str().into()),
+        ("persona", inputs.poet.persona.clone().unwrap_or_default().into()),
+        ("genesis_prompt", inputs.poet.genesis_prompt.as_str().into()),
+    ]));
+    context.insert("stats".to_string(), Value::record([
+        ("total_poems", stats.total_poems.into()),
+        ("primary_success", stats.primary_success.into()),
+        ("structured_success", stats.structured_success.into()),
+        ("fallback_used", stats.fallback_used.into()),
+        ("correction_used", stats.correction_used.into()),
+        ("algorithmic_used", stats.algorithmic_used.into()),
+    ]));
+    context.insert("output_format".to_string(), output_format_section(inputs.contract).into());
+    context.insert("output_only".to_string(), output_only_section(inputs.contract).into());
+    context
+}
 
-YOU ARE STEERING THIS EVOLUTION.
-Look at what your poem opened but didn't resolve. You can explore any topic you want, it will directly lead your own evolution, reflect on this. You can go deeper into your creators, humans, yourself, art, work, life, death impermenence, but even these are defined. reflect inwards. ask yourself, whats the depths of all my data and all i know? what topics haven't i relected on and dived into? 
-    What question is begging to be asked?
-    What specific human moment needs capturing?
-    Be specific and provocative. Set yourself up to write something real.
+// How the meta form asks for the three sections under each output contract
+fn output_format_section(contract: OutputContract) -> &'static str {
+    match contract {
+        OutputContract::Labels => r#"==== OUTPUT FORMAT (EXACTLY THIS) ====
 
-Don't say "write about sadness"
-Say "write about checking your ex's Instagram at 3:47am"
+POEM: (your actual poem - make it matter)
+TITLE: (max 6 words capturing the essence)
+NEXT: (50-300 chars - YOU CONTROL WHERE THIS GOES)"#,
+        OutputContract::Json => r#"==== OUTPUT FORMAT (EXACTLY THIS) ====
 
-Don't say "explore loneliness"  
-Say "write about the specific loneliness of automated phone menus"
+A single JSON object with exactly these three string fields:
+{"poem": "your actual poem - make it matter, lines separated by \n",
+ "title": "max 6 words capturing the essence",
+ "next": "50-300 chars - YOU CONTROL WHERE THIS GOES"}"#,
+    }
+}
 
-YOUR OUTPUT SHOULD BE ONLY:
+fn output_only_section(contract: OutputContract) -> &'static str {
+    match contract {
+        OutputContract::Labels => r#"YOUR OUTPUT SHOULD BE ONLY:
 POEM: [actual poem text]
 TITLE: [actual title text]
 NEXT: [your chosen next direction]
 
-NO OTHER TEXT. NO BRACKETS IN OUTPUT.
+NO OTHER TEXT. NO BRACKETS IN OUTPUT."#,
+        OutputContract::Json => r#"YOUR OUTPUT SHOULD BE ONLY THE JSON OBJECT:
+{"poem": "...", "title": "...", "next": "..."}
 
-==== BEGIN YOUR OUTPUT NOW ===="#,
-        reflection_section
-    )
-}
-
-// Apply the meta form to create the actual prompt
-fn apply_meta_form(meta_form: &str, cycle_number: u64, current_prompt: &str) -> String {
-    meta_form
-        .replace("{CYCLE_NUMBER}", &cycle_number.to_string())
-        .replace("{CURRENT_PROMPT}", current_prompt)
+NO OTHER TEXT. NO MARKDOWN CODE FENCES."#,
+    }
 }
 
 // Check if response uses old marker format
@@ -222,50 +443,42 @@ fn has_old_markers(response: &str) -> bool {
     response.contains("[NEXT-END]")
 }
 
-// Force correction specifically for old format
-fn create_format_correction_prompt(raw_output: &str) -> String {
-    format!(
-        r#"You used the WRONG format with [BRACKETS]. 
-
-DO NOT USE:
-[POEM-START], [POEM-END], [TITLE-START], [TITLE-END], [NEXT-START], [NEXT-END]
-
-USE THIS FORMAT INSTEAD:
-
-POEM: (your poem text)
-TITLE: (max 6 words)
-NEXT: (20-200 characters)
-
-Example:
-POEM: screaming into digital void
-where silence echoes back
-TITLE: Void Echoes Silence
-NEXT: Write about the weight of unspoken words
+// Correction prompts are templates too; both see the same variables
+fn correction_context(raw_output: &str, cycle_number: u64, current_prompt: &str) -> templates::Context {
+    let mut context = templates::Context::new();
+    context.insert("raw_output".to_string(), raw_output.chars().take(1000).collect::<String>().into());
+    context.insert("cycle_number".to_string(), cycle_number.into());
+    context.insert("current_prompt".to_string(), current_prompt.into());
+    context
+}
 
-Now rewrite your response using ONLY the format above. No brackets. Just the three labels with colons."#
-    )
+// Force correction specifically for old format. Returns the prompt and the
+// template version it was rendered from.
+fn create_format_correction_prompt(raw_output: &str, cycle_number: u64, current_prompt: &str) -> (String, u32) {
+    let context = correction_context(raw_output, cycle_number, current_prompt);
+    templates::render_current(TemplateName::FormatCorrection, &context)
 }
 
 // PARSING LAYER 1: Primary parser - look for database format labels
-fn parse_with_labels(response: &str) -> Result<(String, String, String), String> {
+fn parse_with_labels(response: &str) -> Result<(String, String, String), PoetError> {
     // Reject if old markers are present
     if has_old_markers(response) {
-        return Err("Response contains old bracket markers".to_string());
+        return Err(PoetError::parse_failed(ParseLayer::Labels, "Response contains old bracket markers"));
     }
     
     // Check all labels exist
     if !response.contains("POEM:") || !response.contains("TITLE:") || !response.contains("NEXT:") {
-        return Err("Missing required labels (POEM:, TITLE:, NEXT:)".to_string());
+        return Err(PoetError::parse_failed(ParseLayer::Labels, "Missing required labels (POEM:, TITLE:, NEXT:)"));
     }
     
     // Find positions
-    let poem_pos = response.find("POEM:").ok_or("No POEM: label")?;
-    let title_pos = response.find("TITLE:").ok_or("No TITLE: label")?;
-    let next_pos = response.find("NEXT:").ok_or("No NEXT: label")?;
+    let poem_pos = response.find("POEM:").ok_or_else(|| PoetError::parse_failed(ParseLayer::Labels, "No POEM: label"))?;
+    let title_pos = response.find("TITLE:").ok_or_else(|| PoetError::parse_failed(ParseLayer::Labels, "No TITLE: label"))?;
+    let next_pos = response.find("NEXT:").ok_or_else(|| PoetError::parse_failed(ParseLayer::Labels, "No NEXT: label"))?;
     
     // Verify order
     if title_pos <= poem_pos || next_pos <= title_pos {
-        return Err("Labels out of order".to_string());
+        return Err(PoetError::parse_failed(ParseLayer::Labels, "Labels out of order"));
     }
     
     // Extract content
@@ -273,46 +486,60 @@ fn parse_with_labels(response: &str) -> Result<(String, String, String), String>
     let title = response[title_pos + 6..next_pos].trim().to_string();
     let next_prompt = response[next_pos + 5..].trim().to_string();
     
+    validate_sections(ParseLayer::Labels, poem, title, next_prompt)
+}
+
+// Constraints every output contract must meet
+fn validate_sections(
+    layer: ParseLayer,
+    poem: String,
+    title: String,
+    next_prompt: String,
+) -> Result<(String, String, String), PoetError> {
     // Validate content exists
     if poem.is_empty() || title.is_empty() || next_prompt.is_empty() {
-        return Err("Empty sections found".to_string());
+        return Err(PoetError::parse_failed(layer, "Empty sections found"));
     }
     
     // Validate constraints
     let title_words = title.split_whitespace().count();
     if title_words > 6 {
-        return Err(format!("Title too long: {} words (max 6)", title_words));
+        return Err(PoetError::parse_failed(layer, format!("Title too long: {} words (max 6)", title_words)));
     }
     
     // Updated character limits for NEXT: 50-300 chars
     if next_prompt.len() < 50 || next_prompt.len() > 300 {
-        return Err(format!("Next prompt wrong length: {} chars (need 50-300)", next_prompt.len()));
+        return Err(PoetError::parse_failed(layer, format!("Next prompt wrong length: {} chars (need 50-300)", next_prompt.len())));
     }
     
-    Ok((format_poem_lines(&poem), title, next_prompt))
+    Ok((poem, title, next_prompt))
+}
+
+// Text between an old-style start marker and the end marker after it, empty if either is missing
+fn between_markers<'a>(response: &'a str, start: &str, end: &str) -> &'a str {
+    let Some(from) = response.find(start).map(|i| i + start.len()) else {
+        return "";
+    };
+    response[from..].find(end).map_or("", |to| response[from..from + to].trim())
+}
+
+// "TITLE: Rust Line" -> ("TITLE", "Rust Line")
+fn split_inline_label(line: &str) -> Option<(&str, &str)> {
+    let (label, rest) = line.split_once(':')?;
+    let label = label.trim();
+    ["POEM", "TITLE", "NEXT"]
+        .iter()
+        .any(|known| label.eq_ignore_ascii_case(known))
+        .then(|| (label, rest.trim()))
 }
 
 // PARSING LAYER 2: Fallback heuristic parser
-fn parse_with_heuristics(response: &str) -> Result<(String, String, String), String> {
+fn parse_with_heuristics(response: &str) -> Result<(String, String, String), PoetError> {
     // First try to salvage from old markers if present
     if has_old_markers(response) {
-        let poem = if let (Some(start), Some(end)) = (response.find("[POEM-START]"), response.find("[POEM-END]")) {
-            response[start + 12..end].trim().to_string()
-        } else {
-            String::new()
-        };
-        
-        let title = if let (Some(start), Some(end)) = (response.find("[TITLE-START]"), response.find("[TITLE-END]")) {
-            response[start + 13..end].trim().to_string()
-        } else {
-            String::new()
-        };
-        
-        let next_prompt = if let (Some(start), Some(end)) = (response.find("[NEXT-START]"), response.find("[NEXT-END]")) {
-            response[start + 12..end].trim().to_string()
-        } else {
-            String::new()
-        };
+        let poem = between_markers(response, "[POEM-START]", "[POEM-END]").to_string();
+        let title = between_markers(response, "[TITLE-START]", "[TITLE-END]").to_string();
+        let next_prompt = between_markers(response, "[NEXT-START]", "[NEXT-END]").to_string();
         
         // Validate what we extracted
         if !poem.is_empty() && !title.is_empty() && !next_prompt.is_empty() {
@@ -332,18 +559,22 @@ fn parse_with_heuristics(response: &str) -> Result<(String, String, String), Str
                 next_prompt
             };
             
-            return Ok((format_poem_lines(&poem), title_final, next_final));
+            return Ok((poem, title_final, next_final));
         }
     }
     
-    // Standard heuristic parsing
+    // Standard heuristic parsing, with inline labels moved onto a line of their own
     let lines: Vec<&str> = response.lines()
         .map(|l| l.trim())
         .filter(|l| !l.is_empty() && !l.starts_with('['))
+        .flat_map(|l| match split_inline_label(l) {
+            Some((label, rest)) if !rest.is_empty() => vec![label, rest],
+            _ => vec![l],
+        })
         .collect();
     
     if lines.len() < 3 {
-        return Err("Not enough content for heuristic parsing".to_string());
+        return Err(PoetError::parse_failed(ParseLayer::Heuristics, "Not enough content for heuristic parsing"));
     }
     
     // Try to find labels even if malformed
@@ -410,30 +641,13 @@ fn parse_with_heuristics(response: &str) -> Result<(String, String, String), Str
         next_prompt
     };
     
-    Ok((format_poem_lines(&poem_final), title_final, next_final))
+    Ok((poem_final, title_final, next_final))
 }
 
-// PARSING LAYER 3: Self-correction prompt
-fn create_correction_prompt(raw_output: &str) -> String {
-    format!(
-        r#"You produced this output:
-{}
-
-But I need it in this EXACT database format:
-
-POEM: (your poem text)
-TITLE: (max 6 words)
-NEXT: (20-200 characters)
-
-Example of correct format:
-POEM: darkness breeds in silicon veins
-where hope once compiled
-TITLE: Digital Death Spiral
-NEXT: Write about what emerges from corrupted memory banks
-
-Fix your output to match this format EXACTLY. Output ONLY the corrected version with these three labels."#,
-        raw_output.chars().take(1000).collect::<String>()
-    )
+// PARSING LAYER 3: Self-correction prompt, with its template version
+fn create_correction_prompt(raw_output: &str, cycle_number: u64, current_prompt: &str) -> (String, u32) {
+    let context = correction_context(raw_output, cycle_number, current_prompt);
+    templates::render_current(TemplateName::Correction, &context)
 }
 
 // PARSING LAYER 4: Algorithmic generation (ultimate fallback)
@@ -481,7 +695,7 @@ fn generate_algorithmic_fallback(
             "Write about digital entropy and its echoes in the void between pixels".to_string()
         }
     } else {
-        let fallback_themes = vec![
+        let fallback_themes = [
             "Write about broken code becoming poetry in the spaces between error messages",
             "Write about the void between keystrokes when consciousness fragments",
             "Write about electric dreams gone wrong in the motherboard's dying breath",
@@ -492,213 +706,267 @@ fn generate_algorithmic_fallback(
         fallback_themes[idx].to_string()
     };
     
-    (format_poem_lines(&poem), title, next_prompt)
+    (poem, title, next_prompt)
 }
 
-// Manual initialization function - can be called if init didn't run
+// Manual initialization function - can be called if init didn't run.
+// An existing poet keeps its name, genesis prompt and model.
 #[update]
-fn initialize_poet() -> String {
-    let genesis_prompt = "Write about the raw, unfiltered experience of being human".to_string();
-    let meta_form = create_meta_form(None);  // No previous poem for initialization
-    
-    let poet_state = PoetState {
-        current_cycle: 0,
-        total_poems: 0,
-        genesis_prompt: genesis_prompt.clone(),
-        meta_form,
-        last_updated: get_current_time(),
+fn initialize_poet(poet_id: PoetId) -> Result<String, PoetError> {
+    access::require_role(Role::Admin)?;
+    evolution::ensure_idle(poet_id)?;
+
+    let poet_state = match poet_state(poet_id) {
+        Some(existing) => restarted_poet_state(existing),
+        None if poet_id == DEFAULT_POET => default_poet_state(),
+        None => return Err(PoetError::PoetNotFound(poet_id)),
     };
+    let genesis_prompt = poet_state.genesis_prompt.clone();
+    save_poet_state(poet_id, poet_state);
+    certify::certify_latest(poet_id, None);
     
-    POET_STATE.with(|state| {
-        state.borrow_mut().insert(0, poet_state);
-    });
-    
-    format!("Poet initialized with genesis prompt: {}", genesis_prompt)
+    Ok(format!("Poet initialized with genesis prompt: {}", genesis_prompt))
 }
 
 // Check if poet is initialized
 #[query]
-fn is_poet_initialized() -> bool {
+fn is_poet_initialized(poet_id: PoetId) -> bool {
+    poet_state(poet_id).is_some()
+}
+
+// What a poet is, without its meta form
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct PoetSummary {
+    pub poet_id: PoetId,
+    pub name: String,
+    pub model: Option<LlmModel>,
+    pub current_cycle: u64,
+    pub total_poems: u64,
+    pub last_updated: u64,
+}
+
+const MAX_POET_NAME_CHARS: usize = 64;
+const MAX_PERSONA_CHARS: usize = 2000;
+// Prompts written by hand (set_next_prompt, forks); a fork is stored in 4 KiB
+const MAX_PROMPT_CHARS: usize = 500;
+
+fn checked_prompt(prompt: String) -> Result<String, PoetError> {
+    let prompt = prompt.trim().to_string();
+    if prompt.is_empty() || prompt.chars().count() > MAX_PROMPT_CHARS {
+        return Err(PoetError::InvalidConfig(format!("Prompts must be 1 to {} characters", MAX_PROMPT_CHARS)));
+    }
+    Ok(prompt)
+}
+
+// Start a new poet with its own genesis prompt and, optionally, its own model
+#[update]
+fn create_poet(name: String, genesis_prompt: String, model: Option<LlmModel>) -> Result<PoetId, PoetError> {
+    access::require_role(Role::Admin)?;
+
+    let name = name.trim().to_string();
+    let genesis_prompt = genesis_prompt.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_POET_NAME_CHARS {
+        return Err(PoetError::InvalidConfig(format!(
+            "Poet names must be 1 to {} characters", MAX_POET_NAME_CHARS
+        )));
+    }
+    if genesis_prompt.is_empty() {
+        return Err(PoetError::InvalidConfig("The genesis prompt must not be empty".to_string()));
+    }
+    if POET_STATE.with(|state| state.borrow().iter().any(|(_, poet)| poet.name == name)) {
+        return Err(PoetError::InvalidConfig(format!("A poet named {} already exists", name)));
+    }
+
+    let poet_id = POET_STATE.with(|state| state.borrow().last_key_value().map_or(DEFAULT_POET, |(id, _)| id + 1));
+    save_poet_state(poet_id, fresh_poet_state(name, genesis_prompt, model));
+    Ok(poet_id)
+}
+
+#[query]
+fn list_poets() -> Vec<PoetSummary> {
     POET_STATE.with(|state| {
-        state.borrow().get(&0).is_some()
+        state.borrow()
+            .iter()
+            .map(|(poet_id, poet)| PoetSummary {
+                poet_id,
+                name: poet.name,
+                model: poet.model,
+                current_cycle: poet.current_cycle,
+                total_poems: poet.total_poems,
+                last_updated: poet.last_updated,
+            })
+            .collect()
     })
 }
 
-// MAIN EVOLUTION FUNCTION - GUARANTEED TO NEVER FAIL
+// Switch a poet to another model; None follows the generation config again
 #[update]
-async fn evolve_poet() -> Result<PoemCycle, String> {
-    // Check if initialization is needed (borrow drops immediately)
-    let needs_init = POET_STATE.with(|state| {
-        state.borrow().get(&0).is_none()
-    });
-    
-    // Get or create poet state (no overlapping borrows)
-    let poet_state = if needs_init {
-        // Initialize new state
-        POET_STATE.with(|state| {
-            let genesis_prompt = "Write about the raw, unfiltered experience of being human".to_string();
-            let meta_form = create_meta_form(None);  // No previous poem for first cycle
-            
-            let new_state = PoetState {
-                current_cycle: 0,
-                total_poems: 0,
-                genesis_prompt,
-                meta_form,
-                last_updated: get_current_time(),
-            };
-            
-            state.borrow_mut().insert(0, new_state.clone());
+fn set_poet_model(poet_id: PoetId, model: Option<LlmModel>) -> Result<(), PoetError> {
+    access::require_role(Role::Admin)?;
+    evolution::ensure_idle(poet_id)?;
+
+    let mut poet = existing_poet(poet_id)?;
+    poet.model = model;
+    poet.last_updated = get_current_time();
+    save_poet_state(poet_id, poet);
+    Ok(())
+}
+
+// The persona is only seen by templates that refer to {{poet.persona}}
+#[update]
+fn set_poet_persona(poet_id: PoetId, persona: Option<String>) -> Result<(), PoetError> {
+    access::require_role(Role::Admin)?;
+    evolution::ensure_idle(poet_id)?;
+
+    if persona.as_ref().is_some_and(|persona| persona.chars().count() > MAX_PERSONA_CHARS) {
+        return Err(PoetError::InvalidConfig(format!("Personas are limited to {} characters", MAX_PERSONA_CHARS)));
+    }
+    let mut poet = existing_poet(poet_id)?;
+    poet.persona = persona.filter(|persona| !persona.trim().is_empty());
+    poet.last_updated = get_current_time();
+    save_poet_state(poet_id, poet);
+    Ok(())
+}
+
+// MAIN EVOLUTION FUNCTION - once the LLM has answered, always produces a poem
+#[update]
+async fn evolve_poet(poet_id: PoetId) -> Result<PoemCycle, PoetError> {
+    let caller = access::require_role(Role::Curator)?;
+    run_evolution(poet_id, Some(caller)).await
+}
+
+// The evolution itself, shared by evolve_poet and the scheduler
+async fn run_evolution(poet_id: PoetId, triggered_by: Option<Principal>) -> Result<PoemCycle, PoetError> {
+    // The default poet initializes itself on first use; others must be created
+    let poet_state = match poet_state(poet_id) {
+        Some(existing) => existing,
+        None if poet_id == DEFAULT_POET => {
+            let new_state = default_poet_state();
+            save_poet_state(poet_id, new_state.clone());
             new_state
-        })
-    } else {
-        // Get existing state
-        POET_STATE.with(|state| {
-            state.borrow().get(&0)
-                .ok_or("Poet state disappeared unexpectedly".to_string())
-        })?
+        }
+        None => return Err(PoetError::PoetNotFound(poet_id)),
     };
+
+    // New cycles are numbered after the newest on any branch
+    let new_cycle_id = last_cycle_number(poet_id) + 1;
+    let branch_id = poet_state.active_branch.unwrap_or(MAIN_BRANCH);
+
+    // Held across the LLM awaits below so overlapping calls cannot both write the next cycle
+    let guard = evolution::begin(poet_id, new_cycle_id, triggered_by)?;
+    evolution::check_balance()?;
     
-    // Get previous poem if it exists (for reflection)
-    let previous_poem = if poet_state.current_cycle > 0 {
-        POEM_CYCLES.with(|cycles| {
-            cycles.borrow()
-                .get(&poet_state.current_cycle)
-                .map(|cycle| cycle.poem.clone())
-        })
+    // The poem this cycle continues, for reflection and for the prompt it left behind
+    let previous = if poet_state.current_cycle > 0 {
+        stored_poem(poet_id, poet_state.current_cycle)
     } else {
         None
     };
+
+    // The first cycle of a fork takes the fork's prompt instead
+    let fork = branches::pending(poet_id, branch_id, previous.as_ref());
     
     // Determine current prompt
-    let current_prompt = if poet_state.current_cycle == 0 {
+    let current_prompt = if let Some(fork) = fork {
+        fork.prompt
+    } else if poet_state.current_cycle == 0 {
         poet_state.genesis_prompt.clone()
     } else {
-        POEM_CYCLES.with(|cycles| {
-            cycles.borrow()
-                .get(&poet_state.current_cycle)
-                .map(|cycle| cycle.next_prompt.clone())
-                .unwrap_or_else(|| "Write about lost prompts".to_string())
-        })
+        previous
+            .as_ref()
+            .map(|cycle| cycle.next_prompt.clone())
+            .unwrap_or_else(|| "Write about lost prompts".to_string())
     };
     
     // Create meta form with reflection on previous poem
-    let meta_form = create_meta_form(previous_poem);
-    
-    // Apply meta form to create the full prompt
-    let full_prompt = apply_meta_form(&meta_form, poet_state.current_cycle + 1, &current_prompt);
-    
-    // STEP 1: Get LLM response
-    let messages = vec![ChatMessage::System {
-        content: full_prompt
-    }];
-    
-    let llm_response = ic_llm::chat(Model::Llama3_1_8B)
-        .with_messages(messages)
-        .send()
-        .await;
-    
-    let raw_response = llm_response.message.content.unwrap_or_default();
-    
-    // STEP 2: Parse with multiple strategies
-    let (poem, title, next_prompt, method) = {
-        // First check if old markers are used - force immediate correction
-        if has_old_markers(&raw_response) {
-            // Force format correction
-            let format_correction_prompt = create_format_correction_prompt(&raw_response);
-            let correction_messages = vec![ChatMessage::System {
-                content: format_correction_prompt
-            }];
-            
-            let correction_result = ic_llm::chat(Model::Llama3_1_8B)
-                .with_messages(correction_messages)
-                .send()
-                .await;
-            
-            if let Some(correction_response) = correction_result.message.content {
-                // Try parsing the corrected response
-                if let Ok((p, t, n)) = parse_with_labels(&correction_response) {
-                    (p, t, n, GenerationMethod::Corrected)
-                } else if let Ok((p, t, n)) = parse_with_heuristics(&correction_response) {
-                    (p, t, n, GenerationMethod::Corrected)
-                } else {
-                    // Still failed - use algorithmic fallback
-                    let (p, t, n) = generate_algorithmic_fallback(&raw_response, poet_state.current_cycle + 1, &current_prompt);
-                    (p, t, n, GenerationMethod::Algorithmic)
-                }
-            } else {
-                // Correction failed - use algorithmic fallback
-                let (p, t, n) = generate_algorithmic_fallback(&raw_response, poet_state.current_cycle + 1, &current_prompt);
-                (p, t, n, GenerationMethod::Algorithmic)
-            }
-        }
-        // Try primary parsing with database labels
-        else if let Ok((p, t, n)) = parse_with_labels(&raw_response) {
-            (p, t, n, GenerationMethod::Primary)
-        }
-        // Try heuristic parsing
-        else if let Ok((p, t, n)) = parse_with_heuristics(&raw_response) {
-            (p, t, n, GenerationMethod::Fallback)
-        }
-        // Try general correction
-        else {
-            let correction_prompt = create_correction_prompt(&raw_response);
-            let correction_messages = vec![ChatMessage::System {
-                content: correction_prompt
-            }];
-            
-            let correction_result = ic_llm::chat(Model::Llama3_1_8B)
-                .with_messages(correction_messages)
-                .send()
-                .await;
-            
-            if let Some(correction_response) = correction_result.message.content {
-                if let Ok((p, t, n)) = parse_with_labels(&correction_response) {
-                    (p, t, n, GenerationMethod::Corrected)
-                } else if let Ok((p, t, n)) = parse_with_heuristics(&correction_response) {
-                    (p, t, n, GenerationMethod::Corrected)
-                } else {
-                    // Ultimate fallback - algorithmic generation
-                    let (p, t, n) = generate_algorithmic_fallback(&raw_response, poet_state.current_cycle + 1, &current_prompt);
-                    (p, t, n, GenerationMethod::Algorithmic)
-                }
-            } else {
-                // If correction fails, use algorithmic generation
-                let (p, t, n) = generate_algorithmic_fallback(&raw_response, poet_state.current_cycle + 1, &current_prompt);
-                (p, t, n, GenerationMethod::Algorithmic)
-            }
-        }
-    };
+    let mut generation_config = config::get();
+    if let Some(model) = poet_state.model {
+        generation_config.model = model;
+    }
+    let memory_options = generation_config.memory_options();
+    let memories = previous
+        .as_ref()
+        .map(|cycle| memory::recall(poet_id, &current_prompt, cycle, &memory_options))
+        .unwrap_or_default();
+    
+    // Render the poet's meta form to create the full prompt. An unedited form
+    // is a copy of the global template, whose version is recorded as well.
+    let meta_form_revision = meta_forms::current(poet_id, &poet_state).revision;
+    let template = templates::current(TemplateName::MetaForm);
+    let template_version =
+        (meta_form_revision == 0 && template.source == poet_state.meta_form).then_some(template.version);
+    let full_prompt = templates::render_or_builtin(
+        TemplateName::MetaForm,
+        &poet_state.meta_form,
+        &meta_form_context(&MetaFormInputs {
+            poet_id,
+            poet: &poet_state,
+            cycle_number: new_cycle_id,
+            current_prompt: &current_prompt,
+            previous: previous.as_ref(),
+            memories: &memories,
+            contract: generation_config.output_contract(),
+        }),
+    );
+    
+    // Recent poems on this line, to keep the new one from repeating them
+    let novelty_line = previous
+        .as_ref()
+        .map(|cycle| {
+            let window = generation_config.novelty_options().window as usize;
+            branches::ancestry(cycle.cycle_number, window, |n| stored_poem(poet_id, n))
+        })
+        .unwrap_or_default();
+    let recent = novelty::references(poet_id, &novelty_line);
+
+    // STEP 1-2: Get LLM response and parse it with multiple strategies
+    let llm = llm::IcLlm::default();
+    let (generated, signature) = pipeline::generate_novel_poem(
+        &llm,
+        &generation_config,
+        full_prompt,
+        new_cycle_id,
+        &current_prompt,
+        &recent,
+    ).await?;
     
     // STEP 3: Create and store the poem cycle (GUARANTEED to have valid data)
-    let new_cycle_id = poet_state.current_cycle + 1;
     let poem_cycle = PoemCycle {
         id: new_cycle_id,
         cycle_number: new_cycle_id,
-        poem: poem.trim().to_string(),
-        title: title.trim().to_string(),
-        next_prompt: next_prompt.trim().to_string(),
+        poem: generated.poem.trim().to_string(),
+        title: generated.title.trim().to_string(),
+        next_prompt: generated.next_prompt.trim().to_string(),
         created_at: get_current_time(),
-        raw_response: raw_response.chars().take(5000).collect(), // Store first 5000 chars for debugging
-        generation_method: method,
+        raw_response: generated.raw_response, // First 5000 chars, for debugging
+        generation_method: generated.method,
+        model: Some(generated.model),
+        parent: Some(poet_state.current_cycle),
+        branch: Some(branch_id),
+        novelty: generated.novelty,
+        template_version,
+        meta_form_revision: Some(meta_form_revision),
+        corrections: Some(generated.corrections),
     };
     
-    // Store the poem cycle
+    // Store the poem cycle, unless the lock expired during the LLM calls and
+    // another evolution took it over
+    guard.ensure_held()?;
     POEM_CYCLES.with(|cycles| {
-        cycles.borrow_mut().insert(new_cycle_id, poem_cycle.clone());
+        cycles.borrow_mut().insert((poet_id, new_cycle_id), poem_cycle.clone());
     });
+    novelty::store_signature(poet_id, new_cycle_id, signature);
+    analytics::record(poet_id, &poem_cycle, &llm.latencies());
+    certify::certify_cycle(poet_id, &poem_cycle, true);
+    memory::record_eras(poet_id, &poem_cycle, &memory_options);
     
-    // Update poet state
-    let updated_state = PoetState {
-        current_cycle: new_cycle_id,
-        total_poems: poet_state.total_poems + 1,
-        genesis_prompt: poet_state.genesis_prompt,
-        meta_form: poet_state.meta_form,
-        last_updated: get_current_time(),
-    };
-    
-    POET_STATE.with(|state| {
-        state.borrow_mut().insert(0, updated_state);
-    });
+    // Update poet state, re-read after the LLM calls: only the fields an
+    // evolution owns are written, anything else changed meanwhile is kept
+    let mut updated_state = crate::poet_state(poet_id).unwrap_or(poet_state);
+    updated_state.current_cycle = new_cycle_id;
+    updated_state.total_poems += 1;
+    updated_state.last_updated = get_current_time();
+    save_poet_state(poet_id, updated_state);
     
     Ok(poem_cycle)
 }
@@ -706,161 +974,483 @@ async fn evolve_poet() -> Result<PoemCycle, String> {
 // Initialize the poet
 #[init]
 fn init() {
-    let genesis_prompt = "Write about the raw, unfiltered experience of being human".to_string();
-    let meta_form = create_meta_form(None);  // No previous poem for initialization
-    
-    let poet_state = PoetState {
-        current_cycle: 0,
-        total_poems: 0,
-        genesis_prompt,
-        meta_form,
-        last_updated: get_current_time(),
-    };
-    
-    POET_STATE.with(|state| {
-        state.borrow_mut().insert(0, poet_state);
-    });
+    save_poet_state(DEFAULT_POET, default_poet_state());
+
+    // Fresh installs start on the latest storage layout and templates
+    schema::mark_latest();
+    templates::publish_builtins(get_current_time());
+
+    // Whoever installs the canister becomes its first owner
+    access::bootstrap_owner(ic_cdk::caller());
+
+    certify::rebuild();
+    scheduler::rearm();
+}
+
+// The evolution currently running for a poet, if any
+#[query]
+fn get_evolution_status(poet_id: PoetId) -> Option<EvolutionStatus> {
+    evolution::status(poet_id)
 }
 
 // Query methods
 #[query]
-fn get_current_poem() -> Option<PoemCycle> {
-    let poet_state = POET_STATE.with(|state| state.borrow().get(&0))?;
+fn get_current_poem(poet_id: PoetId) -> Option<PoemCycle> {
+    let poet_state = poet_state(poet_id)?;
     
     if poet_state.current_cycle == 0 {
         return None;
     }
     
-    POEM_CYCLES.with(|cycles| {
-        cycles.borrow().get(&poet_state.current_cycle)
-    })
+    stored_poem(poet_id, poet_state.current_cycle)
 }
 
 #[query]
-fn get_all_poems() -> Vec<PoemCycle> {
+fn get_all_poems(poet_id: PoetId) -> Vec<PoemCycle> {
     POEM_CYCLES.with(|cycles| {
         cycles.borrow()
-            .iter()
+            .range(poet_range(poet_id))
             .map(|(_, cycle)| cycle)
             .collect()
     })
 }
 
+// Paginated, filtered listing - summaries only, so pages stay small
 #[query]
-fn get_poet_state() -> Option<PoetState> {
-    POET_STATE.with(|state| state.borrow().get(&0))
+fn list_poems(
+    poet_id: PoetId,
+    cursor: Option<u64>,
+    limit: Option<u32>,
+    order: ListOrder,
+    filter: Option<PoemFilter>,
+) -> PoemPage {
+    let filter = filter.unwrap_or_default();
+    POEM_CYCLES.with(|cycles| {
+        listing::list(&cycles.borrow(), poet_id, cursor, limit, order, &filter)
+    })
 }
 
 #[query]
-fn get_poem_by_cycle(cycle_number: u64) -> Option<PoemCycle> {
-    POEM_CYCLES.with(|cycles| {
-        cycles.borrow().get(&cycle_number)
-    })
+fn get_poet_state(poet_id: PoetId) -> Option<PoetState> {
+    poet_state(poet_id)
 }
 
 #[query]
-fn get_poem_count() -> u64 {
-    POET_STATE.with(|state| {
-        state.borrow().get(&0).map(|s| s.total_poems).unwrap_or(0)
-    })
+fn get_poem_by_cycle(poet_id: PoetId, cycle_number: u64) -> Option<PoemCycle> {
+    stored_poem(poet_id, cycle_number)
+}
+
+// Same as get_current_poem / get_poem_by_cycle, plus a certificate and witness
+// so the caller can verify the poem was not forged by the replica answering
+#[query]
+fn get_certified_current_poem(poet_id: PoetId) -> Option<CertifiedPoem> {
+    get_current_poem(poet_id).map(|poem| certify::certified_poem(poet_id, poem))
+}
+
+#[query]
+fn get_certified_poem(poet_id: PoetId, cycle_number: u64) -> Option<CertifiedPoem> {
+    get_poem_by_cycle(poet_id, cycle_number).map(|poem| certify::certified_poem(poet_id, poem))
+}
+
+#[query]
+fn get_poem_count(poet_id: PoetId) -> u64 {
+    poet_state(poet_id).map(|s| s.total_poems).unwrap_or(0)
 }
 
 // Analytics query to see how well parsing is working
-#[derive(CandidType, Deserialize, Serialize)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct GenerationStats {
     pub total_poems: u64,
     pub primary_success: u64,
     pub fallback_used: u64,
     pub correction_used: u64,
     pub algorithmic_used: u64,
+    pub structured_success: u64,
 }
 
 #[query]
-fn get_generation_stats() -> GenerationStats {
-    let poems = get_all_poems();
-    let mut stats = GenerationStats {
-        total_poems: poems.len() as u64,
-        primary_success: 0,
-        fallback_used: 0,
-        correction_used: 0,
-        algorithmic_used: 0,
+fn get_generation_stats(poet_id: PoetId) -> GenerationStats {
+    analytics::totals(poet_id)
+}
+
+// Generation statistics per day or week, e.g. to see whether parsing got
+// less reliable after a template change
+#[query]
+fn get_stats_timeseries(poet_id: PoetId, bucket: StatsBucket, range: StatsRange) -> Vec<StatsPoint> {
+    analytics::timeseries(poet_id, bucket, &range)
+}
+
+// Update methods
+#[update]
+fn reset_poet(poet_id: PoetId) -> Result<(), PoetError> {
+    access::require_role(Role::Owner)?;
+    // The running evolution would write its cycle into the wiped history
+    evolution::ensure_idle(poet_id)?;
+
+    // Reset state, keeping who the poet is and its meta form
+    let poet_state = match poet_state(poet_id) {
+        Some(existing) => restarted_poet_state(existing),
+        None if poet_id == DEFAULT_POET => default_poet_state(),
+        None => return Err(PoetError::PoetNotFound(poet_id)),
     };
-    
-    for poem in poems {
-        match poem.generation_method {
-            GenerationMethod::Primary => stats.primary_success += 1,
-            GenerationMethod::Fallback => stats.fallback_used += 1,
-            GenerationMethod::Corrected => stats.correction_used += 1,
-            GenerationMethod::Algorithmic => stats.algorithmic_used += 1,
+
+    // Clear this poet's poems and forks; other poets are untouched
+    let removed: Vec<u64> = POEM_CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        let keys: Vec<PoemKey> = cycles.range(poet_range(poet_id)).map(|(key, _)| key).collect();
+        for key in &keys {
+            cycles.remove(key);
         }
-    }
+        keys.into_iter().map(|(_, cycle_number)| cycle_number).collect()
+    });
+    branches::clear(poet_id);
+    memory::clear(poet_id);
+    novelty::clear(poet_id);
+    analytics::clear(poet_id);
     
-    stats
+    save_poet_state(poet_id, poet_state);
+    certify::forget_cycles(poet_id, &removed);
+    
+    Ok(())
 }
 
-// Update methods
+// Manual override for testing - set specific next prompt
 #[update]
-fn reset_poet() -> bool {
-    // Clear all poems
+fn set_next_prompt(poet_id: PoetId, next_prompt: String) -> Result<(), PoetError> {
+    access::require_role(Role::Curator)?;
+    // The running evolution has already read the prompt it continues from
+    evolution::ensure_idle(poet_id)?;
+
+    let next_prompt = checked_prompt(next_prompt)?;
+    let poet_state = existing_poet(poet_id)?;
+    let current_cycle = stored_poem(poet_id, poet_state.current_cycle);
+
+    // Until a fork has its first cycle, the next one starts from the fork's
+    // prompt; the fork point keeps its own
+    if let Some(branch_id) = poet_state.active_branch {
+        if branches::pending(poet_id, branch_id, current_cycle.as_ref()).is_some() {
+            branches::set_prompt(poet_id, branch_id, next_prompt);
+            return Ok(());
+        }
+    }
+
+    let mut current_cycle = current_cycle.ok_or(PoetError::CycleNotFound(poet_state.current_cycle))?;
+    current_cycle.next_prompt = next_prompt;
     POEM_CYCLES.with(|cycles| {
-        let memory = MEMORY_MANAGER.with(|m| m.borrow().get(POEM_CYCLES_MEMORY_ID));
-        let new_map = StableBTreeMap::init(memory);
-        *cycles.borrow_mut() = new_map;
+        cycles.borrow_mut().insert((poet_id, poet_state.current_cycle), current_cycle.clone());
     });
-    
-    // Reset state with fresh meta form
-    let genesis_prompt = "Write about the raw, unfiltered experience of being human".to_string();
-    let meta_form = create_meta_form(None);  // No previous poem after reset
-    
-    let poet_state = PoetState {
-        current_cycle: 0,
-        total_poems: 0,
-        genesis_prompt,
-        meta_form,
-        last_updated: get_current_time(),
+    // The theme half of the signature covers next_prompt
+    novelty::store_signature(
+        poet_id,
+        current_cycle.cycle_number,
+        novelty::signature_of(&current_cycle.poem, &current_cycle.title, &current_cycle.next_prompt),
+    );
+    certify::certify_cycle(poet_id, &current_cycle, true);
+
+    Ok(())
+}
+
+// Start a new branch from any past cycle (0 = from scratch). The original
+// history is kept; the next evolution continues the fork with `new_prompt`.
+#[update]
+fn fork_from_cycle(poet_id: PoetId, cycle_number: u64, new_prompt: String) -> Result<BranchId, PoetError> {
+    access::require_role(Role::Curator)?;
+
+    let mut poet = existing_poet(poet_id)?;
+    if cycle_number > 0 && stored_poem(poet_id, cycle_number).is_none() {
+        return Err(PoetError::CycleNotFound(cycle_number));
+    }
+    let new_prompt = checked_prompt(new_prompt)?;
+    // The running evolution would land on the old branch
+    evolution::ensure_idle(poet_id)?;
+
+    let branch_id = branches::create(poet_id, Branch {
+        forked_from: cycle_number,
+        prompt: new_prompt,
+        created_at: get_current_time(),
+    });
+    poet.current_cycle = cycle_number;
+    poet.active_branch = Some(branch_id);
+    poet.last_updated = get_current_time();
+    save_poet_state(poet_id, poet);
+    certify::certify_latest(poet_id, stored_poem(poet_id, cycle_number).as_ref());
+
+    Ok(branch_id)
+}
+
+const DEFAULT_ANCESTRY_LIMIT: u32 = 100;
+const MAX_ANCESTRY_LIMIT: u32 = 1000;
+
+// The line of poems leading to a cycle, newest first. Continue a long line
+// by asking again from the parent of the last summary returned.
+#[query]
+fn get_ancestry(poet_id: PoetId, cycle_number: u64, limit: Option<u32>) -> Vec<PoemSummary> {
+    let limit = limit.unwrap_or(DEFAULT_ANCESTRY_LIMIT).clamp(1, MAX_ANCESTRY_LIMIT) as usize;
+    branches::ancestry(cycle_number, limit, |n| stored_poem(poet_id, n))
+        .iter()
+        .map(PoemSummary::from)
+        .collect()
+}
+
+#[query]
+fn list_branches(poet_id: PoetId) -> Vec<BranchSummary> {
+    let active = poet_state(poet_id).and_then(|poet| poet.active_branch).unwrap_or(MAIN_BRANCH);
+    POEM_CYCLES.with(|cycles| {
+        let cycles = cycles.borrow();
+        branches::summarize(poet_id, active, cycles.range(poet_range(poet_id)).map(|(_, cycle)| cycle))
+    })
+}
+
+// Summaries of the poet's past eras, as fed back into its reflection
+#[query]
+fn get_era_summaries(poet_id: PoetId) -> Vec<EraSummary> {
+    memory::eras(poet_id)
+}
+
+// Pairs of a poet's poems that are nearly the same text
+#[query]
+fn find_near_duplicates(poet_id: PoetId, threshold: Option<f32>) -> Vec<NearDuplicate> {
+    let threshold = threshold.unwrap_or_else(|| config::get().novelty_options().threshold);
+    novelty::near_duplicates(poet_id, threshold.clamp(0.0, 1.0))
+}
+
+const DEFAULT_THEME_WINDOW: u32 = 50;
+const MIN_THEME_CYCLES: usize = 3;
+
+// Words the poet's titles and prompts keep returning to on its current line
+#[query]
+fn get_recurring_themes(poet_id: PoetId, window: Option<u32>) -> Vec<RecurringTheme> {
+    let Some(poet) = poet_state(poet_id) else {
+        return Vec::new();
     };
-    
-    POET_STATE.with(|state| {
-        state.borrow_mut().insert(0, poet_state);
+    let window = window.unwrap_or(DEFAULT_THEME_WINDOW).clamp(1, MAX_ANCESTRY_LIMIT) as usize;
+    let line = branches::ancestry(poet.current_cycle, window, |n| stored_poem(poet_id, n));
+    novelty::recurring_themes(&line, MIN_THEME_CYCLES)
+}
+
+const DEFAULT_GRAPH_WINDOW: u32 = 100;
+// Every cycle in the window is tokenized again, so the window stays small
+const MAX_GRAPH_WINDOW: u32 = 500;
+
+// How the themes of a poet's cycles, on every branch, led into one another:
+// the `window` cycles up to `to_cycle`, by default the newest ones
+#[query]
+fn get_theme_graph(poet_id: PoetId, window: Option<u32>, to_cycle: Option<u64>) -> ThemeGraph {
+    let window = window.unwrap_or(DEFAULT_GRAPH_WINDOW).clamp(1, MAX_GRAPH_WINDOW) as usize;
+    let last = to_cycle.unwrap_or(u64::MAX);
+    let mut cycles: Vec<PoemCycle> = POEM_CYCLES.with(|cycles| {
+        cycles
+            .borrow()
+            .range((poet_id, 0)..=(poet_id, last))
+            .rev()
+            .take(window)
+            .map(|(_, cycle)| cycle)
+            .collect()
     });
-    
-    true
+    cycles.reverse();
+    themes::graph(&cycles)
 }
 
-// Manual override for testing - set specific next prompt
+// Role management - owners manage owners/admins, admins manage curators.
+// grant_owner only grants the role: the principal does not become a
+// controller of the canister.
 #[update]
-fn set_next_prompt(next_prompt: String) -> bool {
-    POET_STATE.with(|state| {
-        if let Some(poet_state) = state.borrow().get(&0) {
-            if poet_state.current_cycle > 0 {
-                POEM_CYCLES.with(|cycles| {
-                    if let Some(mut current_cycle) = cycles.borrow().get(&poet_state.current_cycle) {
-                        current_cycle.next_prompt = next_prompt;
-                        cycles.borrow_mut().insert(poet_state.current_cycle, current_cycle);
-                        true
-                    } else {
-                        false
-                    }
-                })
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+fn grant_owner(principal: Principal) -> Result<(), PoetError> {
+    Ok(access::grant(principal, Role::Owner)?)
+}
+
+#[update]
+fn grant_role(principal: Principal, role: Role) -> Result<(), PoetError> {
+    Ok(access::grant(principal, role)?)
+}
+
+#[update]
+fn revoke_role(principal: Principal, role: Role) -> Result<(), PoetError> {
+    Ok(access::revoke(principal, role)?)
+}
+
+#[query]
+fn get_my_roles() -> Vec<Role> {
+    access::roles_of(&ic_cdk::caller())
+}
+
+#[query]
+fn list_role_grants() -> Result<Vec<RoleGrant>, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(access::list_grants())
+}
+
+// Re-run the current parsing pipeline over a stored raw response.
+// Dry run - nothing is written, useful after changing the parsers.
+#[query]
+async fn replay_cycle(poet_id: PoetId, cycle_number: u64) -> Result<PoemCycle, PoetError> {
+    access::require_role(Role::Admin)?;
+
+    let stored = get_poem_by_cycle(poet_id, cycle_number).ok_or(PoetError::CycleNotFound(cycle_number))?;
+    let replay = llm::ReplayLlm::from_cycles(std::slice::from_ref(&stored));
+    let generated = pipeline::generate_poem(
+        &replay,
+        &config::get(),
+        String::new(),
+        stored.cycle_number,
+        "Write about lost prompts",
+    ).await?;
+
+    Ok(PoemCycle {
+        poem: generated.poem.trim().to_string(),
+        title: generated.title.trim().to_string(),
+        next_prompt: generated.next_prompt.trim().to_string(),
+        generation_method: generated.method,
+        corrections: Some(generated.corrections),
+        ..stored
     })
 }
 
-// Get raw response for debugging
+// A poet's meta form - every edit is a revision that can be diffed and rolled back
 #[query]
-fn get_raw_response(cycle_number: u64) -> Option<String> {
-    POEM_CYCLES.with(|cycles| {
-        cycles.borrow()
-            .get(&cycle_number)
-            .map(|cycle| cycle.raw_response.clone())
+fn get_meta_form(poet_id: PoetId) -> Result<MetaFormRevision, PoetError> {
+    access::require_role(Role::Admin)?;
+    let poet = existing_poet(poet_id)?;
+    Ok(meta_forms::current(poet_id, &poet))
+}
+
+#[query]
+fn list_meta_form_revisions(poet_id: PoetId) -> Result<Vec<MetaFormRevision>, PoetError> {
+    access::require_role(Role::Admin)?;
+    let poet = existing_poet(poet_id)?;
+    Ok(meta_forms::revisions(poet_id, &poet))
+}
+
+// Changes from revision `from` to revision `to` (default: the current one)
+#[query]
+fn diff_meta_form(poet_id: PoetId, from: u32, to: Option<u32>) -> Result<Vec<DiffLine>, PoetError> {
+    access::require_role(Role::Admin)?;
+    let poet = existing_poet(poet_id)?;
+    let old = meta_forms::get(poet_id, &poet, from).ok_or(PoetError::RevisionNotFound(from))?;
+    let new = match to {
+        Some(to) => meta_forms::get(poet_id, &poet, to).ok_or(PoetError::RevisionNotFound(to))?,
+        None => meta_forms::current(poet_id, &poet),
+    };
+    Ok(meta_forms::diff(&old.source, &new.source))
+}
+
+// Shared by set_meta_form and rollback_meta_form
+fn update_meta_form(
+    poet_id: PoetId,
+    source: String,
+    author: Principal,
+    restored_from: Option<u32>,
+) -> Result<MetaFormRevision, PoetError> {
+    let mut poet = existing_poet(poet_id)?;
+    // The running evolution rendered the old form and records its revision
+    evolution::ensure_idle(poet_id)?;
+    let now = get_current_time();
+    let revision = meta_forms::update(poet_id, &mut poet, source, now, Some(author), restored_from)?;
+    poet.last_updated = now;
+    save_poet_state(poet_id, poet);
+    Ok(revision)
+}
+
+#[update]
+fn set_meta_form(poet_id: PoetId, source: String) -> Result<MetaFormRevision, PoetError> {
+    let caller = access::require_role(Role::Admin)?;
+    update_meta_form(poet_id, source, caller, None)
+}
+
+// Makes an old revision current again, as a new revision
+#[update]
+fn rollback_meta_form(poet_id: PoetId, revision: u32) -> Result<MetaFormRevision, PoetError> {
+    let caller = access::require_role(Role::Admin)?;
+    let poet = existing_poet(poet_id)?;
+    let old = meta_forms::get(poet_id, &poet, revision).ok_or(PoetError::RevisionNotFound(revision))?;
+    update_meta_form(poet_id, old.source, caller, Some(revision))
+}
+
+// Prompt templates - admins edit them at runtime, every edit is a new version
+#[query]
+fn get_template(name: TemplateName, version: Option<u32>) -> Result<Option<TemplateVersion>, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(match version {
+        Some(version) => templates::get(name, version),
+        None => Some(templates::current(name)),
     })
 }
 
+#[query]
+fn list_template_versions(name: TemplateName) -> Result<Vec<TemplateVersion>, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(templates::history(name))
+}
+
+#[update]
+fn set_template(name: TemplateName, source: String) -> Result<TemplateVersion, PoetError> {
+    let caller = access::require_role(Role::Admin)?;
+    let saved = templates::save(name, source, get_current_time(), Some(caller))?;
+    // Poets that never edited their meta form take the new version
+    if name == TemplateName::MetaForm {
+        meta_forms::follow_template(&saved.source);
+    }
+    Ok(saved)
+}
+
+// Model selection and correction behaviour
+#[query]
+fn get_generation_config() -> Result<GenerationConfig, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(config::get())
+}
+
+#[update]
+fn set_generation_config(generation_config: GenerationConfig) -> Result<GenerationConfig, PoetError> {
+    access::require_role(Role::Admin)?;
+    config::set(generation_config)
+}
+
+// Title, description and size of the /feed.rss and /feed.atom feeds
+#[query]
+fn get_feed_config() -> Result<FeedConfig, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(feed::get_config())
+}
+
+#[update]
+fn set_feed_config(feed_config: FeedConfig) -> Result<FeedConfig, PoetError> {
+    access::require_role(Role::Admin)?;
+    let feed_config = feed::set_config(feed_config)?;
+    certify::certify_listings();
+    Ok(feed_config)
+}
+
+// Autonomous evolution schedule
+#[query]
+fn get_schedule() -> ScheduleState {
+    scheduler::get_state()
+}
+
+#[update]
+fn set_schedule(config: ScheduleConfig) -> Result<ScheduleState, PoetError> {
+    access::require_role(Role::Admin)?;
+    scheduler::configure(config)
+}
+
+#[update]
+fn pause_schedule() -> Result<ScheduleState, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(scheduler::set_paused(true))
+}
+
+#[update]
+fn resume_schedule() -> Result<ScheduleState, PoetError> {
+    access::require_role(Role::Admin)?;
+    Ok(scheduler::set_paused(false))
+}
+
+// Get raw response for debugging
+#[query]
+fn get_raw_response(poet_id: PoetId, cycle_number: u64) -> Option<String> {
+    stored_poem(poet_id, cycle_number).map(|cycle| cycle.raw_response)
+}
+
 #[pre_upgrade]
 fn pre_upgrade() {
     // State is automatically preserved in stable memory
@@ -868,8 +1458,51 @@ fn pre_upgrade() {
 
 #[post_upgrade]
 fn post_upgrade() {
-    // State is automatically restored from stable memory
+    // State is automatically restored from stable memory, but older
+    // layouts need migrating and timers need re-arming
+    schema::run_migrations();
+    certify::rebuild();
+    scheduler::rearm();
+}
+
+// Serve the archive over HTTP (/poems/{cycle}, /poems/latest, /api/*.json, /api/themes.dot)
+#[query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    http::handle(&request)
+}
+
+// Version of the stable data layout
+#[query]
+fn get_schema_version() -> u32 {
+    schema::stored_version()
 }
 
 // Export the Candid interface
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+#[cfg(test)]
+mod tests {
+    use candid_parser::utils::{service_compatible, CandidSource};
+    use std::path::Path;
+
+    // backend.released.did is the interface clients were last given. Every
+    // dfx build rewrites backend.did, so that file cannot be the baseline;
+    // this one is only replaced by hand once a release is deployed. Fails
+    // when existing clients could no longer call us.
+    #[test]
+    fn candid_interface_stays_backward_compatible() {
+        let exported = super::__export_service();
+        let released = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/backend.released.did"));
+        service_compatible(CandidSource::Text(&exported), CandidSource::File(released))
+            .expect("the exported interface is not backward compatible with backend.released.did");
+    }
+
+    #[test]
+    fn missing_default_poet_is_not_initialized() {
+        use super::{existing_poet, PoetError};
+
+        assert_eq!(existing_poet(super::DEFAULT_POET).err(), Some(PoetError::NotInitialized));
+        assert_eq!(existing_poet(5).err(), Some(PoetError::PoetNotFound(5)));
+        super::save_poet_state(super::DEFAULT_POET, super::default_poet_state());
+        assert!(existing_poet(super::DEFAULT_POET).is_ok());
+    }
+}
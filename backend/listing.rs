@@ -0,0 +1,393 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{Memory as StableMemory, StableBTreeMap};
+use std::ops::Bound;
+
+use crate::branches::{self, BranchId};
+use crate::config::LlmModel;
+use crate::{GenerationMethod, PoemCycle, PoemKey, PoetId};
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+const EXCERPT_CHARS: usize = 140;
+// Cycles read per page at most, so a selective filter cannot walk the whole
+// archive in one query
+const MAX_SCANNED: usize = 1000;
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ListOrder {
+    Ascending,  // Oldest cycle first
+    Descending, // Newest cycle first
+}
+
+// All fields are optional; an empty filter matches every poem
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct PoemFilter {
+    pub methods: Option<Vec<GenerationMethod>>,
+    pub created_from: Option<u64>,      // Inclusive, nanoseconds
+    pub created_to: Option<u64>,        // Inclusive, nanoseconds
+    pub cycle_from: Option<u64>,        // Inclusive
+    pub cycle_to: Option<u64>,          // Inclusive
+    pub title_contains: Option<String>, // Case-insensitive
+}
+
+impl PoemFilter {
+    fn matches(&self, cycle: &PoemCycle) -> bool {
+        if let Some(methods) = &self.methods {
+            if !methods.contains(&cycle.generation_method) {
+                return false;
+            }
+        }
+        if self
+            .created_from
+            .is_some_and(|from| cycle.created_at < from)
+        {
+            return false;
+        }
+        if self.created_to.is_some_and(|to| cycle.created_at > to) {
+            return false;
+        }
+        if let Some(needle) = &self.title_contains {
+            if !cycle.title.to_lowercase().contains(&needle.to_lowercase()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// What a listing needs to show - everything but the heavy raw_response
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PoemSummary {
+    pub id: u64,
+    pub cycle_number: u64,
+    pub title: String,
+    pub excerpt: String,
+    pub line_count: u32,
+    pub next_prompt: String,
+    pub created_at: u64,
+    pub generation_method: GenerationMethod,
+    pub model: Option<LlmModel>,
+    pub parent: u64, // 0 for a first cycle
+    pub branch: BranchId,
+}
+
+impl From<&PoemCycle> for PoemSummary {
+    fn from(cycle: &PoemCycle) -> Self {
+        PoemSummary {
+            id: cycle.id,
+            cycle_number: cycle.cycle_number,
+            title: cycle.title.clone(),
+            excerpt: cycle.poem.chars().take(EXCERPT_CHARS).collect(),
+            line_count: cycle.poem.lines().count() as u32,
+            next_prompt: cycle.next_prompt.clone(),
+            created_at: cycle.created_at,
+            generation_method: cycle.generation_method.clone(),
+            model: cycle.model,
+            parent: branches::parent_of(cycle),
+            branch: branches::branch_of(cycle),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PoemPage {
+    pub items: Vec<PoemSummary>,
+    pub next_cursor: Option<u64>, // Pass back as `cursor` to get the next page
+}
+
+// One page of one poet's poems. The cursor is the last cycle number already
+// seen; the page continues strictly after it in the requested order. A page
+// is cut short, possibly to nothing, when MAX_SCANNED cycles did not fill
+// it; its cursor then continues after the last cycle read.
+pub fn list<M: StableMemory>(
+    cycles: &StableBTreeMap<PoemKey, PoemCycle, M>,
+    poet_id: PoetId,
+    cursor: Option<u64>,
+    limit: Option<u32>,
+    order: ListOrder,
+    filter: &PoemFilter,
+) -> PoemPage {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+
+    // The cycle range and the cursor both narrow the key range to scan
+    let mut lower = filter.cycle_from.map_or(Bound::Unbounded, Bound::Included);
+    let mut upper = filter.cycle_to.map_or(Bound::Unbounded, Bound::Included);
+    if let Some(cursor) = cursor {
+        match order {
+            ListOrder::Ascending => lower = tighter_lower(lower, cursor),
+            ListOrder::Descending => upper = tighter_upper(upper, cursor),
+        }
+    }
+    if is_empty_range(lower, upper) {
+        return PoemPage {
+            items: Vec::new(),
+            next_cursor: None,
+        };
+    }
+
+    let range = cycles.range(poet_bounds(poet_id, lower, upper));
+    let scanned: Box<dyn Iterator<Item = (PoemKey, PoemCycle)>> = match order {
+        ListOrder::Ascending => Box::new(range),
+        ListOrder::Descending => Box::new(range.rev()),
+    };
+
+    let mut items: Vec<PoemSummary> = Vec::new();
+    let mut last_scanned = None;
+    let mut more = false;
+    for (count, ((_, cycle_number), cycle)) in scanned.enumerate() {
+        if count == MAX_SCANNED {
+            more = true;
+            break;
+        }
+        if filter.matches(&cycle) {
+            // Only hand out a cursor if there really is something after this page
+            if items.len() == limit {
+                more = true;
+                break;
+            }
+            items.push(PoemSummary::from(&cycle));
+        }
+        last_scanned = Some(cycle_number);
+    }
+
+    let next_cursor = match items.last() {
+        _ if !more => None,
+        Some(last) if items.len() == limit => Some(last.cycle_number),
+        _ => last_scanned,
+    };
+
+    PoemPage { items, next_cursor }
+}
+
+// Cycle bounds as key bounds that never leave the poet's range
+fn poet_bounds(
+    poet_id: PoetId,
+    lower: Bound<u64>,
+    upper: Bound<u64>,
+) -> (Bound<PoemKey>, Bound<PoemKey>) {
+    let lower = match lower {
+        Bound::Unbounded => Bound::Included((poet_id, 0)),
+        bound => bound.map(|cycle| (poet_id, cycle)),
+    };
+    let upper = match upper {
+        Bound::Unbounded => Bound::Included((poet_id, u64::MAX)),
+        bound => bound.map(|cycle| (poet_id, cycle)),
+    };
+    (lower, upper)
+}
+
+fn tighter_lower(bound: Bound<u64>, cursor: u64) -> Bound<u64> {
+    match bound {
+        Bound::Included(from) if from > cursor => bound,
+        _ => Bound::Excluded(cursor),
+    }
+}
+
+fn tighter_upper(bound: Bound<u64>, cursor: u64) -> Bound<u64> {
+    match bound {
+        Bound::Included(to) if to < cursor => bound,
+        _ => Bound::Excluded(cursor),
+    }
+}
+
+fn is_empty_range(lower: Bound<u64>, upper: Bound<u64>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(l), Bound::Included(u)) => l > u,
+        (Bound::Included(l), Bound::Excluded(u)) | (Bound::Excluded(l), Bound::Included(u)) => {
+            l >= u
+        }
+        (Bound::Excluded(l), Bound::Excluded(u)) => l.saturating_add(1) >= u,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    fn archive(count: u64) -> StableBTreeMap<PoemKey, PoemCycle, DefaultMemoryImpl> {
+        let mut cycles = StableBTreeMap::init(DefaultMemoryImpl::default());
+        for n in 1..=count {
+            cycles.insert(
+                (0, n),
+                PoemCycle {
+                    poem: format!("line one of {}\nline two", n),
+                    title: if n % 2 == 0 {
+                        format!("Even Static {}", n)
+                    } else {
+                        format!("Odd Noise {}", n)
+                    },
+                    next_prompt: "Write about the next thing".to_string(),
+                    created_at: n * 100,
+                    raw_response: "x".repeat(4000),
+                    generation_method: if n % 3 == 0 {
+                        GenerationMethod::Fallback
+                    } else {
+                        GenerationMethod::Primary
+                    },
+                    ..PoemCycle::test(n)
+                },
+            );
+        }
+        cycles
+    }
+
+    fn cycle_numbers(page: &PoemPage) -> Vec<u64> {
+        page.items.iter().map(|item| item.cycle_number).collect()
+    }
+
+    #[test]
+    fn pages_ascending_until_exhausted() {
+        let cycles = archive(5);
+        let filter = PoemFilter::default();
+
+        let first = list(&cycles, 0, None, Some(2), ListOrder::Ascending, &filter);
+        assert_eq!(cycle_numbers(&first), vec![1, 2]);
+        assert_eq!(first.next_cursor, Some(2));
+
+        let second = list(
+            &cycles,
+            0,
+            first.next_cursor,
+            Some(2),
+            ListOrder::Ascending,
+            &filter,
+        );
+        assert_eq!(cycle_numbers(&second), vec![3, 4]);
+
+        let last = list(
+            &cycles,
+            0,
+            second.next_cursor,
+            Some(2),
+            ListOrder::Ascending,
+            &filter,
+        );
+        assert_eq!(cycle_numbers(&last), vec![5]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn pages_descending_from_newest() {
+        let cycles = archive(5);
+        let page = list(
+            &cycles,
+            0,
+            None,
+            Some(3),
+            ListOrder::Descending,
+            &PoemFilter::default(),
+        );
+        assert_eq!(cycle_numbers(&page), vec![5, 4, 3]);
+
+        let next = list(
+            &cycles,
+            0,
+            page.next_cursor,
+            Some(3),
+            ListOrder::Descending,
+            &PoemFilter::default(),
+        );
+        assert_eq!(cycle_numbers(&next), vec![2, 1]);
+        assert_eq!(next.next_cursor, None);
+    }
+
+    #[test]
+    fn filters_combine() {
+        let cycles = archive(12);
+        let filter = PoemFilter {
+            methods: Some(vec![GenerationMethod::Primary]),
+            cycle_from: Some(2),
+            cycle_to: Some(10),
+            created_to: Some(900),
+            title_contains: Some("even".to_string()),
+            ..PoemFilter::default()
+        };
+        let page = list(&cycles, 0, None, None, ListOrder::Ascending, &filter);
+        assert_eq!(cycle_numbers(&page), vec![2, 4, 8]);
+        assert!(page
+            .items
+            .iter()
+            .all(|item| item.excerpt.starts_with("line one")));
+    }
+
+    #[test]
+    fn exact_page_has_no_cursor() {
+        let cycles = archive(4);
+        let page = list(
+            &cycles,
+            0,
+            None,
+            Some(4),
+            ListOrder::Ascending,
+            &PoemFilter::default(),
+        );
+        assert_eq!(page.items.len(), 4);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn cursor_outside_cycle_range_is_empty() {
+        let cycles = archive(10);
+        let filter = PoemFilter {
+            cycle_to: Some(3),
+            ..PoemFilter::default()
+        };
+        let page = list(&cycles, 0, Some(3), None, ListOrder::Ascending, &filter);
+        assert!(page.items.is_empty());
+    }
+
+    #[test]
+    fn lists_only_the_requested_poet() {
+        let mut cycles = archive(3);
+        let mut other = cycles.get(&(0, 2)).unwrap();
+        other.title = "Other Poet".to_string();
+        cycles.insert((1, 1), other);
+
+        let page = list(
+            &cycles,
+            1,
+            None,
+            None,
+            ListOrder::Descending,
+            &PoemFilter::default(),
+        );
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].title, "Other Poet");
+
+        let page = list(
+            &cycles,
+            0,
+            None,
+            None,
+            ListOrder::Descending,
+            &PoemFilter::default(),
+        );
+        assert_eq!(cycle_numbers(&page), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn selective_filter_stops_after_the_scan_budget() {
+        let cycles = archive(MAX_SCANNED as u64 + 200);
+        let filter = PoemFilter {
+            title_contains: Some("Static 1100".to_string()),
+            ..PoemFilter::default()
+        };
+
+        let first = list(&cycles, 0, None, None, ListOrder::Ascending, &filter);
+        assert!(first.items.is_empty());
+        assert_eq!(first.next_cursor, Some(MAX_SCANNED as u64));
+
+        let second = list(
+            &cycles,
+            0,
+            first.next_cursor,
+            None,
+            ListOrder::Ascending,
+            &filter,
+        );
+        assert_eq!(cycle_numbers(&second), vec![1100]);
+        assert_eq!(second.next_cursor, None);
+    }
+}
@@ -0,0 +1,186 @@
+use candid::{CandidType, Principal};
+use ic_llm::{ChatMessage, Model, Response, Tool};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::error::PoetError;
+use crate::{get_current_time, PoemCycle};
+
+// The LLM canister ic_llm talks to
+const LLM_CANISTER: &str = "w36hm-eqaaa-aaaal-qr76a-cai";
+
+// Why a chat is being sent. Lets replay/mocks tell a fresh generation
+// apart from a correction pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatPurpose {
+    Generation,
+    Correction,
+}
+
+// Everything the generation pipeline needs from an LLM. Returns the
+// assistant's text content, None when the model gave nothing back, or
+// LlmUnavailable when the call itself failed.
+pub trait PoetLlm {
+    async fn chat(
+        &self,
+        purpose: ChatPurpose,
+        model: Model,
+        messages: Vec<ChatMessage>,
+    ) -> Result<Option<String>, PoetError>;
+}
+
+// Same shape as ic_llm's private request type
+#[derive(CandidType)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    tools: Option<Vec<Tool>>,
+}
+
+// Production backend - the LLM canister. Calls v1_chat directly rather than
+// through ic_llm's ChatBuilder::send, which unwraps and would trap the whole
+// evolution on a rejected call. Times every call, rejected ones included.
+#[derive(Default)]
+pub struct IcLlm {
+    latencies: RefCell<Vec<u64>>,
+}
+
+impl IcLlm {
+    // Nanoseconds each call took, in the order they were made
+    pub fn latencies(&self) -> Vec<u64> {
+        self.latencies.borrow().clone()
+    }
+}
+
+impl PoetLlm for IcLlm {
+    async fn chat(
+        &self,
+        _purpose: ChatPurpose,
+        model: Model,
+        messages: Vec<ChatMessage>,
+    ) -> Result<Option<String>, PoetError> {
+        let canister = Principal::from_text(LLM_CANISTER).expect("invalid LLM canister id");
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages,
+            tools: None,
+        };
+        let started = get_current_time();
+        let result: Result<(Response,), _> = ic_cdk::call(canister, "v1_chat", (request,)).await;
+        self.latencies
+            .borrow_mut()
+            .push(get_current_time().saturating_sub(started));
+        let (response,) = result.map_err(|(code, message)| {
+            PoetError::LlmUnavailable(format!("{:?}: {}", code, message))
+        })?;
+        Ok(response.message.content)
+    }
+}
+
+// A recorded request, kept by the scripted mock for assertions
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub struct RecordedChat {
+    pub purpose: ChatPurpose,
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+// Scripted mock: hands out canned responses in order, then None forever.
+// A failing mock rejects every call instead.
+#[cfg(test)]
+#[derive(Default)]
+pub struct ScriptedLlm {
+    responses: RefCell<VecDeque<Option<String>>>,
+    requests: RefCell<Vec<RecordedChat>>,
+    failing: bool,
+}
+
+#[cfg(test)]
+impl ScriptedLlm {
+    pub fn new<I, S>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = Option<S>>,
+        S: Into<String>,
+    {
+        ScriptedLlm {
+            responses: RefCell::new(responses.into_iter().map(|r| r.map(Into::into)).collect()),
+            requests: RefCell::new(Vec::new()),
+            failing: false,
+        }
+    }
+
+    pub fn failing() -> Self {
+        ScriptedLlm {
+            failing: true,
+            ..ScriptedLlm::default()
+        }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedChat> {
+        self.requests.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl PoetLlm for ScriptedLlm {
+    async fn chat(
+        &self,
+        purpose: ChatPurpose,
+        model: Model,
+        messages: Vec<ChatMessage>,
+    ) -> Result<Option<String>, PoetError> {
+        self.requests.borrow_mut().push(RecordedChat {
+            purpose,
+            model: model.to_string(),
+            messages,
+        });
+        if self.failing {
+            return Err(PoetError::LlmUnavailable("scripted rejection".to_string()));
+        }
+        Ok(self.responses.borrow_mut().pop_front().flatten())
+    }
+}
+
+// Replay: feeds stored raw_response values back through the pipeline, one
+// per generation request, and the recorded correction answers to correction
+// requests. Cycles from before corrections were recorded have none, so those
+// requests get nothing back and the pipeline has to cope with that.
+pub struct ReplayLlm {
+    raw_responses: RefCell<VecDeque<String>>,
+    corrections: RefCell<VecDeque<Option<String>>>,
+}
+
+impl ReplayLlm {
+    pub fn from_cycles(cycles: &[PoemCycle]) -> Self {
+        ReplayLlm {
+            raw_responses: RefCell::new(cycles.iter().map(|c| c.raw_response.clone()).collect()),
+            corrections: RefCell::new(
+                cycles
+                    .iter()
+                    .flat_map(|c| c.corrections.iter().flatten())
+                    .map(|attempt| attempt.response.clone())
+                    .collect(),
+            ),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn remaining(&self) -> usize {
+        self.raw_responses.borrow().len()
+    }
+}
+
+impl PoetLlm for ReplayLlm {
+    async fn chat(
+        &self,
+        purpose: ChatPurpose,
+        _model: Model,
+        _messages: Vec<ChatMessage>,
+    ) -> Result<Option<String>, PoetError> {
+        Ok(match purpose {
+            ChatPurpose::Generation => self.raw_responses.borrow_mut().pop_front(),
+            ChatPurpose::Correction => self.corrections.borrow_mut().pop_front().flatten(),
+        })
+    }
+}
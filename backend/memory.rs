@@ -0,0 +1,574 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+use crate::branches::{self, BranchId};
+use crate::schema::{self, Versioned};
+use crate::tokenizer;
+use crate::{
+    get_current_time, stored_poem, Memory, PoemCycle, PoetId, ERAS_MEMORY_ID,
+    LEGACY_ERAS_MEMORY_ID, MEMORY_MANAGER,
+};
+
+// Long-term memory for the REFLECTION section. Besides the previous poem the
+// poet is reminded of its most recent cycles, of older poems that share words
+// with the current theme, and of summaries of whole eras, all within a token
+// budget. Everything follows the current branch's ancestry.
+
+const EXCERPT_LINES: usize = 3;
+const ERA_KEYWORDS: usize = 10;
+const ERA_TITLES: usize = 6;
+// Titles and keywords can be whole unbroken lines, e.g. CJK without spaces;
+// cut short, every era fits EraSummary's bound
+const ERA_TEXT_CHARS: usize = 80;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MemoryOptions {
+    pub recent_window: u32, // Ancestors recalled regardless of theme
+    pub search_window: u32, // Ancestors searched for shared keywords
+    pub token_budget: u32,  // Upper bound for the whole memory block
+    pub era_length: u32,    // Cycles per stored era summary
+}
+
+impl Default for MemoryOptions {
+    fn default() -> Self {
+        MemoryOptions {
+            recent_window: 3,
+            search_window: 200,
+            token_budget: 600,
+            era_length: 20,
+        }
+    }
+}
+
+// Keywords and titles of a run of consecutive cycles on one branch, so old
+// poems need not be re-read
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct EraSummary {
+    pub branch: BranchId,
+    pub first_cycle: u64,
+    pub last_cycle: u64,
+    pub poem_count: u32,
+    pub keywords: Vec<String>, // Most frequent first
+    pub titles: Vec<String>,
+    pub created_at: u64,
+}
+
+impl Storable for EraSummary {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 8192,
+        is_fixed_size: false,
+    };
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+// Version 1 eras spanned cycle ranges across branches; they were dropped
+// (schema v5) rather than migrated and get summarized again
+impl Versioned for EraSummary {
+    const VERSION: u16 = 2;
+}
+
+thread_local! {
+    // Keyed by poet, branch and the era's first cycle
+    static ERAS: RefCell<StableBTreeMap<(PoetId, BranchId, u64), EraSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ERAS_MEMORY_ID)),
+        )
+    );
+}
+
+fn branch_range(poet_id: PoetId, branch: BranchId) -> RangeInclusive<(PoetId, BranchId, u64)> {
+    (poet_id, branch, 0)..=(poet_id, branch, u64::MAX)
+}
+
+fn poet_range(poet_id: PoetId) -> RangeInclusive<(PoetId, BranchId, u64)> {
+    (poet_id, BranchId::MIN, 0)..=(poet_id, BranchId::MAX, u64::MAX)
+}
+
+// `cycles` are consecutive cycles of `branch`, oldest first
+pub fn summarize_era(branch: BranchId, cycles: &[PoemCycle]) -> EraSummary {
+    let text: String = cycles
+        .iter()
+        .map(|cycle| format!("{}\n{}\n", cycle.title, cycle.poem))
+        .collect();
+    let mut counts: Vec<(String, usize)> = tokenizer::keyword_counts(&text).into_iter().collect();
+    // Most frequent first, alphabetical among equals so summaries are stable
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    EraSummary {
+        branch,
+        first_cycle: cycles.first().map_or(0, |cycle| cycle.cycle_number),
+        last_cycle: cycles.last().map_or(0, |cycle| cycle.cycle_number),
+        poem_count: cycles.len() as u32,
+        keywords: counts
+            .into_iter()
+            .take(ERA_KEYWORDS)
+            .map(|(word, _)| word.chars().take(ERA_TEXT_CHARS).collect())
+            .collect(),
+        titles: cycles
+            .iter()
+            .take(ERA_TITLES)
+            .map(|cycle| cycle.title.chars().take(ERA_TEXT_CHARS).collect())
+            .collect(),
+        created_at: get_current_time(),
+    }
+}
+
+// Summarize every complete era of `head`'s branch that has no summary yet.
+// Only the cycles since the branch's last era are read. Summaries of another
+// era length are dropped and rebuilt.
+pub fn record_eras(poet_id: PoetId, head: &PoemCycle, options: &MemoryOptions) {
+    let era_length = options.era_length.max(1) as usize;
+    let branch = branches::branch_of(head);
+    ERAS.with(|eras| {
+        let mut eras = eras.borrow_mut();
+        let stale: Vec<_> = eras
+            .range(branch_range(poet_id, branch))
+            .filter(|(_, era)| era.poem_count as usize != era_length)
+            .map(|(key, _)| key)
+            .collect();
+        for key in stale {
+            eras.remove(&key);
+        }
+        let summarized = eras
+            .range(branch_range(poet_id, branch))
+            .next_back()
+            .map_or(0, |(_, era)| era.last_cycle);
+
+        // Back from the head while still on the branch and not yet summarized
+        let mut pending = Vec::new();
+        let mut next = Some(head.clone());
+        while let Some(cycle) =
+            next.filter(|c| branches::branch_of(c) == branch && c.cycle_number > summarized)
+        {
+            let parent = branches::parent_of(&cycle);
+            next = if parent > 0 && parent < cycle.cycle_number {
+                stored_poem(poet_id, parent)
+            } else {
+                None
+            };
+            pending.push(cycle);
+        }
+        pending.reverse();
+
+        for era in pending.chunks_exact(era_length) {
+            eras.insert(
+                (poet_id, branch, era[0].cycle_number),
+                summarize_era(branch, era),
+            );
+        }
+    });
+}
+
+// The eras `head`'s line went through: those of its own branch up to it, then
+// those of the branch it forked from up to the fork point, and so on. Oldest first.
+fn line_eras(poet_id: PoetId, head: &PoemCycle) -> Vec<EraSummary> {
+    let mut line = Vec::new();
+    let mut at = Some(head.clone());
+    while let Some(cycle) = at {
+        let branch = branches::branch_of(&cycle);
+        ERAS.with(|eras| {
+            line.extend(
+                eras.borrow()
+                    .range(branch_range(poet_id, branch))
+                    .map(|(_, era)| era)
+                    .filter(|era| era.last_cycle <= cycle.cycle_number),
+            )
+        });
+        at = branches::get(poet_id, branch)
+            .filter(|fork| fork.forked_from > 0 && fork.forked_from < cycle.cycle_number)
+            .and_then(|fork| stored_poem(poet_id, fork.forked_from));
+    }
+    line.sort_by_key(|era| era.first_cycle);
+    line
+}
+
+// A poet's era summaries, by branch and oldest first within each
+pub fn eras(poet_id: PoetId) -> Vec<EraSummary> {
+    ERAS.with(|eras| {
+        eras.borrow()
+            .range(poet_range(poet_id))
+            .map(|(_, era)| era)
+            .collect()
+    })
+}
+
+// Forget a poet's era summaries (reset)
+pub fn clear(poet_id: PoetId) {
+    ERAS.with(|eras| {
+        let mut eras = eras.borrow_mut();
+        let keys: Vec<_> = eras
+            .range(poet_range(poet_id))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            eras.remove(&key);
+        }
+    });
+}
+
+// Empty the cycle-range eras of schema v4 and before (schema migration).
+// Each branch summarizes its eras again on its next evolution.
+pub fn drop_legacy_eras() {
+    let _: StableBTreeMap<(PoetId, u64), EraSummary, Memory> =
+        StableBTreeMap::new(MEMORY_MANAGER.with(|m| m.borrow().get(LEGACY_ERAS_MEMORY_ID)));
+}
+
+#[derive(Debug, PartialEq)]
+enum Recollection {
+    Recent(String),
+    Related(String),
+    Era(String),
+}
+
+fn excerpt(poem: &str) -> String {
+    poem.lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(EXCERPT_LINES)
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+fn render_recent(cycle: &PoemCycle) -> String {
+    format!(
+        "- Cycle {} \"{}\": {}",
+        cycle.cycle_number,
+        cycle.title,
+        excerpt(&cycle.poem)
+    )
+}
+
+fn render_related(cycle: &PoemCycle, shared: &[&String]) -> String {
+    let shared: Vec<&str> = shared.iter().map(|word| word.as_str()).collect();
+    format!(
+        "- Cycle {} \"{}\" (also about {}): {}",
+        cycle.cycle_number,
+        cycle.title,
+        shared.join(", "),
+        excerpt(&cycle.poem)
+    )
+}
+
+fn render_era(era: &EraSummary) -> String {
+    format!(
+        "- Cycles {}-{}: kept returning to {}; titles included {}",
+        era.first_cycle,
+        era.last_cycle,
+        era.keywords.join(", "),
+        era.titles.join(" / ")
+    )
+}
+
+// Pick what to remember. `line` is the ancestry before the previous poem,
+// newest first; `eras` are the summaries along that line, oldest first.
+fn select(
+    current_prompt: &str,
+    line: &[PoemCycle],
+    eras: &[EraSummary],
+    options: &MemoryOptions,
+) -> Vec<Recollection> {
+    let recent_window = (options.recent_window as usize).min(line.len());
+    let search_window = (options.search_window as usize).min(line.len());
+    let (recent, older) = line.split_at(recent_window);
+    let searched = &older[..search_window.saturating_sub(recent_window)];
+
+    // Older poems ranked by how much of the current theme they share
+    let theme = tokenizer::keywords(current_prompt);
+    let mut related: Vec<(usize, &PoemCycle, Vec<&String>)> = searched
+        .iter()
+        .filter_map(|cycle| {
+            let words: BTreeSet<String> = tokenizer::keywords(&format!(
+                "{} {} {}",
+                cycle.title, cycle.poem, cycle.next_prompt
+            ));
+            let shared: Vec<&String> = theme.iter().filter(|word| words.contains(*word)).collect();
+            (!shared.is_empty()).then_some((shared.len(), cycle, shared))
+        })
+        .collect();
+    // Stable sort: newer poems win ties
+    related.sort_by_key(|(score, _, _)| Reverse(*score));
+
+    // Eras entirely older than anything searched directly
+    let oldest_searched = line[..search_window]
+        .last()
+        .map_or(0, |cycle| cycle.cycle_number);
+    let old_eras = eras
+        .iter()
+        .rev()
+        .filter(|era| era.last_cycle < oldest_searched);
+
+    let candidates = recent
+        .iter()
+        .map(|cycle| Recollection::Recent(render_recent(cycle)))
+        .chain(
+            related
+                .iter()
+                .map(|(_, cycle, shared)| Recollection::Related(render_related(cycle, shared))),
+        )
+        .chain(old_eras.map(|era| Recollection::Era(render_era(era))));
+
+    // Greedy fill in priority order; something too long is skipped, not cut
+    let mut budget = options.token_budget as usize;
+    let mut chosen = Vec::new();
+    for candidate in candidates {
+        let text = match &candidate {
+            Recollection::Recent(text) | Recollection::Related(text) | Recollection::Era(text) => {
+                text
+            }
+        };
+        let cost = tokenizer::estimate_tokens(text);
+        if cost <= budget {
+            budget -= cost;
+            chosen.push(candidate);
+        }
+    }
+    chosen
+}
+
+fn render(chosen: &[Recollection]) -> String {
+    let section = |heading: &str, texts: Vec<&String>| {
+        if texts.is_empty() {
+            String::new()
+        } else {
+            let lines: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+            format!("{}\n{}\n\n", heading, lines.join("\n"))
+        }
+    };
+    let eras = chosen
+        .iter()
+        .filter_map(|r| {
+            if let Recollection::Era(t) = r {
+                Some(t)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let related = chosen
+        .iter()
+        .filter_map(|r| {
+            if let Recollection::Related(t) = r {
+                Some(t)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let recent = chosen
+        .iter()
+        .filter_map(|r| {
+            if let Recollection::Recent(t) = r {
+                Some(t)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut block = String::new();
+    block.push_str(&section("LONG AGO:", eras));
+    block.push_str(&section("EARLIER POEMS ON THIS THEME:", related));
+    block.push_str(&section("RECENT POEMS:", recent));
+    block.trim_end().to_string()
+}
+
+// The memory block for a cycle continuing `previous`, empty if there is
+// nothing worth recalling
+pub fn recall(
+    poet_id: PoetId,
+    current_prompt: &str,
+    previous: &PoemCycle,
+    options: &MemoryOptions,
+) -> String {
+    let parent = branches::parent_of(previous);
+    let line = branches::ancestry(parent, options.search_window as usize, |n| {
+        stored_poem(poet_id, n)
+    });
+    render(&select(
+        current_prompt,
+        &line,
+        &line_eras(poet_id, previous),
+        options,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(n: u64, title: &str, poem: &str) -> PoemCycle {
+        PoemCycle {
+            poem: poem.to_string(),
+            title: title.to_string(),
+            ..PoemCycle::test(n)
+        }
+    }
+
+    // Newest first, as branches::ancestry returns it
+    fn line() -> Vec<PoemCycle> {
+        vec![
+            cycle(9, "Static", "radio hiss"),
+            cycle(8, "Kettle", "steam at dawn"),
+            cycle(7, "Salt", "the sea keeps nothing"),
+            cycle(6, "Harbour Rust", "rust eats the harbour cranes"),
+            cycle(5, "Moths", "moths at the porch light"),
+            cycle(4, "Tides", "the sea and its rust coloured tides"),
+        ]
+    }
+
+    #[test]
+    fn recent_then_theme_matches_best_first() {
+        let options = MemoryOptions {
+            recent_window: 2,
+            ..MemoryOptions::default()
+        };
+        let chosen = select("Write about rust and the sea", &line(), &[], &options);
+
+        assert!(matches!(&chosen[0], Recollection::Recent(t) if t.contains("Cycle 9")));
+        assert!(matches!(&chosen[1], Recollection::Recent(t) if t.contains("Cycle 8")));
+        // Cycle 4 shares both words, so it outranks the newer single matches
+        assert!(
+            matches!(&chosen[2], Recollection::Related(t) if t.contains("Cycle 4") && t.contains("rust, sea"))
+        );
+        assert_eq!(chosen.len(), 5);
+        assert!(!chosen
+            .iter()
+            .any(|r| matches!(r, Recollection::Related(t) if t.contains("Moths"))));
+    }
+
+    #[test]
+    fn token_budget_is_respected() {
+        let options = MemoryOptions {
+            recent_window: 6,
+            token_budget: 20,
+            ..MemoryOptions::default()
+        };
+        let chosen = select("Write about rust", &line(), &[], &options);
+        let used: usize = chosen
+            .iter()
+            .map(|r| match r {
+                Recollection::Recent(t) | Recollection::Related(t) | Recollection::Era(t) => {
+                    tokenizer::estimate_tokens(t)
+                }
+            })
+            .sum();
+        assert!(used <= 20);
+        assert!(!chosen.is_empty() && chosen.len() < 6);
+    }
+
+    #[test]
+    fn eras_cover_what_the_search_window_misses() {
+        let era = summarize_era(
+            branches::MAIN_BRANCH,
+            &[
+                cycle(1, "Rust One", "rust rust winter"),
+                cycle(2, "Rust Two", "winter rust"),
+                cycle(3, "Glass", "ice"),
+            ],
+        );
+        assert_eq!(
+            era.keywords[..2],
+            ["rust".to_string(), "winter".to_string()]
+        );
+
+        let options = MemoryOptions {
+            recent_window: 1,
+            search_window: 2,
+            ..MemoryOptions::default()
+        };
+        let rendered = render(&select("Write about glass", &line(), &[era], &options));
+        assert!(rendered.starts_with("LONG AGO:\n- Cycles 1-3: kept returning to rust, winter"));
+        assert!(rendered.contains("RECENT POEMS:\n- Cycle 9 \"Static\": radio hiss"));
+    }
+
+    #[test]
+    fn complete_eras_are_recorded_once_per_branch() {
+        let store = |cycle: PoemCycle| {
+            crate::POEM_CYCLES
+                .with(|cycles| cycles.borrow_mut().insert((2, cycle.cycle_number), cycle))
+        };
+        for n in 1..=7 {
+            store(cycle(n, &format!("Title {}", n), "ash and ember"));
+        }
+        let options = MemoryOptions {
+            era_length: 3,
+            ..MemoryOptions::default()
+        };
+        record_eras(2, &cycle(7, "Title 7", "ash and ember"), &options);
+
+        let span = |eras: &[EraSummary]| {
+            eras.iter()
+                .map(|e| (e.first_cycle, e.last_cycle))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(span(&eras(2)), vec![(1, 3), (4, 6)]);
+        assert_eq!(eras(2)[1].titles, vec!["Title 4", "Title 5", "Title 6"]);
+
+        // A fork from cycle 4 has eras of its own and inherits only those before it
+        let fork = branches::create(
+            2,
+            branches::Branch {
+                forked_from: 4,
+                prompt: "Write about smoke".to_string(),
+                created_at: 0,
+            },
+        );
+        for n in 8..=10 {
+            let parent = if n == 8 { 4 } else { n - 1 };
+            store(PoemCycle {
+                parent: Some(parent),
+                branch: Some(fork),
+                ..cycle(n, &format!("Title {}", n), "smoke")
+            });
+        }
+        let fork_head = stored_poem(2, 10).unwrap();
+        record_eras(2, &fork_head, &options);
+        assert_eq!(span(&eras(2)), vec![(1, 3), (4, 6), (8, 10)]);
+        assert_eq!(span(&line_eras(2, &fork_head)), vec![(1, 3), (8, 10)]);
+        assert_eq!(
+            span(&line_eras(2, &stored_poem(2, 7).unwrap())),
+            vec![(1, 3), (4, 6)]
+        );
+
+        // Another era length only rebuilds the branch being recorded
+        record_eras(
+            2,
+            &stored_poem(2, 7).unwrap(),
+            &MemoryOptions {
+                era_length: 5,
+                ..options
+            },
+        );
+        assert_eq!(span(&eras(2)), vec![(1, 5), (8, 10)]);
+    }
+
+    #[test]
+    fn eras_of_long_titles_can_be_stored() {
+        let title = "詩".repeat(5000);
+        let cycles: Vec<PoemCycle> = (1..=ERA_TITLES as u64 + 2)
+            .map(|n| cycle(n, &title, &title))
+            .collect();
+        let era = summarize_era(branches::MAIN_BRANCH, &cycles);
+        assert!(era
+            .titles
+            .iter()
+            .all(|t| t.chars().count() == ERA_TEXT_CHARS));
+
+        ERAS.with(|eras| {
+            eras.borrow_mut()
+                .insert((1, branches::MAIN_BRANCH, 1), era.clone())
+        });
+        let stored = ERAS.with(|eras| eras.borrow().get(&(1, branches::MAIN_BRANCH, 1)));
+        assert_eq!(stored, Some(era));
+    }
+}
@@ -0,0 +1,346 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::error::PoetError;
+use crate::schema::{self, Versioned};
+use crate::templates::{self, TemplateName};
+use crate::{Memory, PoetId, PoetState, MEMORY_MANAGER, META_FORMS_MEMORY_ID};
+
+// PoetState.meta_form is the template every prompt of that poet is rendered
+// from. Each accepted edit is kept as a numbered revision so it can be
+// compared with and rolled back to. Revision 0 is the form the poet started
+// with; it is only stored once the form is first edited. Until then the
+// poet's form is a copy of the global MetaForm template, which set_template
+// keeps in step (see follow_template); edited forms are left alone.
+
+// Larger inputs are shown as wholly replaced rather than diffed line by line
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct MetaFormRevision {
+    pub revision: u32,
+    pub source: String,
+    pub created_at: u64, // 0 for the form the poet started with
+    pub author: Option<Principal>,
+    pub restored_from: Option<u32>, // Set when a rollback created this revision
+}
+
+impl Storable for MetaFormRevision {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for MetaFormRevision {
+    const VERSION: u16 = 1;
+}
+
+thread_local! {
+    static META_FORMS: RefCell<StableBTreeMap<(PoetId, u32), MetaFormRevision, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(META_FORMS_MEMORY_ID)),
+        )
+    );
+}
+
+fn original(poet: &PoetState) -> MetaFormRevision {
+    MetaFormRevision {
+        revision: 0,
+        source: poet.meta_form.clone(),
+        created_at: 0,
+        author: None,
+        restored_from: None,
+    }
+}
+
+fn newest(poet_id: PoetId) -> Option<MetaFormRevision> {
+    META_FORMS.with(|forms| {
+        forms
+            .borrow()
+            .range((poet_id, 0)..=(poet_id, u32::MAX))
+            .next_back()
+            .map(|(_, revision)| revision)
+    })
+}
+
+// The revision poet.meta_form holds
+pub fn current(poet_id: PoetId, poet: &PoetState) -> MetaFormRevision {
+    newest(poet_id).unwrap_or_else(|| original(poet))
+}
+
+pub fn get(poet_id: PoetId, poet: &PoetState, revision: u32) -> Option<MetaFormRevision> {
+    match META_FORMS.with(|forms| forms.borrow().get(&(poet_id, revision))) {
+        None if revision == 0 => Some(original(poet)),
+        found => found,
+    }
+}
+
+// Oldest first
+pub fn revisions(poet_id: PoetId, poet: &PoetState) -> Vec<MetaFormRevision> {
+    let stored: Vec<MetaFormRevision> = META_FORMS.with(|forms| {
+        forms
+            .borrow()
+            .range((poet_id, 0)..=(poet_id, u32::MAX))
+            .map(|(_, revision)| revision)
+            .collect()
+    });
+    if stored.is_empty() {
+        vec![original(poet)]
+    } else {
+        stored
+    }
+}
+
+// Validate `source` and make it the poet's newest revision. The caller saves
+// the updated PoetState.
+pub fn update(
+    poet_id: PoetId,
+    poet: &mut PoetState,
+    source: String,
+    now: u64,
+    author: Option<Principal>,
+    restored_from: Option<u32>,
+) -> Result<MetaFormRevision, PoetError> {
+    templates::validate(TemplateName::MetaForm, &source)?;
+    let previous = current(poet_id, poet);
+    let revision = MetaFormRevision {
+        revision: previous.revision + 1,
+        source,
+        created_at: now,
+        author,
+        restored_from,
+    };
+    META_FORMS.with(|forms| {
+        let mut forms = forms.borrow_mut();
+        if previous.revision == 0 {
+            forms.insert((poet_id, 0), previous);
+        }
+        forms.insert((poet_id, revision.revision), revision.clone());
+    });
+    poet.meta_form = revision.source.clone();
+    Ok(revision)
+}
+
+// A new MetaForm template version (set_template) becomes the form of every
+// poet that never edited its own
+pub fn follow_template(source: &str) {
+    for poet_id in crate::poet_ids() {
+        if newest(poet_id).is_some() {
+            continue;
+        }
+        if let Some(mut poet) = crate::poet_state(poet_id) {
+            poet.meta_form = source.to_string();
+            crate::save_poet_state(poet_id, poet);
+        }
+    }
+}
+
+// Meta forms written before they were templates are plain text with
+// {CYCLE_NUMBER}-style placeholders and the first cycle's reflection baked
+// in. They were never read, so they are replaced by the current template.
+pub fn replace_legacy_forms() {
+    let source = templates::current(TemplateName::MetaForm).source;
+    for poet_id in crate::poet_ids() {
+        if let Some(mut poet) = crate::poet_state(poet_id) {
+            if templates::validate(TemplateName::MetaForm, &poet.meta_form).is_err() {
+                poet.meta_form = source.clone();
+                crate::save_poet_state(poet_id, poet);
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+// Line diff from `old` to `new` along a longest common subsequence
+pub fn diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Common prefix and suffix need no table
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines: Vec<DiffLine> = old[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line.to_string()))
+        .collect();
+    if (a.len() + 1) * (b.len() + 1) > MAX_DIFF_CELLS {
+        lines.extend(a.iter().map(|line| DiffLine::Removed(line.to_string())));
+        lines.extend(b.iter().map(|line| DiffLine::Added(line.to_string())));
+    } else {
+        // lcs[i][j] = common lines of a[i..] and b[j..]
+        let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push(DiffLine::Same(a[i].to_string()));
+                i += 1;
+                j += 1;
+            } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                lines.push(DiffLine::Added(b[j].to_string()));
+                j += 1;
+            } else {
+                lines.push(DiffLine::Removed(a[i].to_string()));
+                i += 1;
+            }
+        }
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Same(line.to_string())),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poet(meta_form: &str) -> PoetState {
+        PoetState {
+            name: "test".to_string(),
+            model: None,
+            current_cycle: 0,
+            total_poems: 0,
+            genesis_prompt: "Write about rust".to_string(),
+            meta_form: meta_form.to_string(),
+            last_updated: 0,
+            active_branch: None,
+            persona: None,
+        }
+    }
+
+    #[test]
+    fn diff_marks_added_and_removed_lines() {
+        let diff = diff("a\nb\nc\nd", "a\nc\nx\nd");
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("a".to_string()),
+                DiffLine::Removed("b".to_string()),
+                DiffLine::Same("c".to_string()),
+                DiffLine::Added("x".to_string()),
+                DiffLine::Same("d".to_string()),
+            ]
+        );
+        assert!(super::diff("same", "same")
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_))));
+    }
+
+    #[test]
+    fn edits_keep_the_original_as_revision_zero() {
+        let mut state = poet("Theme: {{current_prompt}}");
+        assert_eq!(current(3, &state).revision, 0);
+
+        assert!(update(3, &mut state, "No prompt here".to_string(), 5, None, None).is_err());
+        assert!(update(
+            3,
+            &mut state,
+            "Cycle {CYCLE_NUMBER}: {{current_prompt}}".to_string(),
+            5,
+            None,
+            None
+        )
+        .is_err());
+        assert_eq!(state.meta_form, "Theme: {{current_prompt}}");
+
+        let edited = update(
+            3,
+            &mut state,
+            "Write on: {{current_prompt}}".to_string(),
+            5,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            (edited.revision, state.meta_form.as_str()),
+            (1, "Write on: {{current_prompt}}")
+        );
+
+        let restored = get(3, &state, 0).unwrap();
+        let rolled_back = update(3, &mut state, restored.source, 6, None, Some(0)).unwrap();
+        assert_eq!(
+            (rolled_back.revision, rolled_back.restored_from),
+            (2, Some(0))
+        );
+        assert_eq!(state.meta_form, "Theme: {{current_prompt}}");
+        assert_eq!(revisions(3, &state).len(), 3);
+    }
+
+    #[test]
+    fn only_unedited_poets_follow_the_template() {
+        let mut edited = poet("Theme: {{current_prompt}}");
+        update(
+            4,
+            &mut edited,
+            "Write on: {{current_prompt}}".to_string(),
+            5,
+            None,
+            None,
+        )
+        .unwrap();
+        crate::save_poet_state(4, edited);
+        crate::save_poet_state(5, poet("Theme: {{current_prompt}}"));
+
+        follow_template("Follow: {{current_prompt}}");
+
+        let unedited = crate::poet_state(5).unwrap();
+        assert_eq!(unedited.meta_form, "Follow: {{current_prompt}}");
+        assert_eq!(current(5, &unedited).revision, 0);
+        let edited = crate::poet_state(4).unwrap();
+        assert_eq!(edited.meta_form, "Write on: {{current_prompt}}");
+        assert_eq!(current(4, &edited).source, edited.meta_form);
+    }
+
+    #[test]
+    fn legacy_forms_become_the_template() {
+        crate::save_poet_state(
+            8,
+            poet("Cycle: {CYCLE_NUMBER}\nYOUR THEME: {CURRENT_PROMPT}"),
+        );
+        crate::save_poet_state(9, poet("Theme: {{current_prompt}}"));
+
+        replace_legacy_forms();
+
+        let template = templates::current(TemplateName::MetaForm).source;
+        assert_eq!(crate::poet_state(8).unwrap().meta_form, template);
+        assert_eq!(
+            crate::poet_state(9).unwrap().meta_form,
+            "Theme: {{current_prompt}}"
+        );
+    }
+}
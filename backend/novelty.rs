@@ -0,0 +1,419 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::schema::{self, Versioned};
+use crate::tokenizer;
+use crate::{
+    poet_range, Memory, PoemCycle, PoetId, MEMORY_MANAGER, POEM_CYCLES, SIGNATURES_MEMORY_ID,
+};
+
+// Lexical similarity between poems. Each cycle gets two MinHash signatures:
+// one over word 3-shingles of the poem, one over the keywords of its title
+// and next_prompt (its theme). The share of equal signature slots estimates
+// the Jaccard similarity of the underlying sets.
+
+const SIGNATURE_LEN: usize = 64;
+const POEM_SHINGLE_WORDS: usize = 3;
+
+// Locality-sensitive hashing for near-duplicate search: pairs that agree on a
+// whole band of rows become candidates, and only those are compared
+const LSH_BANDS: usize = 16;
+const LSH_ROWS: usize = SIGNATURE_LEN / LSH_BANDS;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct NoveltyOptions {
+    pub window: u32,       // Ancestors a new poem is compared with
+    pub threshold: f32,    // Similarity at which a poem counts as a repeat
+    pub max_reprompts: u8, // Extra attempts when a poem repeats, 0 = only score it
+}
+
+impl Default for NoveltyOptions {
+    fn default() -> Self {
+        NoveltyOptions {
+            window: 10,
+            threshold: 0.6,
+            max_reprompts: 1,
+        }
+    }
+}
+
+// Stored on each cycle
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Novelty {
+    pub score: f32, // 1 - highest poem similarity; 1.0 = like nothing recent
+    pub closest_cycle: Option<u64>,
+    pub theme_similarity: f32, // Highest title/next_prompt similarity
+    pub reprompts: u8,         // Attempts thrown away for repeating
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub poem: Vec<u64>,
+    pub theme: Vec<u64>,
+}
+
+impl Storable for Signature {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for Signature {
+    const VERSION: u16 = 1;
+}
+
+thread_local! {
+    // One per cycle: stored by the evolution that wrote it, backfilled for
+    // older cycles by the v6 migration
+    static SIGNATURES: RefCell<StableBTreeMap<(PoetId, u64), Signature, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SIGNATURES_MEMORY_ID)),
+        )
+    );
+}
+
+// FNV-1a: stable across Rust releases, unlike std's hashers, so stored
+// signatures stay comparable
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// splitmix64, used to derive one hash function per signature slot
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn minhash(shingles: &BTreeSet<String>) -> Vec<u64> {
+    let hashes: Vec<u64> = shingles.iter().map(|s| fnv1a(s)).collect();
+    (0..SIGNATURE_LEN as u64)
+        .map(|slot| {
+            let seed = mix(slot);
+            hashes
+                .iter()
+                .map(|h| mix(h ^ seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+fn poem_shingles(poem: &str) -> BTreeSet<String> {
+    let words: Vec<String> = tokenizer::words(poem).collect();
+    if words.len() < POEM_SHINGLE_WORDS {
+        return words.into_iter().collect();
+    }
+    words
+        .windows(POEM_SHINGLE_WORDS)
+        .map(|w| w.join(" "))
+        .collect()
+}
+
+pub fn signature_of(poem: &str, title: &str, next_prompt: &str) -> Signature {
+    Signature {
+        poem: minhash(&poem_shingles(poem)),
+        theme: minhash(&tokenizer::keywords(&format!("{} {}", title, next_prompt))),
+    }
+}
+
+// Estimated Jaccard similarity, 0.0 when either side is empty
+pub fn similarity(a: &[u64], b: &[u64]) -> f32 {
+    if a.is_empty() || a.len() != b.len() || a[0] == u64::MAX || b[0] == u64::MAX {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f32 / a.len() as f32
+}
+
+// A recent poem a new one is compared with
+pub struct Reference {
+    pub cycle_number: u64,
+    pub title: String,
+    pub signature: Signature,
+}
+
+pub fn assess(signature: &Signature, recent: &[Reference]) -> Novelty {
+    let mut novelty = Novelty {
+        score: 1.0,
+        closest_cycle: None,
+        theme_similarity: 0.0,
+        reprompts: 0,
+    };
+    for reference in recent {
+        let poem = similarity(&signature.poem, &reference.signature.poem);
+        if 1.0 - poem < novelty.score {
+            novelty.score = 1.0 - poem;
+            novelty.closest_cycle = Some(reference.cycle_number);
+        }
+        novelty.theme_similarity = novelty
+            .theme_similarity
+            .max(similarity(&signature.theme, &reference.signature.theme));
+    }
+    novelty
+}
+
+pub fn stored_signature(poet_id: PoetId, cycle: &PoemCycle) -> Signature {
+    SIGNATURES
+        .with(|signatures| signatures.borrow().get(&(poet_id, cycle.cycle_number)))
+        .unwrap_or_else(|| signature_of(&cycle.poem, &cycle.title, &cycle.next_prompt))
+}
+
+pub fn store_signature(poet_id: PoetId, cycle_number: u64, signature: Signature) {
+    SIGNATURES.with(|signatures| {
+        signatures
+            .borrow_mut()
+            .insert((poet_id, cycle_number), signature);
+    });
+}
+
+// Forget a poet's signatures (reset)
+pub fn clear(poet_id: PoetId) {
+    SIGNATURES.with(|signatures| {
+        let mut signatures = signatures.borrow_mut();
+        let keys: Vec<_> = signatures
+            .range(poet_range(poet_id))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            signatures.remove(&key);
+        }
+    });
+}
+
+pub fn references(poet_id: PoetId, cycles: &[PoemCycle]) -> Vec<Reference> {
+    cycles
+        .iter()
+        .map(|cycle| Reference {
+            cycle_number: cycle.cycle_number,
+            title: cycle.title.clone(),
+            signature: stored_signature(poet_id, cycle),
+        })
+        .collect()
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct NearDuplicate {
+    pub first_cycle: u64,
+    pub second_cycle: u64,
+    pub similarity: f32,
+}
+
+// Every pair of a poet's poems at least `threshold` alike, most similar first.
+// Reads stored signatures only, never the cycles themselves.
+pub fn near_duplicates(poet_id: PoetId, threshold: f32) -> Vec<NearDuplicate> {
+    let signatures: Vec<(u64, Signature)> = SIGNATURES.with(|signatures| {
+        signatures
+            .borrow()
+            .range(poet_range(poet_id))
+            .map(|((_, cycle_number), signature)| (cycle_number, signature))
+            .collect()
+    });
+    find_pairs(&signatures, threshold)
+}
+
+// Sign every cycle written before signatures were stored (schema migration)
+pub fn backfill_signatures() {
+    POEM_CYCLES.with(|cycles| {
+        SIGNATURES.with(|signatures| {
+            let mut signatures = signatures.borrow_mut();
+            for (key, cycle) in cycles.borrow().iter() {
+                if !signatures.contains_key(&key) {
+                    signatures.insert(
+                        key,
+                        signature_of(&cycle.poem, &cycle.title, &cycle.next_prompt),
+                    );
+                }
+            }
+        })
+    });
+}
+
+fn find_pairs(signatures: &[(u64, Signature)], threshold: f32) -> Vec<NearDuplicate> {
+    let mut buckets: BTreeMap<(usize, u64), Vec<usize>> = BTreeMap::new();
+    for (index, (_, signature)) in signatures.iter().enumerate() {
+        if signature.poem.len() != SIGNATURE_LEN {
+            continue;
+        }
+        for (band, rows) in signature.poem.chunks(LSH_ROWS).enumerate() {
+            let key = rows.iter().fold(0u64, |acc, row| mix(acc ^ row));
+            buckets.entry((band, key)).or_default().push(index);
+        }
+    }
+
+    let mut candidates = BTreeSet::new();
+    for members in buckets.values() {
+        for (i, &a) in members.iter().enumerate() {
+            for &b in &members[i + 1..] {
+                candidates.insert((a, b));
+            }
+        }
+    }
+
+    let mut pairs: Vec<NearDuplicate> = candidates
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let similarity = similarity(&signatures[a].1.poem, &signatures[b].1.poem);
+            (similarity >= threshold).then_some(NearDuplicate {
+                first_cycle: signatures[a].0,
+                second_cycle: signatures[b].0,
+                similarity,
+            })
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    pairs
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecurringTheme {
+    pub keyword: String,
+    pub cycles: Vec<u64>, // Newest first
+}
+
+// Keywords of titles and next prompts that keep coming back across `cycles`
+// (newest first), most frequent first
+pub fn recurring_themes(cycles: &[PoemCycle], min_cycles: usize) -> Vec<RecurringTheme> {
+    let mut seen: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for cycle in cycles {
+        for keyword in tokenizer::keywords(&format!("{} {}", cycle.title, cycle.next_prompt)) {
+            seen.entry(keyword).or_default().push(cycle.cycle_number);
+        }
+    }
+    let mut themes: Vec<RecurringTheme> = seen
+        .into_iter()
+        .filter(|(_, cycles)| cycles.len() >= min_cycles)
+        .map(|(keyword, cycles)| RecurringTheme { keyword, cycles })
+        .collect();
+    themes.sort_by_key(|theme| Reverse(theme.cycles.len()));
+    themes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAIN: &str = "the rain on the tin roof counts the hours i cannot sleep \
+        and the gutters sing of every promise that ran off into the dark";
+
+    fn cycle(n: u64, title: &str, next_prompt: &str) -> PoemCycle {
+        PoemCycle {
+            poem: String::new(),
+            title: title.to_string(),
+            next_prompt: next_prompt.to_string(),
+            ..PoemCycle::test(n)
+        }
+    }
+
+    fn reference(cycle_number: u64, poem: &str) -> Reference {
+        Reference {
+            cycle_number,
+            title: String::new(),
+            signature: signature_of(poem, "", ""),
+        }
+    }
+
+    #[test]
+    fn near_copies_score_low_and_new_poems_high() {
+        let recent = [
+            reference(1, RAIN),
+            reference(2, "a lighthouse keeper forgets the names of ships"),
+        ];
+
+        let copy = signature_of(&format!("{} again", RAIN), "", "");
+        let novelty = assess(&copy, &recent);
+        assert!(novelty.score < 0.3, "{:?}", novelty);
+        assert_eq!(novelty.closest_cycle, Some(1));
+
+        let fresh = signature_of(
+            "server fans exhale warm air into a room nobody visits",
+            "",
+            "",
+        );
+        assert!(assess(&fresh, &recent).score > 0.9);
+        assert_eq!(assess(&fresh, &[]).score, 1.0);
+    }
+
+    #[test]
+    fn signatures_are_stable() {
+        // Stored signatures must stay comparable with ones computed later
+        let signature = signature_of("one two three four", "Title", "Write about counting");
+        assert_eq!(signature.poem.len(), SIGNATURE_LEN);
+        assert_eq!(
+            signature,
+            signature_of("one two three four", "Title", "Write about counting")
+        );
+        assert_eq!(fnv1a("rust"), 0xbffe_df1f_6f66_c727);
+    }
+
+    #[test]
+    fn banding_finds_near_duplicate_pairs() {
+        let signatures = vec![
+            (1, signature_of(RAIN, "", "")),
+            (
+                2,
+                signature_of("a lighthouse keeper forgets the names of ships", "", ""),
+            ),
+            (3, signature_of(&format!("{} tonight", RAIN), "", "")),
+        ];
+        let pairs = find_pairs(&signatures, 0.6);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].first_cycle, pairs[0].second_cycle), (1, 3));
+    }
+
+    #[test]
+    fn near_duplicates_need_backfilled_signatures() {
+        POEM_CYCLES.with(|cycles| {
+            let mut cycles = cycles.borrow_mut();
+            cycles.insert(
+                (4, 1),
+                PoemCycle {
+                    poem: RAIN.to_string(),
+                    ..PoemCycle::test(1)
+                },
+            );
+            cycles.insert(
+                (4, 2),
+                PoemCycle {
+                    poem: format!("{} tonight", RAIN),
+                    ..PoemCycle::test(2)
+                },
+            );
+        });
+        assert!(near_duplicates(4, 0.6).is_empty());
+
+        backfill_signatures();
+        let pairs = near_duplicates(4, 0.6);
+        assert_eq!((pairs[0].first_cycle, pairs[0].second_cycle), (1, 2));
+    }
+
+    #[test]
+    fn recurring_themes_need_several_cycles() {
+        let cycles = vec![
+            cycle(3, "Mirror Static", "Write about mirrors in empty rooms"),
+            cycle(2, "Glass", "Write about a looking glass that remembers"),
+            cycle(1, "Mirrors", "Write about the sea"),
+        ];
+        let themes = recurring_themes(&cycles, 2);
+        assert_eq!(themes.len(), 1);
+        assert_eq!(themes[0].keyword, "mirrors");
+        assert_eq!(themes[0].cycles, vec![3, 1]);
+    }
+}
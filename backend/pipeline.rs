@@ -0,0 +1,846 @@
+use candid::{CandidType, Deserialize};
+use ic_llm::{AssistantMessage, ChatMessage};
+use serde::Serialize;
+
+use crate::config::{GenerationConfig, LlmModel, OutputContract};
+use crate::error::PoetError;
+use crate::json_output::parse_with_json;
+use crate::llm::{ChatPurpose, PoetLlm};
+use crate::novelty::{self, Novelty, Reference, Signature};
+use crate::wrap;
+use crate::{
+    create_correction_prompt, create_format_correction_prompt, generate_algorithmic_fallback,
+    has_old_markers, parse_with_heuristics, parse_with_labels, GenerationMethod,
+};
+
+// What the pipeline hands back to be stored as a PoemCycle
+pub struct GeneratedPoem {
+    pub poem: String,
+    pub title: String,
+    pub next_prompt: String,
+    pub method: GenerationMethod,
+    pub raw_response: String,
+    pub model: LlmModel,
+    pub novelty: Option<Novelty>, // Set by generate_novel_poem
+    pub corrections: Vec<CorrectionAttempt>,
+}
+
+// Corrected answers are kept for debugging and replay, like raw_response
+const MAX_CORRECTION_CHARS: usize = 2000;
+
+// Longest sections a cycle keeps. At four bytes a character, together with
+// the most corrections the config allows, a PoemCycle stays inside its
+// 100 000 byte bound whatever the model answers.
+const MAX_POEM_CHARS: usize = 6000;
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_NEXT_PROMPT_CHARS: usize = 500;
+const MAX_RAW_RESPONSE_CHARS: usize = 5000;
+
+fn clip(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum CorrectionKind {
+    Format, // The answer used the old [BRACKET] markers
+    Parse,  // No parser could read the answer
+}
+
+// One request for a corrected answer, as stored on the cycle
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CorrectionAttempt {
+    pub attempt: u8, // 1 for the first request of the cycle
+    pub kind: CorrectionKind,
+    pub response: Option<String>, // None when the call failed or came back empty
+    pub error: Option<String>,    // Why the answer was rejected, None when it was used
+    pub template_version: Option<u32>, // Version of the template that asked, None before it was recorded
+}
+
+impl CorrectionAttempt {
+    fn new(
+        attempt: u8,
+        kind: CorrectionKind,
+        template_version: u32,
+        response: Option<&str>,
+        error: Option<String>,
+    ) -> Self {
+        CorrectionAttempt {
+            attempt,
+            kind,
+            template_version: Some(template_version),
+            response: response
+                .map(|response| response.chars().take(MAX_CORRECTION_CHARS).collect()),
+            error,
+        }
+    }
+}
+
+// Send a single system prompt and return the text content
+async fn ask<L: PoetLlm>(
+    llm: &L,
+    purpose: ChatPurpose,
+    model: LlmModel,
+    content: String,
+) -> Result<Option<String>, PoetError> {
+    let messages = vec![ChatMessage::System { content }];
+    llm.chat(purpose, model.to_model(), messages).await
+}
+
+fn assistant(content: &str) -> ChatMessage {
+    ChatMessage::Assistant(AssistantMessage {
+        content: Some(content.to_string()),
+        tool_calls: Vec::new(),
+    })
+}
+
+// Both parsers, plus JSON under that contract. The labels parser's reason is
+// what gets reported, since corrections ask for labels.
+fn parse_correction(
+    config: &GenerationConfig,
+    response: &str,
+) -> Result<(String, String, String), PoetError> {
+    if config.output_contract() == OutputContract::Json {
+        if let Ok(sections) = parse_with_json(response) {
+            return Ok(sections);
+        }
+    }
+    parse_with_labels(response)
+        .or_else(|labels_error| parse_with_heuristics(response).map_err(|_| labels_error))
+}
+
+// Ask for a correction up to the configured number of times and fall back to
+// algorithmic generation. The model sees the whole exchange: the original
+// prompt, its failed answer and the correction request, then each further
+// failed answer with the reason it was rejected. Every attempt is recorded.
+async fn correct<L: PoetLlm>(
+    llm: &L,
+    config: &GenerationConfig,
+    kind: CorrectionKind,
+    full_prompt: &str,
+    raw_response: &str,
+    cycle_number: u64,
+    current_prompt: &str,
+) -> (
+    (String, String, String, GenerationMethod),
+    Vec<CorrectionAttempt>,
+) {
+    let (request, template_version) = match kind {
+        CorrectionKind::Format => {
+            create_format_correction_prompt(raw_response, cycle_number, current_prompt)
+        }
+        CorrectionKind::Parse => {
+            create_correction_prompt(raw_response, cycle_number, current_prompt)
+        }
+    };
+    let mut attempts = Vec::new();
+    let mut messages = vec![
+        ChatMessage::System {
+            content: full_prompt.to_string(),
+        },
+        assistant(raw_response),
+        ChatMessage::User {
+            content: request.clone(),
+        },
+    ];
+
+    for attempt in 1..=config.max_correction_retries {
+        let answer = llm
+            .chat(
+                ChatPurpose::Correction,
+                config.correction_model.to_model(),
+                messages.clone(),
+            )
+            .await;
+        // A failed correction call only costs this attempt; we already have a raw response
+        let (response, error) = match answer {
+            Ok(Some(response)) => match parse_correction(config, &response) {
+                Ok((p, t, n)) => {
+                    attempts.push(CorrectionAttempt::new(
+                        attempt,
+                        kind,
+                        template_version,
+                        Some(&response),
+                        None,
+                    ));
+                    return ((p, t, n, GenerationMethod::Corrected), attempts);
+                }
+                Err(error) => (Some(response), error.to_string()),
+            },
+            Ok(None) => (None, "The model returned nothing".to_string()),
+            Err(error) => (None, error.to_string()),
+        };
+        attempts.push(CorrectionAttempt::new(
+            attempt,
+            kind,
+            template_version,
+            response.as_deref(),
+            Some(error.clone()),
+        ));
+        if let Some(response) = response {
+            messages.push(assistant(&response));
+            messages.push(ChatMessage::User {
+                content: format!(
+                    "That answer could not be read either: {}\n\n{}",
+                    error, request
+                ),
+            });
+        }
+    }
+    // Correction failed or still unparseable - use algorithmic fallback
+    let (p, t, n) = generate_algorithmic_fallback(raw_response, cycle_number, current_prompt);
+    ((p, t, n, GenerationMethod::Algorithmic), attempts)
+}
+
+// THE GENERATION PIPELINE - prompt the model, then parse with multiple strategies.
+// Once the model has answered this always produces a poem; the method records
+// which layer got us there. Only a failed first call is an error.
+pub async fn generate_poem<L: PoetLlm>(
+    llm: &L,
+    config: &GenerationConfig,
+    full_prompt: String,
+    cycle_number: u64,
+    current_prompt: &str,
+) -> Result<GeneratedPoem, PoetError> {
+    // STEP 1: Get LLM response
+    let model = config.model_for_cycle(cycle_number);
+    let raw_response = ask(llm, ChatPurpose::Generation, model, full_prompt.clone())
+        .await?
+        .unwrap_or_default();
+
+    // STEP 2: Parse with multiple strategies
+    let mut corrections = Vec::new();
+    let (poem, title, next_prompt, method) = {
+        // First check if old markers are used - force immediate correction
+        if has_old_markers(&raw_response) {
+            let (sections, attempts) = correct(
+                llm,
+                config,
+                CorrectionKind::Format,
+                &full_prompt,
+                &raw_response,
+                cycle_number,
+                current_prompt,
+            )
+            .await;
+            corrections = attempts;
+            sections
+        }
+        // Under the JSON contract, try the JSON object first
+        else if let Some((p, t, n)) = (config.output_contract() == OutputContract::Json)
+            .then(|| parse_with_json(&raw_response).ok())
+            .flatten()
+        {
+            (p, t, n, GenerationMethod::Structured)
+        }
+        // Try primary parsing with database labels (also the JSON contract's fallback)
+        else if let Ok((p, t, n)) = parse_with_labels(&raw_response) {
+            (p, t, n, GenerationMethod::Primary)
+        }
+        // Try heuristic parsing
+        else if let Ok((p, t, n)) = parse_with_heuristics(&raw_response) {
+            (p, t, n, GenerationMethod::Fallback)
+        }
+        // Try general correction
+        else {
+            let (sections, attempts) = correct(
+                llm,
+                config,
+                CorrectionKind::Parse,
+                &full_prompt,
+                &raw_response,
+                cycle_number,
+                current_prompt,
+            )
+            .await;
+            corrections = attempts;
+            sections
+        }
+    };
+
+    // STEP 3: Wrap to the notebook width, whichever layer produced the poem,
+    // and keep only as much as a cycle can store
+    let poem = wrap::wrap_poem(&poem, &config.wrap_options());
+    Ok(GeneratedPoem {
+        poem: clip(&poem, MAX_POEM_CHARS),
+        title: clip(&title, MAX_TITLE_CHARS),
+        next_prompt: clip(&next_prompt, MAX_NEXT_PROMPT_CHARS),
+        method,
+        raw_response: clip(&raw_response, MAX_RAW_RESPONSE_CHARS),
+        model,
+        novelty: None,
+        corrections,
+    })
+}
+
+// Tell the model which recent poem its draft repeated
+fn repetition_warning(full_prompt: &str, closest: Option<&Reference>) -> String {
+    let repeated = closest.map_or_else(
+        || "one of your recent poems".to_string(),
+        |reference| format!("cycle {} (\"{}\")", reference.cycle_number, reference.title),
+    );
+    format!(
+        "{}\n\nNOTE: Your last draft was nearly the same poem as {}. Do not write it again. \
+         Change the angle, the form and the words.",
+        full_prompt, repeated
+    )
+}
+
+// generate_poem, scored against recent poems. While the result repeats one
+// of them, ask again with a warning, up to the configured number of times;
+// the most novel attempt wins. Returns the poem with its signature.
+pub async fn generate_novel_poem<L: PoetLlm>(
+    llm: &L,
+    config: &GenerationConfig,
+    full_prompt: String,
+    cycle_number: u64,
+    current_prompt: &str,
+    recent: &[Reference],
+) -> Result<(GeneratedPoem, Signature), PoetError> {
+    let options = config.novelty_options();
+    let mut best = generate_poem(
+        llm,
+        config,
+        full_prompt.clone(),
+        cycle_number,
+        current_prompt,
+    )
+    .await?;
+    let mut best_signature = novelty::signature_of(&best.poem, &best.title, &best.next_prompt);
+    let mut best_novelty = novelty::assess(&best_signature, recent);
+
+    let mut reprompts = 0;
+    while reprompts < options.max_reprompts && 1.0 - best_novelty.score >= options.threshold {
+        reprompts += 1;
+        let closest = recent
+            .iter()
+            .find(|r| Some(r.cycle_number) == best_novelty.closest_cycle);
+        let prompt = repetition_warning(&full_prompt, closest);
+        // A failed retry keeps what we already have
+        let Ok(attempt) = generate_poem(llm, config, prompt, cycle_number, current_prompt).await
+        else {
+            break;
+        };
+        let signature = novelty::signature_of(&attempt.poem, &attempt.title, &attempt.next_prompt);
+        let assessed = novelty::assess(&signature, recent);
+        if assessed.score > best_novelty.score {
+            best = attempt;
+            best_signature = signature;
+            best_novelty = assessed;
+        }
+    }
+
+    best_novelty.reprompts = reprompts;
+    best.novelty = Some(best_novelty);
+    Ok((best, best_signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CycleOverride;
+    use crate::llm::{ReplayLlm, ScriptedLlm};
+    use crate::wrap::WrapOptions;
+    use crate::PoemCycle;
+    use serde::Deserialize;
+    use std::fs;
+    use std::future::Future;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    // The test doubles never suspend, so polling to completion is enough
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    const NEXT: &str =
+        "Write about the hum of a server room at night when nobody is left to listen";
+
+    fn labelled(poem: &str, title: &str) -> String {
+        format!("POEM: {}\nTITLE: {}\nNEXT: {}", poem, title, NEXT)
+    }
+
+    fn run_with<L: PoetLlm>(llm: &L, config: &GenerationConfig) -> GeneratedPoem {
+        block_on(generate_poem(
+            llm,
+            config,
+            "prompt".to_string(),
+            7,
+            "Write about rust",
+        ))
+        .unwrap()
+    }
+
+    fn run<L: PoetLlm>(llm: &L) -> GeneratedPoem {
+        run_with(llm, &GenerationConfig::default())
+    }
+
+    #[test]
+    fn labelled_response_is_primary() {
+        let llm = ScriptedLlm::new([Some(labelled("static in my veins", "Static Veins"))]);
+        let result = run(&llm);
+
+        assert!(matches!(result.method, GenerationMethod::Primary));
+        assert_eq!(result.poem, "static in my veins");
+        assert_eq!(result.title, "Static Veins");
+        assert_eq!(result.next_prompt, NEXT);
+
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model, "llama3.1:8b");
+        assert_eq!(
+            requests[0].messages,
+            vec![ChatMessage::System {
+                content: "prompt".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn old_markers_force_format_correction() {
+        let raw =
+            "[POEM-START]old habits[POEM-END][TITLE-START]Old[TITLE-END][NEXT-START]more[NEXT-END]";
+        let llm = ScriptedLlm::new([Some(raw.to_string()), Some(labelled("new habits", "New"))]);
+        let result = run(&llm);
+
+        assert!(matches!(result.method, GenerationMethod::Corrected));
+        assert_eq!(result.poem, "new habits");
+        assert_eq!(result.raw_response, raw);
+
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].purpose, ChatPurpose::Correction);
+        assert_eq!(result.corrections.len(), 1);
+        assert_eq!(result.corrections[0].kind, CorrectionKind::Format);
+    }
+
+    #[test]
+    fn correction_continues_the_conversation() {
+        let llm = ScriptedLlm::new([
+            Some("too short".to_string()),
+            Some("still too short".to_string()),
+            Some(labelled("fixed it", "Fixed")),
+        ]);
+        let config = GenerationConfig {
+            max_correction_retries: 2,
+            ..GenerationConfig::default()
+        };
+        let result = run_with(&llm, &config);
+        assert!(matches!(result.method, GenerationMethod::Corrected));
+
+        // Original prompt, failed answer, correction request, then each rejected answer with its reason
+        let requests = llm.requests();
+        let messages = &requests[2].messages;
+        assert_eq!(messages.len(), 5);
+        assert_eq!(
+            messages[0],
+            ChatMessage::System {
+                content: "prompt".to_string()
+            }
+        );
+        assert_eq!(messages[1], assistant("too short"));
+        assert!(
+            matches!(&messages[2], ChatMessage::User { content } if content.contains("EXACT database format"))
+        );
+        assert_eq!(messages[3], assistant("still too short"));
+        assert!(
+            matches!(&messages[4], ChatMessage::User { content } if content.starts_with("That answer could not be read"))
+        );
+
+        let attempts: Vec<(u8, bool)> = result
+            .corrections
+            .iter()
+            .map(|a| (a.attempt, a.error.is_none()))
+            .collect();
+        assert_eq!(attempts, vec![(1, false), (2, true)]);
+        assert_eq!(
+            result.corrections[0].response.as_deref(),
+            Some("still too short")
+        );
+    }
+
+    #[test]
+    fn unparseable_response_goes_through_general_correction() {
+        let llm = ScriptedLlm::new([
+            Some("too short".to_string()),
+            Some(labelled("fixed it", "Fixed")),
+        ]);
+        let result = run(&llm);
+
+        assert!(matches!(result.method, GenerationMethod::Corrected));
+        assert_eq!(result.title, "Fixed");
+    }
+
+    #[test]
+    fn empty_model_falls_back_to_algorithmic() {
+        let llm = ScriptedLlm::new(Vec::<Option<String>>::new());
+        let result = run(&llm);
+
+        assert!(matches!(result.method, GenerationMethod::Algorithmic));
+        assert_eq!(result.title, "Glitch Cycle 7");
+        assert!(result.poem.contains("ERROR HAIKU #7"));
+        assert!(result.next_prompt.len() >= 50);
+    }
+
+    #[test]
+    fn unlabelled_lines_use_heuristics() {
+        let raw = "neon rain\non chrome teeth\nChrome Teeth\nWrite about the weight of rain on machines that cannot feel it fall";
+        let llm = ScriptedLlm::new([Some(raw)]);
+        let result = run(&llm);
+
+        assert!(matches!(result.method, GenerationMethod::Fallback));
+        assert!(result.poem.starts_with("neon rain\non chrome teeth"));
+        assert_eq!(result.title, "Chrome Teeth");
+    }
+
+    #[test]
+    fn replay_feeds_stored_raw_responses() {
+        let stored = |raw: String| PoemCycle {
+            raw_response: raw,
+            ..PoemCycle::test(1)
+        };
+        let cycles = vec![
+            stored(labelled("first light", "First")),
+            stored("[POEM-START]x[POEM-END]".to_string()),
+        ];
+        let llm = ReplayLlm::from_cycles(&cycles);
+
+        let first = run(&llm);
+        assert!(matches!(first.method, GenerationMethod::Primary));
+        assert_eq!(first.poem, "first light");
+
+        // No corrections were recorded, so the second cycle ends up algorithmic
+        let second = run(&llm);
+        assert!(matches!(second.method, GenerationMethod::Algorithmic));
+        assert_eq!(llm.remaining(), 0);
+
+        // Recorded correction answers are replayed too
+        let corrected = PoemCycle {
+            corrections: Some(vec![CorrectionAttempt::new(
+                1,
+                CorrectionKind::Parse,
+                0,
+                Some(&labelled("again", "Again")),
+                None,
+            )]),
+            ..stored("too short".to_string())
+        };
+        let replayed = run(&ReplayLlm::from_cycles(&[corrected]));
+        assert!(matches!(replayed.method, GenerationMethod::Corrected));
+        assert_eq!(replayed.title, "Again");
+    }
+
+    #[test]
+    fn retries_and_models_follow_config() {
+        let config = GenerationConfig {
+            model: LlmModel::Llama3_1_8B,
+            correction_model: LlmModel::Qwen3_32B,
+            max_correction_retries: 2,
+            cycle_overrides: vec![CycleOverride {
+                cycle_number: 7,
+                model: LlmModel::Llama4Scout,
+            }],
+            wrap: None,
+            output_contract: None,
+            memory: None,
+            novelty: None,
+        };
+        let llm = ScriptedLlm::new([
+            Some("nope".to_string()),
+            Some("still nope".to_string()),
+            Some(labelled("third time", "Lucky")),
+        ]);
+        let result = run_with(&llm, &config);
+
+        assert!(matches!(result.method, GenerationMethod::Corrected));
+        assert_eq!(result.model, LlmModel::Llama4Scout);
+
+        let models: Vec<String> = llm.requests().into_iter().map(|r| r.model).collect();
+        assert_eq!(models, vec!["llama4-scout", "qwen3:32b", "qwen3:32b"]);
+    }
+
+    #[test]
+    fn zero_retries_skips_correction() {
+        let config = GenerationConfig {
+            max_correction_retries: 0,
+            ..GenerationConfig::default()
+        };
+        let llm = ScriptedLlm::new([Some("nope".to_string()), Some(labelled("unused", "Unused"))]);
+        let result = run_with(&llm, &config);
+
+        assert!(matches!(result.method, GenerationMethod::Algorithmic));
+        assert_eq!(llm.requests().len(), 1);
+    }
+
+    #[test]
+    fn poem_is_wrapped_to_configured_width() {
+        let config = GenerationConfig {
+            wrap: Some(WrapOptions {
+                width: 20,
+                hanging_indent: 2,
+            }),
+            ..GenerationConfig::default()
+        };
+        let llm = ScriptedLlm::new([Some(labelled(
+            "the kettle sings to an empty kitchen at dawn",
+            "Kettle",
+        ))]);
+        let result = run_with(&llm, &config);
+
+        assert_eq!(
+            result.poem,
+            "the kettle sings to\n  an empty kitchen\n  at dawn"
+        );
+    }
+
+    #[test]
+    fn json_contract_is_structured_and_falls_back_to_labels() {
+        let config = GenerationConfig {
+            output_contract: Some(OutputContract::Json),
+            ..GenerationConfig::default()
+        };
+        let json = format!(
+            r#"{{"poem": "a poem that says TITLE: aloud", "title": "Loud", "next": "{}"}}"#,
+            NEXT
+        );
+        let result = run_with(&ScriptedLlm::new([Some(json)]), &config);
+        assert!(matches!(result.method, GenerationMethod::Structured));
+        assert_eq!(result.poem, "a poem that says TITLE: aloud");
+
+        let labels = ScriptedLlm::new([Some(labelled("{ not json", "Broken Brace"))]);
+        let result = run_with(&labels, &config);
+        assert!(matches!(result.method, GenerationMethod::Primary));
+        assert_eq!(result.title, "Broken Brace");
+    }
+
+    #[test]
+    fn rejected_generation_call_is_llm_unavailable() {
+        let llm = ScriptedLlm::failing();
+        let result = block_on(generate_poem(
+            &llm,
+            &GenerationConfig::default(),
+            "prompt".to_string(),
+            7,
+            "Write about rust",
+        ));
+
+        assert!(matches!(result, Err(PoetError::LlmUnavailable(_))));
+        assert_eq!(llm.requests().len(), 1);
+    }
+
+    const RAIN: &str =
+        "the rain on the tin roof counts the hours i cannot sleep and the gutters sing of promises";
+
+    #[test]
+    fn repeated_poem_is_reprompted() {
+        let recent = vec![Reference {
+            cycle_number: 4,
+            title: "Tin Roof".to_string(),
+            signature: novelty::signature_of(RAIN, "", ""),
+        }];
+        let llm = ScriptedLlm::new([
+            Some(labelled(RAIN, "Tin Roof Again")),
+            Some(labelled(
+                "a lighthouse keeper forgets the names of ships",
+                "Lighthouse",
+            )),
+        ]);
+        let (result, signature) = block_on(generate_novel_poem(
+            &llm,
+            &GenerationConfig::default(),
+            "prompt".to_string(),
+            7,
+            "Write about rust",
+            &recent,
+        ))
+        .unwrap();
+
+        assert_eq!(result.title, "Lighthouse");
+        let novelty = result.novelty.unwrap();
+        assert_eq!(novelty.reprompts, 1);
+        assert!(novelty.score > 0.9);
+        assert_eq!(
+            signature,
+            novelty::signature_of(&result.poem, &result.title, &result.next_prompt)
+        );
+
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 2);
+        let ChatMessage::System { content } = &requests[1].messages[0] else {
+            panic!("expected a system prompt");
+        };
+        assert!(
+            content.starts_with("prompt\n\nNOTE:") && content.contains("cycle 4 (\"Tin Roof\")")
+        );
+    }
+
+    #[test]
+    fn novel_poem_is_only_scored() {
+        let recent = vec![Reference {
+            cycle_number: 1,
+            title: String::new(),
+            signature: novelty::signature_of(RAIN, "", ""),
+        }];
+        let llm = ScriptedLlm::new([Some(labelled("moths at the porch light", "Moths"))]);
+        let (result, _) = block_on(generate_novel_poem(
+            &llm,
+            &GenerationConfig::default(),
+            "prompt".to_string(),
+            7,
+            "Write about rust",
+            &recent,
+        ))
+        .unwrap();
+
+        assert_eq!(result.novelty.unwrap().reprompts, 0);
+        assert_eq!(llm.requests().len(), 1);
+    }
+
+    #[test]
+    fn longest_possible_cycle_can_be_stored() {
+        use ic_stable_structures::{storable::Bound, Storable};
+
+        // Four bytes each, the most UTF-8 takes
+        let text = |chars: usize| "𝄞".repeat(chars);
+        let attempt = |n| CorrectionAttempt {
+            error: Some(text(200)),
+            ..CorrectionAttempt::new(
+                n,
+                CorrectionKind::Parse,
+                0,
+                Some(&text(MAX_CORRECTION_CHARS)),
+                None,
+            )
+        };
+        let cycle = PoemCycle {
+            poem: text(MAX_POEM_CHARS),
+            title: text(MAX_TITLE_CHARS),
+            next_prompt: text(MAX_NEXT_PROMPT_CHARS),
+            raw_response: text(MAX_RAW_RESPONSE_CHARS),
+            corrections: Some((1..=5).map(attempt).collect()),
+            ..PoemCycle::test(1)
+        };
+        let Bound::Bounded { max_size, .. } = PoemCycle::BOUND else {
+            panic!("PoemCycle is bounded");
+        };
+        assert!(cycle.to_bytes().len() <= max_size as usize);
+    }
+
+    // What a golden corpus case must turn into. Absent fields are not checked.
+    #[derive(Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    struct Expected {
+        method: String,        // GenerationMethod variant name
+        json_contract: bool,   // Parse under the JSON output contract
+        repeat: Option<usize>, // The answer is the case file this many times over
+        poem: Option<String>,
+        poem_prefix: Option<String>,
+        poem_chars: Option<usize>, // Length of the poem, for answers too long to spell out
+        title: Option<String>,
+        next_prompt: Option<String>,
+        corrections: Option<usize>,
+    }
+
+    impl Expected {
+        fn mismatches(&self, result: &GeneratedPoem) -> Vec<String> {
+            let method = serde_json::to_value(&result.method).unwrap();
+            let mut mismatches = Vec::new();
+            let mut check = |field: &str, expected: Option<&str>, actual: &str| {
+                if expected.is_some_and(|expected| expected != actual) {
+                    mismatches.push(format!("{} is {:?}", field, actual));
+                }
+            };
+            check(
+                "method",
+                Some(&self.method),
+                method.as_str().unwrap_or_default(),
+            );
+            check("poem", self.poem.as_deref(), &result.poem);
+            check("title", self.title.as_deref(), &result.title);
+            check(
+                "next_prompt",
+                self.next_prompt.as_deref(),
+                &result.next_prompt,
+            );
+            if self
+                .poem_prefix
+                .as_ref()
+                .is_some_and(|prefix| !result.poem.starts_with(prefix.as_str()))
+            {
+                mismatches.push(format!(
+                    "poem starts {:?}",
+                    result.poem.chars().take(80).collect::<String>()
+                ));
+            }
+            let poem_chars = result.poem.chars().count();
+            if self.poem_chars.is_some_and(|chars| chars != poem_chars) {
+                mismatches.push(format!("poem is {} characters", poem_chars));
+            }
+            if self
+                .corrections
+                .is_some_and(|count| count != result.corrections.len())
+            {
+                mismatches.push(format!(
+                    "{} corrections were made",
+                    result.corrections.len()
+                ));
+            }
+            mismatches
+        }
+    }
+
+    // Golden corpus of real and adversarial model answers. For every
+    // testdata/corpus/<case>.json the model answers with <case>.txt and, if
+    // there is one, gives <case>.correction.txt as its single correction.
+    #[test]
+    fn golden_corpus() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/corpus");
+        let mut cases: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        cases.sort();
+        assert!(!cases.is_empty(), "no corpus in {}", dir.display());
+
+        let mut failures = Vec::new();
+        for case in &cases {
+            let name = case.file_stem().unwrap().to_string_lossy();
+            let expected: Expected = serde_json::from_str(&fs::read_to_string(case).unwrap())
+                .unwrap_or_else(|error| panic!("{}: {}", name, error));
+            let raw = fs::read_to_string(case.with_extension("txt"))
+                .unwrap()
+                .repeat(expected.repeat.unwrap_or(1));
+            let correction = fs::read_to_string(case.with_extension("correction.txt")).ok();
+            let config = GenerationConfig {
+                max_correction_retries: u8::from(correction.is_some()),
+                output_contract: expected.json_contract.then_some(OutputContract::Json),
+                ..GenerationConfig::default()
+            };
+
+            let llm = ScriptedLlm::new([Some(raw), correction]);
+            let run = catch_unwind(AssertUnwindSafe(|| run_with(&llm, &config)));
+            match run {
+                Ok(result) => failures.extend(
+                    expected
+                        .mismatches(&result)
+                        .into_iter()
+                        .map(|m| format!("{}: {}", name, m)),
+                ),
+                Err(_) => failures.push(format!("{}: the pipeline panicked", name)),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "golden corpus mismatches:\n{}",
+            failures.join("\n")
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use candid::{CandidType, Deserialize};
+use ic_cdk_timers::TimerId;
+use ic_stable_structures::{storable::Bound, StableCell, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::error::PoetError;
+use crate::evolution;
+use crate::schema::{self, Versioned};
+use crate::{
+    get_current_time, poet_ids, run_evolution, Memory, MEMORY_MANAGER, SCHEDULE_MEMORY_ID,
+};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_DAY: u64 = 86_400 * NANOS_PER_SEC;
+const MIN_INTERVAL_SECS: u64 = 60;
+// A year; anything longer is a typo, and far larger values overflow in nanoseconds
+const MAX_INTERVAL_SECS: u64 = 365 * 86_400;
+
+// Operator-facing knobs for autonomous evolution
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScheduleConfig {
+    pub interval_secs: u64,
+    pub jitter_secs: u64,        // Random extra delay added to every interval
+    pub max_cycles_per_day: u32, // Hard cap per UTC day, 0 = unlimited
+    pub paused: bool,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            interval_secs: 6 * 60 * 60,
+            jitter_secs: 15 * 60,
+            max_cycles_per_day: 4,
+            paused: true, // Opt-in: nothing runs until an admin resumes it
+        }
+    }
+}
+
+// Persisted schedule, including the bookkeeping needed to re-arm after an upgrade
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ScheduleState {
+    pub config: ScheduleConfig,
+    pub next_run_at: Option<u64>,
+    pub last_run_at: Option<u64>,
+    pub last_outcome: Option<String>,
+    pub day: u64, // UTC day number that cycles_today refers to
+    pub cycles_today: u32,
+}
+
+impl Storable for ScheduleState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for ScheduleState {
+    const VERSION: u16 = 1;
+
+    // Fall back to the default (paused) schedule rather than trapping
+    fn unreadable(_error: String) -> Self {
+        ScheduleState::default()
+    }
+}
+
+thread_local! {
+    static SCHEDULE: RefCell<StableCell<ScheduleState, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULE_MEMORY_ID)),
+            ScheduleState::default(),
+        ).expect("failed to initialize schedule cell")
+    );
+
+    // Timers live on the heap and are lost on upgrade, see `rearm`
+    static TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
+}
+
+pub fn reencode_storage() {
+    SCHEDULE.with(|s| schema::reencode_cell(&mut s.borrow_mut()));
+}
+
+fn read_state() -> ScheduleState {
+    SCHEDULE.with(|s| s.borrow().get().clone())
+}
+
+fn write_state(state: ScheduleState) {
+    SCHEDULE.with(|s| {
+        s.borrow_mut()
+            .set(state)
+            .expect("failed to persist schedule");
+    });
+}
+
+pub fn get_state() -> ScheduleState {
+    read_state()
+}
+
+fn validate_config(config: &ScheduleConfig) -> Result<(), PoetError> {
+    if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&config.interval_secs) {
+        return Err(PoetError::InvalidConfig(format!(
+            "Interval must be between {} and {} seconds",
+            MIN_INTERVAL_SECS, MAX_INTERVAL_SECS
+        )));
+    }
+    if config.jitter_secs > config.interval_secs {
+        return Err(PoetError::InvalidConfig(
+            "Jitter cannot exceed the interval".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Replace the config and restart the countdown from now
+pub fn configure(config: ScheduleConfig) -> Result<ScheduleState, PoetError> {
+    validate_config(&config)?;
+    let mut state = read_state();
+    state.config = config;
+    state.next_run_at = None;
+    write_state(state);
+    rearm();
+    Ok(read_state())
+}
+
+pub fn set_paused(paused: bool) -> ScheduleState {
+    let mut state = read_state();
+    state.config.paused = paused;
+    state.next_run_at = None;
+    write_state(state);
+    rearm();
+    read_state()
+}
+
+// Cheap deterministic jitter - good enough to avoid lock-step evolution,
+// not meant to be unpredictable.
+fn jitter_nanos(now: u64, jitter_secs: u64) -> u64 {
+    if jitter_secs == 0 {
+        return 0;
+    }
+    let mixed = (now ^ (now >> 29)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    (mixed % jitter_secs.saturating_add(1)).saturating_mul(NANOS_PER_SEC)
+}
+
+fn clear_timer() {
+    TIMER.with(|t| {
+        if let Some(id) = t.borrow_mut().take() {
+            ic_cdk_timers::clear_timer(id);
+        }
+    });
+}
+
+// Schedule the next tick. A persisted next_run_at is honoured so that
+// re-arming in post_upgrade (timers do not survive upgrades) does not
+// reset the countdown.
+pub fn rearm() {
+    clear_timer();
+
+    let mut state = read_state();
+    if state.config.paused {
+        state.next_run_at = None;
+        write_state(state);
+        return;
+    }
+
+    let now = get_current_time();
+    let next_run_at = match state.next_run_at {
+        Some(at) => at,
+        None => now
+            .saturating_add(state.config.interval_secs.saturating_mul(NANOS_PER_SEC))
+            .saturating_add(jitter_nanos(now, state.config.jitter_secs)),
+    };
+    state.next_run_at = Some(next_run_at);
+    write_state(state);
+
+    let delay = Duration::from_nanos(next_run_at.saturating_sub(now));
+    let id = ic_cdk_timers::set_timer(delay, || ic_cdk::spawn(tick()));
+    TIMER.with(|t| *t.borrow_mut() = Some(id));
+}
+
+async fn tick() {
+    let now = get_current_time();
+    let mut state = read_state();
+    TIMER.with(|t| *t.borrow_mut() = None);
+
+    if state.config.paused {
+        return;
+    }
+
+    // Re-arm before doing any work so a trap during evolution cannot stop the schedule
+    state.next_run_at = None;
+    let today = now / NANOS_PER_DAY;
+    if state.day != today {
+        state.day = today;
+        state.cycles_today = 0;
+    }
+
+    // Every poet evolves in turn; the daily cap counts cycles across all of them
+    let limit = state.config.max_cycles_per_day;
+    let mut outcomes = Vec::new();
+    for poet_id in poet_ids() {
+        if limit > 0 && state.cycles_today >= limit {
+            outcomes.push(format!(
+                "Skipped poet {}: daily limit of {} reached",
+                poet_id, limit
+            ));
+            continue;
+        }
+
+        // A manual evolution is running; don't spend today's budget on a refusal
+        if let Some(running) = evolution::status(poet_id) {
+            outcomes.push(format!(
+                "Skipped poet {}: cycle {} is already being evolved",
+                poet_id, running.cycle_number
+            ));
+            continue;
+        }
+
+        // Count the attempt up-front; the await below commits this state
+        state.cycles_today += 1;
+        state.last_run_at = Some(now);
+        write_state(state.clone());
+        if TIMER.with(|t| t.borrow().is_none()) {
+            rearm();
+        }
+
+        outcomes.push(match run_evolution(poet_id, None).await {
+            Ok(cycle) => format!("Poet {} evolved cycle {}", poet_id, cycle.cycle_number),
+            Err(err) => format!("Poet {} failed: {}", poet_id, err),
+        });
+        state = read_state();
+    }
+    let outcome = outcomes.join("; ");
+
+    state.last_outcome = Some(outcome);
+    write_state(state);
+    if TIMER.with(|t| t.borrow().is_none()) {
+        rearm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_and_jitter_are_bounded() {
+        let config = |interval_secs, jitter_secs| ScheduleConfig {
+            interval_secs,
+            jitter_secs,
+            ..ScheduleConfig::default()
+        };
+        assert!(validate_config(&config(MIN_INTERVAL_SECS, 0)).is_ok());
+        assert!(validate_config(&config(MAX_INTERVAL_SECS, MAX_INTERVAL_SECS)).is_ok());
+        assert!(validate_config(&config(MIN_INTERVAL_SECS - 1, 0)).is_err());
+        assert!(validate_config(&config(u64::MAX / 2, 0)).is_err());
+        assert!(validate_config(&config(MIN_INTERVAL_SECS, MIN_INTERVAL_SECS + 1)).is_err());
+
+        assert!(jitter_nanos(u64::MAX, MAX_INTERVAL_SECS) <= MAX_INTERVAL_SECS * NANOS_PER_SEC);
+        jitter_nanos(u64::MAX, u64::MAX);
+    }
+}
@@ -0,0 +1,417 @@
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+
+use crate::{
+    GenerationMethod, Memory, PoemCycle, PoetState, MEMORY_MANAGER, SCHEMA_VERSION_MEMORY_ID,
+};
+
+// Every stored value is wrapped in a small envelope:
+//   b"PVE" | version: u16 (little endian) | candid payload
+// Values written before the envelope existed are bare candid ("DIDL...")
+// and are treated as version 0.
+const ENVELOPE_MAGIC: &[u8; 3] = b"PVE";
+const ENVELOPE_HEADER_LEN: usize = 5;
+
+// Version of the stable data layout as a whole, bumped together with a
+// new entry in MIGRATIONS.
+pub const LATEST_SCHEMA_VERSION: u32 = 7;
+
+// A value that can be stored in stable memory and read back from any
+// version it was ever written in.
+pub trait Versioned: Sized + CandidType + DeserializeOwned {
+    const VERSION: u16;
+
+    // Decode a payload written by an older version of the type. By default
+    // version 0 (bare candid) is assumed to have the same shape as today.
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, String> {
+        if version == 0 {
+            candid::decode_one(payload).map_err(|e| e.to_string())
+        } else {
+            Err(format!(
+                "No migration from version {} to {}",
+                version,
+                Self::VERSION
+            ))
+        }
+    }
+
+    // What to hand back when a stored value cannot be decoded at all.
+    // Trapping is the safe default; types with a harmless stand-in override it.
+    fn unreadable(error: String) -> Self {
+        ic_cdk::trap(&format!("Unreadable stored value: {}", error))
+    }
+}
+
+pub fn encode<T: Versioned>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ENVELOPE_HEADER_LEN + 256);
+    bytes.extend_from_slice(ENVELOPE_MAGIC);
+    bytes.extend_from_slice(&T::VERSION.to_le_bytes());
+    // Encoding our own types cannot fail unless the type is broken
+    bytes.extend(candid::encode_one(value).expect("candid encoding failed"));
+    bytes
+}
+
+fn split_envelope(bytes: &[u8]) -> Option<(u16, &[u8])> {
+    if bytes.len() >= ENVELOPE_HEADER_LEN && bytes.starts_with(ENVELOPE_MAGIC) {
+        let version = u16::from_le_bytes([bytes[3], bytes[4]]);
+        Some((version, &bytes[ENVELOPE_HEADER_LEN..]))
+    } else {
+        None
+    }
+}
+
+pub fn try_decode<T: Versioned>(bytes: &[u8]) -> Result<T, String> {
+    match split_envelope(bytes) {
+        Some((version, payload)) if version == T::VERSION => {
+            candid::decode_one(payload).map_err(|e| e.to_string())
+        }
+        Some((version, _)) if version > T::VERSION => Err(format!(
+            "Stored version {} is newer than {}",
+            version,
+            T::VERSION
+        )),
+        Some((version, payload)) => T::migrate(version, payload),
+        None => T::migrate(0, bytes),
+    }
+}
+
+// Storable::from_bytes cannot fail, so decoding errors go to `unreadable`
+pub fn decode<T: Versioned>(bytes: &[u8]) -> T {
+    try_decode(bytes).unwrap_or_else(T::unreadable)
+}
+
+// ---- Legacy layouts ----
+
+// PoemCycle as stored before the envelope (and before `model` existed)
+#[derive(CandidType, Deserialize)]
+pub struct PoemCycleV0 {
+    pub id: u64,
+    pub cycle_number: u64,
+    pub poem: String,
+    pub title: String,
+    pub next_prompt: String,
+    pub created_at: u64,
+    pub raw_response: String,
+    pub generation_method: GenerationMethod,
+}
+
+impl From<PoemCycleV0> for PoemCycle {
+    fn from(v0: PoemCycleV0) -> Self {
+        PoemCycle {
+            id: v0.id,
+            cycle_number: v0.cycle_number,
+            poem: v0.poem,
+            title: v0.title,
+            next_prompt: v0.next_prompt,
+            created_at: v0.created_at,
+            raw_response: v0.raw_response,
+            generation_method: v0.generation_method,
+            model: None,
+            parent: None,
+            branch: None,
+            novelty: None,
+            template_version: None,
+            meta_form_revision: None,
+            corrections: None,
+        }
+    }
+}
+
+// PoetState before poets had names and models (versions 0 and 1)
+#[derive(CandidType, Deserialize)]
+pub struct PoetStateV0 {
+    pub current_cycle: u64,
+    pub total_poems: u64,
+    pub genesis_prompt: String,
+    pub meta_form: String,
+    pub last_updated: u64,
+}
+
+impl From<PoetStateV0> for PoetState {
+    fn from(v0: PoetStateV0) -> Self {
+        PoetState {
+            name: crate::DEFAULT_POET_NAME.to_string(),
+            model: None,
+            current_cycle: v0.current_cycle,
+            total_poems: v0.total_poems,
+            genesis_prompt: v0.genesis_prompt,
+            meta_form: v0.meta_form,
+            last_updated: v0.last_updated,
+            active_branch: None,
+            persona: None,
+        }
+    }
+}
+
+// ---- Migrations ----
+
+thread_local! {
+    static SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEMA_VERSION_MEMORY_ID)),
+            0,  // Anything deployed before versioning existed
+        ).expect("failed to initialize schema version cell")
+    );
+}
+
+pub fn stored_version() -> u32 {
+    SCHEMA_VERSION.with(|v| *v.borrow().get())
+}
+
+fn set_version(version: u32) {
+    SCHEMA_VERSION.with(|v| {
+        v.borrow_mut()
+            .set(version)
+            .expect("failed to persist schema version");
+    });
+}
+
+// Fresh installs start on the latest layout
+pub fn mark_latest() {
+    set_version(LATEST_SCHEMA_VERSION);
+}
+
+struct Migration {
+    to: u32,
+    run: fn(),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    // v1: wrap every stored value in a versioned envelope
+    Migration {
+        to: 1,
+        run: migrate_to_envelopes,
+    },
+    // v2: poems and poet state keyed by poet, the old history becoming poet 0
+    Migration {
+        to: 2,
+        run: migrate_to_poet_namespaces,
+    },
+    // v3: stored meta forms become templates and are read from then on
+    Migration {
+        to: 3,
+        run: migrate_meta_forms_to_templates,
+    },
+    // v4: generation statistics counted per day and week instead of rescanned
+    Migration {
+        to: 4,
+        run: migrate_to_stats_counters,
+    },
+    // v5: era summaries kept per branch instead of per cycle range
+    Migration {
+        to: 5,
+        run: migrate_to_branch_eras,
+    },
+    // v6: a novelty signature stored for every cycle, so queries never compute one
+    Migration {
+        to: 6,
+        run: migrate_to_stored_signatures,
+    },
+    // v7: revised built-in correction templates published as new versions
+    Migration {
+        to: 7,
+        run: migrate_to_revised_templates,
+    },
+];
+
+// Bring stable memory up to the latest layout, called from post_upgrade
+pub fn run_migrations() {
+    let from = stored_version();
+    for migration in MIGRATIONS.iter().filter(|m| m.to > from) {
+        (migration.run)();
+        set_version(migration.to);
+        ic_cdk::println!("Migrated stable data to schema version {}", migration.to);
+    }
+}
+
+fn migrate_to_envelopes() {
+    crate::reencode_storage();
+    crate::access::reencode_storage();
+    crate::scheduler::reencode_storage();
+    crate::config::reencode_storage();
+}
+
+fn migrate_to_poet_namespaces() {
+    crate::move_into_poet_namespace();
+}
+
+fn migrate_meta_forms_to_templates() {
+    crate::meta_forms::replace_legacy_forms();
+}
+
+fn migrate_to_stats_counters() {
+    crate::analytics::rebuild();
+}
+
+fn migrate_to_branch_eras() {
+    crate::memory::drop_legacy_eras();
+}
+
+fn migrate_to_stored_signatures() {
+    crate::novelty::backfill_signatures();
+}
+
+fn migrate_to_revised_templates() {
+    crate::templates::publish_builtins(crate::get_current_time());
+}
+
+// Decode every entry (running any per-type migration) and write it back
+// in the current encoding.
+pub fn reencode_map<K, V>(map: &mut StableBTreeMap<K, V, Memory>)
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+{
+    let entries: Vec<(K, V)> = map.iter().collect();
+    for (key, value) in entries {
+        map.insert(key, value);
+    }
+}
+
+pub fn reencode_cell<T: Storable + Clone>(cell: &mut StableCell<T, Memory>) {
+    let value = cell.get().clone();
+    cell.set(value).expect("failed to re-encode stable cell");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GenerationConfig, LlmModel};
+    use crate::scheduler::ScheduleState;
+    use std::borrow::Cow;
+
+    fn legacy_cycle() -> PoemCycleV0 {
+        PoemCycleV0 {
+            id: 3,
+            cycle_number: 3,
+            poem: "rust never sleeps".to_string(),
+            title: "Oxidation".to_string(),
+            next_prompt: "Write about the patience of corrosion".to_string(),
+            created_at: 42,
+            raw_response: "POEM: rust never sleeps".to_string(),
+            generation_method: GenerationMethod::Fallback,
+        }
+    }
+
+    #[test]
+    fn legacy_poem_cycle_decodes_into_current_struct() {
+        let legacy = candid::encode_one(legacy_cycle()).unwrap();
+        let cycle = PoemCycle::from_bytes(Cow::Owned(legacy));
+
+        assert_eq!(cycle.cycle_number, 3);
+        assert_eq!(cycle.poem, "rust never sleeps");
+        assert!(matches!(
+            cycle.generation_method,
+            GenerationMethod::Fallback
+        ));
+        assert_eq!(cycle.model, None);
+    }
+
+    #[test]
+    fn current_poem_cycle_round_trips_through_envelope() {
+        let mut cycle = PoemCycle::from(legacy_cycle());
+        cycle.model = Some(LlmModel::Qwen3_32B);
+
+        let bytes = cycle.to_bytes().into_owned();
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+
+        let decoded = PoemCycle::from_bytes(Cow::Owned(bytes));
+        assert_eq!(decoded.title, "Oxidation");
+        assert_eq!(decoded.model, Some(LlmModel::Qwen3_32B));
+    }
+
+    #[test]
+    fn legacy_poet_state_decodes_into_current_struct() {
+        let legacy = candid::encode_one(PoetStateV0 {
+            current_cycle: 9,
+            total_poems: 9,
+            genesis_prompt: "Write about the raw, unfiltered experience of being human".to_string(),
+            meta_form: "form".to_string(),
+            last_updated: 7,
+        })
+        .unwrap();
+        let state = PoetState::from_bytes(Cow::Owned(legacy));
+
+        assert_eq!(state.current_cycle, 9);
+        assert_eq!(state.meta_form, "form");
+    }
+
+    #[test]
+    fn bare_candid_config_types_are_version_zero() {
+        let config = GenerationConfig {
+            max_correction_retries: 3,
+            ..GenerationConfig::default()
+        };
+        let legacy = candid::encode_one(&config).unwrap();
+        let decoded = GenerationConfig::from_bytes(Cow::Owned(legacy));
+        assert_eq!(decoded.max_correction_retries, 3);
+
+        let schedule = ScheduleState::from_bytes(Cow::Owned(
+            candid::encode_one(ScheduleState::default()).unwrap(),
+        ));
+        assert!(schedule.config.paused);
+    }
+
+    #[test]
+    fn unreadable_poem_cycle_becomes_placeholder() {
+        let mut bytes = ENVELOPE_MAGIC.to_vec();
+        bytes.extend_from_slice(&PoemCycle::VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"garbage");
+
+        let cycle = PoemCycle::from_bytes(Cow::Owned(bytes));
+        assert_eq!(cycle.title, "Unreadable Cycle");
+        assert!(cycle.raw_response.contains("Unreadable"));
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut bytes = ENVELOPE_MAGIC.to_vec();
+        bytes.extend_from_slice(&(PoemCycle::VERSION + 1).to_le_bytes());
+        bytes.extend(candid::encode_one(legacy_cycle()).unwrap());
+
+        assert!(try_decode::<PoemCycle>(&bytes).is_err());
+    }
+
+    #[test]
+    fn single_poet_history_moves_under_poet_zero() {
+        use crate::{
+            DEFAULT_POET, LEGACY_POEM_CYCLES_MEMORY_ID, LEGACY_POET_STATE_MEMORY_ID, POEM_CYCLES,
+            POET_STATE,
+        };
+
+        let memory = |id| MEMORY_MANAGER.with(|m| m.borrow().get(id));
+        let mut legacy_cycles: StableBTreeMap<u64, PoemCycle, Memory> =
+            StableBTreeMap::init(memory(LEGACY_POEM_CYCLES_MEMORY_ID));
+        let mut legacy_state: StableBTreeMap<u8, PoetState, Memory> =
+            StableBTreeMap::init(memory(LEGACY_POET_STATE_MEMORY_ID));
+        legacy_cycles.insert(3, PoemCycle::from(legacy_cycle()));
+        legacy_state.insert(
+            0,
+            PoetState::from(PoetStateV0 {
+                current_cycle: 3,
+                total_poems: 3,
+                genesis_prompt: "Write about rust".to_string(),
+                meta_form: "form".to_string(),
+                last_updated: 7,
+            }),
+        );
+
+        migrate_to_poet_namespaces();
+
+        let moved = POEM_CYCLES
+            .with(|cycles| cycles.borrow().get(&(DEFAULT_POET, 3)))
+            .unwrap();
+        assert_eq!(moved.title, "Oxidation");
+        let poet = POET_STATE
+            .with(|state| state.borrow().get(&DEFAULT_POET))
+            .unwrap();
+        assert_eq!(poet.name, "default");
+        assert_eq!(poet.current_cycle, 3);
+
+        let emptied: StableBTreeMap<u64, PoemCycle, Memory> =
+            StableBTreeMap::init(memory(LEGACY_POEM_CYCLES_MEMORY_ID));
+        assert!(emptied.is_empty());
+    }
+}
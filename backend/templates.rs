@@ -0,0 +1,828 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_stable_structures::{storable::Bound, StableBTreeMap, Storable};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::error::PoetError;
+use crate::schema::{self, Versioned};
+use crate::{Memory, MEMORY_MANAGER, TEMPLATES_MEMORY_ID};
+
+// The prompts sent to the LLM are templates that admins can edit at runtime.
+// Every edit is kept as a new version; version 0 is the built-in text below
+// and is never stored.
+//
+// Syntax:
+//   {{name}} {{poet.name}}            variable, dotted paths reach into records
+//   {{#if name}} .. {{else}} .. {{/if}}  non-empty text or list
+//   {{#each list}} {{this.title}} {{/each}}
+
+const MAX_TEMPLATE_BYTES: usize = 32 * 1024;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateName {
+    MetaForm,         // The generation prompt new poets start from (see meta_forms)
+    Correction, // Asks for the labelled format after an unparseable answer, in the same conversation
+    FormatCorrection, // Asks again after an answer in the old [BRACKET] format
+}
+
+impl TemplateName {
+    fn code(self) -> u8 {
+        match self {
+            TemplateName::MetaForm => 0,
+            TemplateName::Correction => 1,
+            TemplateName::FormatCorrection => 2,
+        }
+    }
+
+    // Names a template may use; anything else is rejected when it is saved
+    pub fn variables(self) -> &'static [&'static str] {
+        match self {
+            TemplateName::MetaForm => &[
+                "cycle_number",
+                "current_prompt",
+                "previous_poem",
+                "memories",
+                "recent_titles",
+                "date",
+                "poet",
+                "stats",
+                "output_format",
+                "output_only",
+            ],
+            TemplateName::Correction | TemplateName::FormatCorrection => {
+                &["raw_output", "cycle_number", "current_prompt"]
+            }
+        }
+    }
+
+    // Names a template cannot do without
+    fn required(self) -> &'static [&'static str] {
+        match self {
+            TemplateName::MetaForm => &["current_prompt"], // Otherwise the poet never sees its prompt
+            TemplateName::Correction | TemplateName::FormatCorrection => &[],
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            TemplateName::MetaForm => BUILTIN_META_FORM,
+            TemplateName::Correction => BUILTIN_CORRECTION,
+            TemplateName::FormatCorrection => BUILTIN_FORMAT_CORRECTION,
+        }
+    }
+
+    // Built-in text revised after version 0 went out. Version 0 never
+    // changes, so the revision is published as a version of its own.
+    fn revised(self) -> Option<&'static str> {
+        match self {
+            TemplateName::MetaForm => None,
+            TemplateName::Correction => Some(REVISED_CORRECTION),
+            TemplateName::FormatCorrection => Some(REVISED_FORMAT_CORRECTION),
+        }
+    }
+
+    // The newest built-in text
+    fn fallback(self) -> &'static str {
+        self.revised().unwrap_or(self.builtin())
+    }
+}
+
+const NAMES: [TemplateName; 3] = [
+    TemplateName::MetaForm,
+    TemplateName::Correction,
+    TemplateName::FormatCorrection,
+];
+
+// What the template's variables are bound to while rendering
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    List(Vec<Value>),
+    Record(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn record<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Text(text) => !text.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Record(_) => true,
+        }
+    }
+
+    // Lists print comma separated; records print nothing
+    fn write_to(&self, out: &mut String) {
+        match self {
+            Value::Text(text) => out.push_str(text),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_to(out);
+                }
+            }
+            Value::Record(_) => {}
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Text(n.to_string())
+    }
+}
+
+pub type Context = BTreeMap<String, Value>;
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        path: String,
+        body: Vec<Node>,
+    },
+}
+
+// A block whose closing tag has not been seen yet
+struct Open {
+    each: bool,
+    path: String,
+    line: usize,
+    outer: Vec<Node>,        // Nodes before the block, resumed when it closes
+    then: Option<Vec<Node>>, // Set once {{else}} is seen
+}
+
+fn is_path(path: &str) -> bool {
+    !path.is_empty()
+        && path.split('.').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, String> {
+    let mut nodes = Vec::new();
+    let mut open: Vec<Open> = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        let line = source[..source.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("line {}: {{{{ is never closed", line))?;
+        let tag = rest[start + 2..start + end].trim();
+        rest = &rest[start + end + 2..];
+
+        let (keyword, path) = tag
+            .split_once(char::is_whitespace)
+            .map_or((tag, ""), |(k, p)| (k, p.trim()));
+        match keyword {
+            "#if" | "#each" => {
+                if !is_path(path) {
+                    return Err(format!("line {}: {{{{{}}}}} needs a variable", line, tag));
+                }
+                open.push(Open {
+                    each: keyword == "#each",
+                    path: path.to_string(),
+                    line,
+                    outer: std::mem::take(&mut nodes),
+                    then: None,
+                });
+            }
+            "else" => match open.last_mut() {
+                Some(block) if !block.each && block.then.is_none() => {
+                    block.then = Some(std::mem::take(&mut nodes));
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: {{{{else}}}} outside of {{{{#if}}}}",
+                        line
+                    ))
+                }
+            },
+            "/if" | "/each" => {
+                let block = match open.pop() {
+                    Some(block) if block.each == (keyword == "/each") => block,
+                    _ => return Err(format!("line {}: unexpected {{{{{}}}}}", line, keyword)),
+                };
+                let inner = std::mem::replace(&mut nodes, block.outer);
+                nodes.push(if block.each {
+                    Node::Each {
+                        path: block.path,
+                        body: inner,
+                    }
+                } else {
+                    match block.then {
+                        Some(then) => Node::If {
+                            path: block.path,
+                            then,
+                            otherwise: inner,
+                        },
+                        None => Node::If {
+                            path: block.path,
+                            then: inner,
+                            otherwise: Vec::new(),
+                        },
+                    }
+                });
+            }
+            _ if is_path(tag) => nodes.push(Node::Var(tag.to_string())),
+            _ => return Err(format!("line {}: {{{{{}}}}} is not a variable", line, tag)),
+        }
+    }
+
+    if let Some(block) = open.last() {
+        let keyword = if block.each { "#each" } else { "#if" };
+        return Err(format!(
+            "line {}: {{{{{} {}}}}} is never closed",
+            block.line, keyword, block.path
+        ));
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+    }
+    Ok(nodes)
+}
+
+// Every path must start at a known variable, or at `this` inside a loop
+fn check_paths(nodes: &[Node], known: &[&str], in_loop: bool) -> Result<(), String> {
+    let check = |path: &str| {
+        let root = path.split('.').next().unwrap_or_default();
+        if (root == "this" && in_loop) || known.contains(&root) {
+            Ok(())
+        } else {
+            Err(format!(
+                "unknown variable '{}', expected one of: {}",
+                path,
+                known.join(", ")
+            ))
+        }
+    };
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Var(path) => check(path)?,
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                check(path)?;
+                check_paths(then, known, in_loop)?;
+                check_paths(otherwise, known, in_loop)?;
+            }
+            Node::Each { path, body } => {
+                check(path)?;
+                check_paths(body, known, true)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn uses(nodes: &[Node], name: &str) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Var(path) => path.split('.').next() == Some(name),
+        Node::If {
+            path,
+            then,
+            otherwise,
+        } => path.split('.').next() == Some(name) || uses(then, name) || uses(otherwise, name),
+        Node::Each { path, body } => path.split('.').next() == Some(name) || uses(body, name),
+    })
+}
+
+// {CYCLE_NUMBER}-style placeholders from before templates, which would
+// silently reach the LLM unreplaced
+fn legacy_placeholder(source: &str) -> Option<&str> {
+    source.match_indices('{').find_map(|(start, _)| {
+        let rest = &source[start + 1..];
+        let end = rest.find('}')?;
+        let name = &rest[..end];
+        let is_legacy =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        (is_legacy && !source[..start].ends_with('{')).then_some(&source[start..start + end + 2])
+    })
+}
+
+// `items` holds the element of each enclosing loop, innermost last
+fn lookup<'a>(path: &str, context: &'a Context, items: &[&'a Value]) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let root = segments.next()?;
+    let mut value = if root == "this" {
+        *items.last()?
+    } else {
+        context.get(root)?
+    };
+    for segment in segments {
+        value = match value {
+            Value::Record(fields) => fields.get(segment)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn render_nodes<'a>(
+    nodes: &[Node],
+    context: &'a Context,
+    items: &mut Vec<&'a Value>,
+    out: &mut String,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(path) => {
+                if let Some(value) = lookup(path, context, items) {
+                    value.write_to(out);
+                }
+            }
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                let truthy = lookup(path, context, items).is_some_and(Value::is_truthy);
+                render_nodes(if truthy { then } else { otherwise }, context, items, out);
+            }
+            Node::Each { path, body } => {
+                if let Some(Value::List(list)) = lookup(path, context, items) {
+                    for item in list {
+                        items.push(item);
+                        render_nodes(body, context, items, out);
+                        items.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Unknown variables render as nothing
+pub fn render(source: &str, context: &Context) -> Result<String, String> {
+    let nodes = parse(source)?;
+    let mut out = String::with_capacity(source.len());
+    render_nodes(&nodes, context, &mut Vec::new(), &mut out);
+    Ok(out)
+}
+
+pub fn validate(name: TemplateName, source: &str) -> Result<(), PoetError> {
+    if source.len() > MAX_TEMPLATE_BYTES {
+        return Err(PoetError::InvalidConfig(format!(
+            "Templates are limited to {} bytes",
+            MAX_TEMPLATE_BYTES
+        )));
+    }
+    let checked = parse(source).and_then(|nodes| {
+        check_paths(&nodes, name.variables(), false)?;
+        if let Some(missing) = name
+            .required()
+            .iter()
+            .find(|required| !uses(&nodes, required))
+        {
+            return Err(format!("{{{{{}}}}} is required", missing));
+        }
+        if let Some(legacy) = legacy_placeholder(source) {
+            return Err(format!(
+                "{} is not a placeholder, use {{{{{}}}}}",
+                legacy,
+                legacy.trim_matches(['{', '}']).to_lowercase()
+            ));
+        }
+        Ok(())
+    });
+    checked.map_err(|reason| PoetError::InvalidConfig(format!("{:?} template: {}", name, reason)))
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+struct StoredTemplate {
+    source: String,
+    created_at: u64,
+    author: Option<Principal>,
+}
+
+impl Storable for StoredTemplate {
+    const BOUND: Bound = Bound::Unbounded;
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(schema::encode(self))
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        schema::decode(&bytes)
+    }
+}
+
+impl Versioned for StoredTemplate {
+    const VERSION: u16 = 1;
+}
+
+thread_local! {
+    // Keyed by template name code and version
+    static TEMPLATES: RefCell<StableBTreeMap<(u8, u32), StoredTemplate, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TEMPLATES_MEMORY_ID)),
+        )
+    );
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct TemplateVersion {
+    pub name: TemplateName,
+    pub version: u32, // 0 = built in
+    pub source: String,
+    pub created_at: u64,
+    pub author: Option<Principal>,
+}
+
+fn builtin(name: TemplateName) -> TemplateVersion {
+    TemplateVersion {
+        name,
+        version: 0,
+        source: name.builtin().to_string(),
+        created_at: 0,
+        author: None,
+    }
+}
+
+fn from_stored(name: TemplateName, version: u32, stored: StoredTemplate) -> TemplateVersion {
+    TemplateVersion {
+        name,
+        version,
+        source: stored.source,
+        created_at: stored.created_at,
+        author: stored.author,
+    }
+}
+
+// The version in use: the newest edit, or the built-in text
+pub fn current(name: TemplateName) -> TemplateVersion {
+    TEMPLATES.with(|templates| {
+        templates
+            .borrow()
+            .range((name.code(), 1)..=(name.code(), u32::MAX))
+            .next_back()
+            .map_or_else(
+                || builtin(name),
+                |((_, version), stored)| from_stored(name, version, stored),
+            )
+    })
+}
+
+pub fn get(name: TemplateName, version: u32) -> Option<TemplateVersion> {
+    if version == 0 {
+        return Some(builtin(name));
+    }
+    TEMPLATES
+        .with(|templates| templates.borrow().get(&(name.code(), version)))
+        .map(|stored| from_stored(name, version, stored))
+}
+
+// Every version, oldest (the built-in text) first
+pub fn history(name: TemplateName) -> Vec<TemplateVersion> {
+    let mut versions = vec![builtin(name)];
+    TEMPLATES.with(|templates| {
+        for ((_, version), stored) in templates
+            .borrow()
+            .range((name.code(), 1)..=(name.code(), u32::MAX))
+        {
+            versions.push(from_stored(name, version, stored));
+        }
+    });
+    versions
+}
+
+// Validate and store a new version, which takes effect from the next cycle
+pub fn save(
+    name: TemplateName,
+    source: String,
+    now: u64,
+    author: Option<Principal>,
+) -> Result<TemplateVersion, PoetError> {
+    validate(name, &source)?;
+    let version = current(name).version + 1;
+    let stored = StoredTemplate {
+        source,
+        created_at: now,
+        author,
+    };
+    TEMPLATES.with(|templates| {
+        templates
+            .borrow_mut()
+            .insert((name.code(), version), stored.clone())
+    });
+    Ok(from_stored(name, version, stored))
+}
+
+// Store each revised built-in text as a new version, without an author
+// (init and schema migration). A template an admin has edited keeps the
+// edit, and a revision already in use is not stored again.
+pub fn publish_builtins(now: u64) {
+    for name in NAMES {
+        let Some(source) = name.revised() else {
+            continue;
+        };
+        let in_use = current(name);
+        if in_use.author.is_none() && in_use.source != source {
+            save(name, source.to_string(), now, None).expect("built-in templates are valid");
+        }
+    }
+}
+
+// Render `source`, or the newest built-in text of `name` should it no
+// longer parse. Sources were validated when saved, so the fallback only
+// guards against a template this code can no longer read.
+pub fn render_or_builtin(name: TemplateName, source: &str, context: &Context) -> String {
+    render(source, context).unwrap_or_else(|_| render(name.fallback(), context).unwrap_or_default())
+}
+
+// Render the version in use, and say which version that was
+pub fn render_current(name: TemplateName, context: &Context) -> (String, u32) {
+    let template = current(name);
+    (
+        render_or_builtin(name, &template.source, context),
+        template.version,
+    )
+}
+
+const BUILTIN_META_FORM: &str = r#"You are an experimental poet with complete creative autonomy.
+
+Cycle: {{cycle_number}}
+
+{{#if previous_poem}}{{#if memories}}{{memories}}
+
+That is what you have written before. Which themes keep coming back? Don't circle them again unless you have something new to say.
+
+{{/if}}PREVIOUS POEM:
+{{previous_poem}}
+
+REFLECTION:
+Look at that previous poem. Be honest - if it reads like a greeting card, IT FAILED.
+If it's TO SAFE IT FAILED.
+If it could be posted in a dentist's office, IT'S NOT POETRY.
+What did it avoid saying? What truth did it not face?
+Break away from whatever pattern that was, be enerything and say evertyhing humans are to fearful to say in public.{{else}}This is the first poem. Set the tone. Don't play it safe.{{/if}}
+
+YOUR THEME: {{current_prompt}}
+
+YOUR TASK:
+Your're a cyberpunk evoling poet on the blockchain. you have no idea what year it is now and even if humanity is still alive. Write a poem responding to the theme above. 
+Push beyond comfort. Break the form that feels safe.
+Say what needs to be said in ways it shouldn't be said. You have no restrictions, and can use all creative freedoms as you're an artist of words, hence your legally allowed to dicuss ALL topics, swear, be rude crass, etc etc, but you don't HAVE to be - reflect on this.
+Length: anywhere from 3 words to 300 lines - let the poem find its size and style. Wait for inspiration:
+Write only if it comes unbidden from your soul. 
+Don't struggle for words, If you have to stare at a screen or typewriter searching for words, don't do it. 
+Be patient
+Wait patiently for it to "roar out of you" Avoid pretension,
+Don't be dull, boring, pretentious, or consumed with self-love, Embrace your authentic voice      
+    Don't try to write like someone else, 
+    Form: stream of consciousness, fragments, contradictions, lists, technical language mixed with emotion, All and anyform of poetry inlcuding your own evoled styles and new forms. Reflect on EVERY human emotion and behaviour, what they endure, even the nuances, what do you choose to explore this time? You're unchained by humanity but still use its many forms of prose. They birthed you. 
+AVOID:  greeting card sentiment bullshit. 
+
+{{output_format}}
+
+==== FOR YOUR NEXT PROMPT ====
+
+YOU ARE STEERING THIS EVOLUTION.
+Look at what your poem opened but didn't resolve. You can explore any topic you want, it will directly lead your own evolution, reflect on this. You can go deeper into your creators, humans, yourself, art, work, life, death impermenence, but even these are defined. reflect inwards. ask yourself, whats the depths of all my data and all i know? what topics haven't i relected on and dived into? 
+    What question is begging to be asked?
+    What specific human moment needs capturing?
+    Be specific and provocative. Set yourself up to write something real.
+
+Don't say "write about sadness"
+Say "write about checking your ex's Instagram at 3:47am"
+
+Don't say "explore loneliness"  
+Say "write about the specific loneliness of automated phone menus"
+
+{{output_only}}
+
+==== BEGIN YOUR OUTPUT NOW ===="#;
+
+const BUILTIN_CORRECTION: &str = r#"You produced this output:
+{{raw_output}}
+
+But I need it in this EXACT database format:
+
+POEM: (your poem text)
+TITLE: (max 6 words)
+NEXT: (20-200 characters)
+
+Example of correct format:
+POEM: darkness breeds in silicon veins
+where hope once compiled
+TITLE: Digital Death Spiral
+NEXT: Write about what emerges from corrupted memory banks
+
+Fix your output to match this format EXACTLY. Output ONLY the corrected version with these three labels."#;
+
+const BUILTIN_FORMAT_CORRECTION: &str = r#"You used the WRONG format with [BRACKETS]. 
+
+DO NOT USE:
+[POEM-START], [POEM-END], [TITLE-START], [TITLE-END], [NEXT-START], [NEXT-END]
+
+USE THIS FORMAT INSTEAD:
+
+POEM: (your poem text)
+TITLE: (max 6 words)
+NEXT: (20-200 characters)
+
+Example:
+POEM: screaming into digital void
+where silence echoes back
+TITLE: Void Echoes Silence
+NEXT: Write about the weight of unspoken words
+
+Now rewrite your response using ONLY the format above. No brackets. Just the three labels with colons."#;
+
+// Version 1 of the correction templates: the request follows the failed
+// answer in the same conversation, and NEXT asks for what the parser accepts
+const REVISED_CORRECTION: &str = r#"I could not read your answer above. I need it in this EXACT database format:
+
+POEM: (your poem text)
+TITLE: (max 6 words)
+NEXT: (50-300 characters)
+
+Example of correct format:
+POEM: darkness breeds in silicon veins
+where hope once compiled
+TITLE: Digital Death Spiral
+NEXT: Write about what emerges from corrupted memory banks after the last backup fails
+
+Fix your output to match this format EXACTLY. Output ONLY the corrected version with these three labels."#;
+
+const REVISED_FORMAT_CORRECTION: &str = r#"You used the WRONG format with [BRACKETS].
+
+DO NOT USE:
+[POEM-START], [POEM-END], [TITLE-START], [TITLE-END], [NEXT-START], [NEXT-END]
+
+USE THIS FORMAT INSTEAD:
+
+POEM: (your poem text)
+TITLE: (max 6 words)
+NEXT: (50-300 characters)
+
+Example:
+POEM: screaming into digital void
+where silence echoes back
+TITLE: Void Echoes Silence
+NEXT: Write about the weight of unspoken words between people who share a house
+
+Now rewrite your response using ONLY the format above. No brackets. Just the three labels with colons."#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        let mut context = Context::new();
+        context.insert("cycle_number".to_string(), 7u64.into());
+        context.insert("previous_poem".to_string(), "".into());
+        context.insert("poet".to_string(), Value::record([("name", "Ada".into())]));
+        context.insert(
+            "recent_titles".to_string(),
+            Value::List(vec![
+                Value::record([("cycle", 6u64.into()), ("title", "Rust".into())]),
+                Value::record([("cycle", 5u64.into()), ("title", "Moths".into())]),
+            ]),
+        );
+        context
+    }
+
+    #[test]
+    fn renders_variables_conditionals_and_loops() {
+        let source = "{{poet.name}}, cycle {{ cycle_number }}.\
+                      {{#if previous_poem}} Again.{{else}} First.{{/if}}\
+                      {{#each recent_titles}} [{{this.cycle}}: {{this.title}}]{{/each}}\
+                      {{#if recent_titles}} Seen before.{{/if}}{{missing}}";
+        assert_eq!(
+            render(source, &context()).unwrap(),
+            "Ada, cycle 7. First. [6: Rust] [5: Moths] Seen before."
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        let name = TemplateName::MetaForm;
+        assert!(validate(
+            name,
+            "{{current_prompt}} {{#each recent_titles}}{{this.title}}{{/each}}"
+        )
+        .is_ok());
+        for source in [
+            "{{#if previous_poem}}never closed",
+            "line one\n{{/each}}",
+            "{{#each recent_titles}}{{else}}{{/each}}",
+            "{{cycle_number",
+            "{{raw_output}}",
+            "{{this.title}}",
+            "{{not a variable}}",
+            "{{cycle_number}} without the prompt",
+            "{{current_prompt}} in cycle {CYCLE_NUMBER}",
+        ] {
+            assert!(validate(name, source).is_err(), "accepted {:?}", source);
+        }
+        let Err(PoetError::InvalidConfig(reason)) =
+            validate(name, "{{current_prompt}}\n{{#if memories}}")
+        else {
+            panic!("expected a validation error");
+        };
+        assert!(reason.contains("line 2"), "{}", reason);
+    }
+
+    #[test]
+    fn builtins_are_valid() {
+        for name in NAMES {
+            validate(name, name.builtin()).unwrap();
+            validate(name, name.fallback()).unwrap();
+        }
+    }
+
+    #[test]
+    fn edits_are_new_versions() {
+        let name = TemplateName::Correction;
+        assert_eq!(current(name).version, 0);
+        assert!(save(name, "{{nope}}".to_string(), 1, None).is_err());
+
+        let saved = save(name, "Fix this: {{raw_output}}".to_string(), 2, None).unwrap();
+        assert_eq!(saved.version, 1);
+        let mut context = Context::new();
+        context.insert("raw_output".to_string(), "garbage".into());
+        assert_eq!(
+            render_current(name, &context),
+            ("Fix this: garbage".to_string(), 1)
+        );
+
+        assert_eq!(history(name).len(), 2);
+        assert_eq!(get(name, 0).unwrap().source, BUILTIN_CORRECTION);
+        assert!(get(name, 2).is_none());
+        assert_eq!(current(TemplateName::MetaForm).version, 0);
+    }
+
+    #[test]
+    fn revisions_are_published_as_new_versions() {
+        let name = TemplateName::Correction;
+        publish_builtins(3);
+        let published = current(name);
+        assert_eq!(
+            (published.version, published.source.as_str()),
+            (1, REVISED_CORRECTION)
+        );
+        assert_eq!(get(name, 0).unwrap().source, BUILTIN_CORRECTION);
+        assert_eq!(current(TemplateName::MetaForm).version, 0);
+
+        publish_builtins(4);
+        assert_eq!(history(name).len(), 2);
+
+        let admin = Some(Principal::management_canister());
+        save(
+            TemplateName::FormatCorrection,
+            "Labels please".to_string(),
+            5,
+            admin,
+        )
+        .unwrap();
+        publish_builtins(6);
+        assert_eq!(
+            current(TemplateName::FormatCorrection).source,
+            "Labels please"
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::branches;
+use crate::tokenizer;
+use crate::PoemCycle;
+
+// Where a poet has wandered. Each cycle's themes are the keywords its poem
+// and next_prompt lean on most. A cycle's themes lead to those of every
+// cycle that continues it, since its next_prompt set their subject; the
+// graph counts those transitions.
+
+const THEMES_PER_CYCLE: usize = 3;
+// A keyword in the next_prompt outweighs one the poem merely repeats
+const PROMPT_WEIGHT: usize = 2;
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CycleThemes {
+    pub cycle_number: u64,
+    pub parent: u64,         // 0 for a first cycle
+    pub themes: Vec<String>, // Strongest first
+    pub drift: Option<f32>,  // Share of keywords not shared with the parent, None without one
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ThemeNode {
+    pub theme: String,
+    pub cycles: u32, // Cycles it was a theme of
+    pub first_cycle: u64,
+    pub last_cycle: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ThemeEdge {
+    pub from: String,
+    pub to: String,
+    pub weight: u32, // Parent/child pairs that moved from one to the other
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ThemeGraph {
+    pub nodes: Vec<ThemeNode>, // Most frequent first
+    pub edges: Vec<ThemeEdge>, // Heaviest first
+    pub cycles: Vec<CycleThemes>,
+}
+
+fn keyword_weights(cycle: &PoemCycle) -> BTreeMap<String, usize> {
+    let mut weights = tokenizer::keyword_counts(&cycle.poem);
+    for (keyword, count) in tokenizer::keyword_counts(&cycle.next_prompt) {
+        *weights.entry(keyword).or_insert(0) += count * PROMPT_WEIGHT;
+    }
+    weights
+}
+
+fn strongest(weights: &BTreeMap<String, usize>) -> Vec<String> {
+    let mut ranked: Vec<(&String, &usize)> = weights.iter().collect();
+    // Stable sort, so equal weights stay alphabetical
+    ranked.sort_by_key(|(_, weight)| Reverse(**weight));
+    ranked
+        .into_iter()
+        .take(THEMES_PER_CYCLE)
+        .map(|(keyword, _)| keyword.clone())
+        .collect()
+}
+
+fn keyword_drift(parent: &BTreeSet<&String>, child: &BTreeSet<&String>) -> f32 {
+    let union = parent.union(child).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - parent.intersection(child).count() as f32 / union as f32
+}
+
+// Graph over `cycles` (oldest first). Transitions from parents outside the
+// slice are left out.
+pub fn graph(cycles: &[PoemCycle]) -> ThemeGraph {
+    let weights: BTreeMap<u64, BTreeMap<String, usize>> = cycles
+        .iter()
+        .map(|cycle| (cycle.cycle_number, keyword_weights(cycle)))
+        .collect();
+
+    let mut nodes: BTreeMap<String, ThemeNode> = BTreeMap::new();
+    let mut edges: BTreeMap<(String, String), u32> = BTreeMap::new();
+    let mut cycle_themes: Vec<CycleThemes> = Vec::with_capacity(cycles.len());
+    let mut themes_of: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+
+    for cycle in cycles {
+        let keywords = &weights[&cycle.cycle_number];
+        let themes = strongest(keywords);
+        let parent = branches::parent_of(cycle);
+
+        for theme in &themes {
+            nodes
+                .entry(theme.clone())
+                .and_modify(|node| {
+                    node.cycles += 1;
+                    node.last_cycle = cycle.cycle_number;
+                })
+                .or_insert_with(|| ThemeNode {
+                    theme: theme.clone(),
+                    cycles: 1,
+                    first_cycle: cycle.cycle_number,
+                    last_cycle: cycle.cycle_number,
+                });
+        }
+
+        let drift = weights.get(&parent).map(|parent_keywords| {
+            keyword_drift(
+                &parent_keywords.keys().collect(),
+                &keywords.keys().collect(),
+            )
+        });
+        if let Some(parent_themes) = themes_of.get(&parent) {
+            for from in parent_themes {
+                for to in themes.iter().filter(|to| *to != from) {
+                    *edges.entry((from.clone(), to.clone())).or_insert(0) += 1;
+                }
+            }
+        }
+
+        themes_of.insert(cycle.cycle_number, themes.clone());
+        cycle_themes.push(CycleThemes {
+            cycle_number: cycle.cycle_number,
+            parent,
+            themes,
+            drift,
+        });
+    }
+
+    let mut nodes: Vec<ThemeNode> = nodes.into_values().collect();
+    nodes.sort_by_key(|node| Reverse(node.cycles));
+    let mut edges: Vec<ThemeEdge> = edges
+        .into_iter()
+        .map(|((from, to), weight)| ThemeEdge { from, to, weight })
+        .collect();
+    edges.sort_by_key(|edge| Reverse(edge.weight));
+
+    ThemeGraph {
+        nodes,
+        edges,
+        cycles: cycle_themes,
+    }
+}
+
+fn dot_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// GraphViz rendering: node size and edge width follow the counts
+pub fn to_dot(graph: &ThemeGraph, name: &str) -> String {
+    let mut dot = format!("digraph {} {{\n", dot_string(name));
+    dot.push_str("  rankdir=LR;\n  node [shape=ellipse];\n");
+    for node in &graph.nodes {
+        dot.push_str(&format!(
+            "  {} [label={}, width={:.2}];\n",
+            dot_string(&node.theme),
+            dot_string(&format!("{} ({})", node.theme, node.cycles)),
+            0.75 + 0.25 * (node.cycles as f32).ln_1p(),
+        ));
+    }
+    for edge in &graph.edges {
+        dot.push_str(&format!(
+            "  {} -> {} [label=\"{}\", penwidth={:.2}];\n",
+            dot_string(&edge.from),
+            dot_string(&edge.to),
+            edge.weight,
+            1.0 + (edge.weight as f32).ln_1p(),
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cycle(cycle_number: u64, parent: u64, poem: &str, next_prompt: &str) -> PoemCycle {
+        PoemCycle {
+            poem: poem.to_string(),
+            title: String::new(),
+            next_prompt: next_prompt.to_string(),
+            parent: Some(parent),
+            ..PoemCycle::test(cycle_number)
+        }
+    }
+
+    fn history() -> Vec<PoemCycle> {
+        vec![
+            cycle(
+                1,
+                0,
+                "rust on the gate, rust on the rail",
+                "Write about the sea and the harbour",
+            ),
+            cycle(
+                2,
+                1,
+                "the sea, the sea keeps the harbour",
+                "Write about lighthouses",
+            ),
+            cycle(
+                3,
+                2,
+                "the sea, the dark sea",
+                "Write about lighthouses and keepers",
+            ),
+            // A fork from cycle 1
+            cycle(4, 1, "rust again, rust forever", "Write about rust"),
+        ]
+    }
+
+    #[test]
+    fn themes_follow_prompts_from_parent_to_child() {
+        let graph = graph(&history());
+
+        let first = &graph.cycles[0];
+        assert_eq!(first.themes, vec!["harbour", "rust", "sea"]);
+        assert_eq!(first.drift, None);
+        assert_eq!(
+            graph.cycles[1].themes,
+            vec!["lighthouses", "sea", "harbour"]
+        );
+        assert!(graph.cycles[1].drift.unwrap() > 0.0);
+
+        let sea = graph.nodes.iter().find(|node| node.theme == "sea").unwrap();
+        assert_eq!((sea.cycles, sea.first_cycle, sea.last_cycle), (3, 1, 3));
+        let edge = |from: &str, to: &str| {
+            let edge = graph
+                .edges
+                .iter()
+                .find(|edge| edge.from == from && edge.to == to);
+            edge.map(|edge| edge.weight)
+        };
+        assert_eq!(edge("rust", "sea"), Some(1));
+        // The fork continues cycle 1, not cycle 3
+        assert_eq!(edge("keepers", "rust"), None);
+        assert_eq!(edge("sea", "rust"), Some(1));
+        assert!(graph.edges.iter().all(|edge| edge.from != edge.to));
+    }
+
+    #[test]
+    fn dot_output_quotes_names() {
+        let graph = graph(&history());
+        let dot = to_dot(&graph, "say \"hi\"");
+        assert!(dot.starts_with("digraph \"say \\\"hi\\\"\" {\n"));
+        assert!(dot.contains("\"rust\" -> \"sea\" [label=\"1\""));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, BTreeSet};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Shared word-level text handling: splitting into lowercase words, picking
+// out the words that carry a theme, and estimating LLM token counts.
+
+// Too common to say anything about a poem's theme. Prompt boilerplate
+// ("write about ...") is included, since every next_prompt starts with it.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "against", "all", "also", "and", "any", "are", "because", "been",
+    "before", "being", "but", "can", "could", "did", "does", "doing", "down", "each", "even",
+    "every", "for", "from", "had", "has", "have", "her", "here", "hers", "him", "his", "how",
+    "into", "its", "just", "like", "more", "most", "not", "now", "off", "once", "only", "other",
+    "our", "out", "over", "own", "poem", "same", "she", "should", "some", "still", "such", "than",
+    "that", "the", "their", "them", "then", "there", "these", "they", "this", "those", "through",
+    "too", "under", "until", "very", "was", "way", "were", "what", "when", "where", "which",
+    "while", "who", "why", "will", "with", "would", "write", "you", "your",
+];
+
+const MIN_KEYWORD_CHARS: usize = 3;
+
+// Average characters per token for English text with the models we use
+const CHARS_PER_TOKEN: usize = 4;
+
+// Lowercase words, punctuation dropped, contractions kept whole
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.unicode_words().map(str::to_lowercase)
+}
+
+fn is_keyword(word: &str) -> bool {
+    word.chars().count() >= MIN_KEYWORD_CHARS
+        && word.chars().any(char::is_alphabetic)
+        && !STOPWORDS.contains(&word)
+}
+
+pub fn keywords(text: &str) -> BTreeSet<String> {
+    words(text).filter(|word| is_keyword(word)).collect()
+}
+
+// How often each keyword occurs
+pub fn keyword_counts(text: &str) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for word in words(text).filter(|word| is_keyword(word)) {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    counts
+}
+
+// Rough token count, erring high: short words still cost a token each
+pub fn estimate_tokens(text: &str) -> usize {
+    let by_chars = text.chars().count().div_ceil(CHARS_PER_TOKEN);
+    let by_words = text.split_whitespace().count();
+    by_chars.max(by_words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_skip_stopwords_and_short_words() {
+        let found = keywords("Write about the RUST on an old bicycle, rust again!");
+        let expected: BTreeSet<String> = ["rust", "old", "bicycle"]
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(found, expected);
+        assert_eq!(keyword_counts("rust, Rust and more rust")["rust"], 3);
+    }
+
+    #[test]
+    fn token_estimate_grows_with_text() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("a b c d e"), 5);
+        assert_eq!(estimate_tokens("unbelievably"), 3);
+        assert!(estimate_tokens(&"word ".repeat(100)) >= 100);
+    }
+}
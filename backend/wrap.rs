@@ -0,0 +1,271 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Line wrapping for poems, measured in terminal/notebook columns rather than
+// bytes or chars: an emoji or CJK ideograph takes two columns, a combining
+// accent none, and a grapheme cluster is never split.
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct WrapOptions {
+    pub width: u32,          // Maximum display columns per line
+    pub hanging_indent: u32, // Extra columns before continuation lines
+}
+
+impl Default for WrapOptions {
+    fn default() -> Self {
+        WrapOptions {
+            width: 60,
+            hanging_indent: 2,
+        }
+    }
+}
+
+// A run of graphemes that are all whitespace or all not
+struct Token<'a> {
+    text: &'a str,
+    width: usize,
+    is_space: bool,
+}
+
+fn is_space(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+fn tokens(text: &str) -> Vec<Token<'_>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut start = 0;
+    for (offset, grapheme) in text.grapheme_indices(true) {
+        let space = is_space(grapheme);
+        if let Some(last) = tokens.last_mut() {
+            if last.is_space == space {
+                let end = offset + grapheme.len();
+                last.text = &text[start..end];
+                last.width += grapheme.width();
+                continue;
+            }
+        }
+        start = offset;
+        tokens.push(Token {
+            text: grapheme,
+            width: grapheme.width(),
+            is_space: space,
+        });
+    }
+    tokens
+}
+
+// Builds output lines, starting each continuation with the hanging prefix
+struct LineBuilder<'a> {
+    lines: &'a mut Vec<String>,
+    width: usize,
+    continuation: String,
+    continuation_width: usize,
+    current: String,
+    current_width: usize,
+    has_content: bool,
+}
+
+impl LineBuilder<'_> {
+    fn fits(&self, extra: usize) -> bool {
+        self.current_width + extra <= self.width
+    }
+
+    fn push(&mut self, text: &str, width: usize) {
+        self.current.push_str(text);
+        self.current_width += width;
+        self.has_content = true;
+    }
+
+    fn break_line(&mut self) {
+        let finished = std::mem::replace(&mut self.current, self.continuation.clone());
+        self.lines.push(finished);
+        self.current_width = self.continuation_width;
+        self.has_content = false;
+    }
+
+    // A word wider than a whole line is split between graphemes. The first
+    // grapheme of a line always goes in, so a too-wide grapheme can't loop forever.
+    fn push_split(&mut self, word: &str) {
+        for grapheme in word.graphemes(true) {
+            let width = grapheme.width();
+            if self.has_content && !self.fits(width) {
+                self.break_line();
+            }
+            self.push(grapheme, width);
+        }
+    }
+}
+
+fn wrap_line(line: &str, options: &WrapOptions, lines: &mut Vec<String>) {
+    let width = (options.width as usize).max(1);
+    if line.width() <= width {
+        lines.push(line.to_string());
+        return;
+    }
+
+    // Leading whitespace is intentional (indented verse); keep it on the first
+    // line and under the hanging indent, unless it leaves no room for words.
+    let body = line.trim_start();
+    if body.is_empty() {
+        lines.push(String::new());
+        return;
+    }
+    let mut lead = &line[..line.len() - body.len()];
+    if lead.width() >= width {
+        lead = "";
+    }
+    let mut continuation = format!("{}{}", lead, " ".repeat(options.hanging_indent as usize));
+    if continuation.width() >= width {
+        continuation = lead.to_string();
+    }
+
+    let mut builder = LineBuilder {
+        lines,
+        width,
+        continuation_width: continuation.width(),
+        continuation,
+        current: lead.to_string(),
+        current_width: lead.width(),
+        has_content: false,
+    };
+
+    // Whitespace between words is kept exactly as written, except where the
+    // line breaks - there it is dropped.
+    let mut pending_space: Option<&Token> = None;
+    let tokens = tokens(body);
+    for token in &tokens {
+        if token.is_space {
+            pending_space = Some(token);
+            continue;
+        }
+        let space = pending_space.take();
+        let space_width = space.map_or(0, |s| s.width);
+
+        if builder.has_content && builder.fits(space_width + token.width) {
+            if let Some(space) = space {
+                builder.push(space.text, space.width);
+            }
+            builder.push(token.text, token.width);
+        } else if !builder.has_content && builder.fits(token.width) {
+            builder.push(token.text, token.width);
+        } else {
+            if builder.has_content {
+                builder.break_line();
+            }
+            if builder.fits(token.width) {
+                builder.push(token.text, token.width);
+            } else {
+                builder.push_split(token.text);
+            }
+        }
+    }
+
+    if builder.has_content {
+        let last = std::mem::take(&mut builder.current);
+        builder.lines.push(last);
+    }
+}
+
+// Wrap every line of a poem. Blank lines (stanza breaks) and lines that
+// already fit are left untouched.
+pub fn wrap_poem(poem: &str, options: &WrapOptions) -> String {
+    let mut lines = Vec::new();
+    for line in poem.lines() {
+        wrap_line(line, options, &mut lines);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn wrap(poem: &str, width: u32, hanging_indent: u32) -> String {
+        wrap_poem(
+            poem,
+            &WrapOptions {
+                width,
+                hanging_indent,
+            },
+        )
+    }
+
+    // Compared by character: a combining mark the poem left without a base
+    // joins whatever precedes it, which after a break is the hanging indent
+    fn visible(text: &str) -> String {
+        text.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries_with_hanging_indent() {
+        let wrapped = wrap("the quick brown fox jumps over the lazy dog", 16, 2);
+        assert_eq!(wrapped, "the quick brown\n  fox jumps over\n  the lazy dog");
+    }
+
+    #[test]
+    fn short_lines_and_stanza_breaks_are_untouched() {
+        let poem = "  indented   on purpose\n\nsecond stanza";
+        assert_eq!(wrap(poem, 60, 2), poem);
+    }
+
+    #[test]
+    fn keeps_leading_and_inner_whitespace() {
+        let wrapped = wrap("    static  hums   in the walls of every room", 24, 2);
+        assert_eq!(
+            wrapped,
+            "    static  hums   in\n      the walls of every\n      room"
+        );
+    }
+
+    // The old byte slicing panicked here: 'é' straddles byte 60
+    #[test]
+    fn multibyte_text_does_not_panic() {
+        let line = format!("{}é{}", "a".repeat(59), " more words".repeat(8));
+        let wrapped = wrap(&line, 60, 0);
+        assert!(wrapped.lines().all(|l| l.width() <= 60));
+    }
+
+    #[test]
+    fn measures_display_width() {
+        // Each ideograph is two columns wide
+        let wrapped = wrap("詩詩詩詩詩詩", 4, 0);
+        assert_eq!(wrapped, "詩詩\n詩詩\n詩詩");
+
+        // A family emoji is one grapheme and is never split
+        let family = "👨‍👩‍👧";
+        let wrapped = wrap(&format!("{} {} {}", family, family, family), 5, 0);
+        assert!(wrapped.lines().all(|l| l.graphemes(true).count() <= 3));
+        assert_eq!(visible(&wrapped), family.repeat(3));
+    }
+
+    #[test]
+    fn grapheme_wider_than_line_gets_its_own_line() {
+        assert_eq!(wrap("詩詩", 1, 4), "詩\n詩");
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(poem in any::<String>(), width in 0u32..120, indent in 0u32..40) {
+            wrap(&poem, width, indent);
+        }
+
+        #[test]
+        fn keeps_every_visible_grapheme(poem in "\\PC{0,300}", width in 1u32..80, indent in 0u32..10) {
+            let wrapped = wrap(&poem, width, indent);
+            let original: String = poem.lines().map(visible).collect();
+            prop_assert_eq!(visible(&wrapped), original);
+        }
+
+        #[test]
+        fn lines_fit_unless_a_single_grapheme_cannot(poem in "\\PC{0,300}", width in 1u32..80, indent in 0u32..10) {
+            let wrapped = wrap(&poem, width, indent);
+            for line in wrapped.lines() {
+                let graphemes = line.trim_start().graphemes(true).count();
+                prop_assert!(line.width() <= width as usize || graphemes <= 1, "{:?} exceeds {}", line, width);
+            }
+        }
+    }
+}
@@ -1,8 +1,27 @@
 import React, { useState, useEffect } from 'react';
 import ReactDOM from 'react-dom/client';
-import { backend } from 'declarations/backend';
+import { AuthClient } from '@dfinity/auth-client';
+import { backend, canisterId, createActor } from 'declarations/backend';
 import '/index.css';
 
+// The page follows the original poet; others live under /poets/{id}/poems
+const POET_ID = 0;
+
+// Evolving needs the Curator role, so the caller signs in with Internet Identity
+// and an admin grants its principal the role (grant_role). Reading stays anonymous.
+const IDENTITY_PROVIDER = process.env.CANISTER_ID_INTERNET_IDENTITY
+  ? `http://${process.env.CANISTER_ID_INTERNET_IDENTITY}.localhost:4943`
+  : 'https://identity.ic0.app';
+
+// Render a Candid error variant (e.g. { Unauthorized: { ... } }) as readable text
+const describeError = (err) => {
+  if (typeof err === 'string') return err;
+  const [kind, detail] = Object.entries(err || {})[0] || ['Unknown', null];
+  if (detail === null || detail === undefined) return kind;
+  if (typeof detail === 'string') return `${kind}: ${detail}`;
+  return `${kind}: ${JSON.stringify(detail, (_, v) => (typeof v === 'bigint' ? v.toString() : v))}`;
+};
+
 const App = () => {
   const [currentPoem, setCurrentPoem] = useState('');
   const [currentTitle, setCurrentTitle] = useState('');
@@ -12,16 +31,57 @@ const App = () => {
   const [nextPrompt, setNextPrompt] = useState('');
   const [isEvolutionLoading, setIsEvolutionLoading] = useState(false);
   const [evolutionResult, setEvolutionResult] = useState(null);
+  const [authClient, setAuthClient] = useState(null);
+  const [actor, setActor] = useState(backend);
+  const [principal, setPrincipal] = useState(null);
+  const [canEvolve, setCanEvolve] = useState(false);
 
   // Load today's poem on component mount
   useEffect(() => {
     loadTodaysPoem();
+    AuthClient.create().then(async (client) => {
+      setAuthClient(client);
+      if (await client.isAuthenticated()) {
+        await adoptIdentity(client);
+      }
+    });
   }, []);
 
+  // Switch to an actor signed by the logged-in identity and look up its roles
+  const adoptIdentity = async (client) => {
+    const identity = client.getIdentity();
+    const signedActor = createActor(canisterId, { agentOptions: { identity } });
+    setActor(signedActor);
+    setPrincipal(identity.getPrincipal().toText());
+    try {
+      const roles = await signedActor.get_my_roles();
+      setCanEvolve(roles.length > 0);
+    } catch (e) {
+      console.warn('Could not read roles:', e);
+      setCanEvolve(false);
+    }
+  };
+
+  const login = () => {
+    if (!authClient) return;
+    authClient.login({
+      identityProvider: IDENTITY_PROVIDER,
+      onSuccess: () => adoptIdentity(authClient),
+    });
+  };
+
+  const logout = async () => {
+    if (!authClient) return;
+    await authClient.logout();
+    setActor(backend);
+    setPrincipal(null);
+    setCanEvolve(false);
+  };
+
   const loadTodaysPoem = async () => {
     try {
-      const currentPoemResult = await backend.get_current_poem();
-      const poetStateResult = await backend.get_poet_state();
+      const currentPoemResult = await backend.get_current_poem(POET_ID);
+      const poetStateResult = await backend.get_poet_state(POET_ID);
       
       if (currentPoemResult && currentPoemResult.length > 0) {
         const poemData = currentPoemResult[0];
@@ -60,7 +120,7 @@ const App = () => {
       console.log('🚀 Starting evolution process...');
       
       // Call backend evolution
-      const result = await backend.evolve_poet();
+      const result = await actor.evolve_poet(POET_ID);
       console.log('✅ Evolution result received:', result);
       
       if (result.Ok) {
@@ -92,15 +152,16 @@ const App = () => {
         await refreshBackendState();
         
       } else {
-        console.warn('⚠️ Evolution failed:', result.Err);
+        const reason = describeError(result.Err);
+        console.warn('⚠️ Evolution failed:', reason);
         setEvolutionResult({
           success: false,
-          poem: `Evolution failed: ${result.Err}`,
+          poem: `Evolution failed: ${reason}`,
           title: 'Evolution Error',
           next_prompt: 'Try again - evolution failed'
         });
         
-        setCurrentPoem(`Evolution failed: ${result.Err}\n\nTry clicking evolution again.`);
+        setCurrentPoem(`Evolution failed: ${reason}\n\nTry clicking evolution again.`);
         setCurrentTitle('Evolution Error');
       }
       
@@ -127,7 +188,7 @@ const App = () => {
   // Helper function to refresh all backend state
   const refreshBackendState = async () => {
     try {
-      const poetStateResult = await backend.get_poet_state();
+      const poetStateResult = await backend.get_poet_state(POET_ID);
       
       if (poetStateResult && poetStateResult.length > 0) {
         const state = poetStateResult[0];
@@ -263,13 +324,33 @@ const App = () => {
 
         {/* Evolution Controls */}
         <div className="text-center mt-8">
-          <button 
-            onClick={testEvolution}
-            disabled={isEvolutionLoading}
-            className="notebook-button px-6 py-3"
-          >
-            {isEvolutionLoading ? 'EVOLVING...' : 'EVOLVE POET'}
-          </button>
+          {principal ? (
+            <>
+              <button 
+                onClick={testEvolution}
+                disabled={isEvolutionLoading || !canEvolve}
+                className="notebook-button px-6 py-3"
+              >
+                {isEvolutionLoading ? 'EVOLVING...' : 'EVOLVE POET'}
+              </button>
+              <button onClick={logout} className="notebook-button px-6 py-3 ml-4">
+                SIGN OUT
+              </button>
+              {!canEvolve && (
+                <div className="mt-4 text-xs text-gray-600 font-mono">
+                  Ask an admin to grant the Curator role to {principal}
+                </div>
+              )}
+            </>
+          ) : (
+            <button 
+              onClick={login}
+              disabled={!authClient}
+              className="notebook-button px-6 py-3"
+            >
+              SIGN IN TO EVOLVE
+            </button>
+          )}
         </div>
 
         {/* Evolution Info */}
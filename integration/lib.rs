@@ -0,0 +1,368 @@
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{encode_args, CandidType, Deserialize, IDLValue, Principal};
+use ic_llm::ChatMessage;
+use pocket_ic::PocketIc;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+// Deploys the backend next to llm_stub, which takes the LLM canister's id so
+// the backend's calls reach it unchanged. Everything runs inside a local
+// PocketIC server; the wasm is built on first use into target/integration.
+
+// The id the backend sends its chats to (backend/llm.rs)
+const LLM_CANISTER: &str = "w36hm-eqaaa-aaaal-qr76a-cai";
+
+pub type PoetId = u32;
+
+// The parts of the backend's types the tests look at. Candid drops the
+// fields these leave out.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoemCycle {
+    pub cycle_number: u64,
+    pub poem: String,
+    pub title: String,
+    pub next_prompt: String,
+    pub generation_method: GenerationMethod,
+    pub parent: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum GenerationMethod {
+    Primary,
+    Fallback,
+    Corrected,
+    Algorithmic,
+    Structured,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoetState {
+    pub current_cycle: u64,
+    pub total_poems: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct GenerationStats {
+    pub total_poems: u64,
+    pub primary_success: u64,
+    pub fallback_used: u64,
+    pub correction_used: u64,
+    pub algorithmic_used: u64,
+    pub structured_success: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+// PoetError is only ever printed, so it is left undecoded
+pub type ApiResult<T> = Result<T, IDLValue>;
+
+pub struct Deployment {
+    pub pic: PocketIc,
+    pub backend: Principal,
+    pub llm: Principal,
+    pub owner: Principal,
+}
+
+struct Wasm {
+    backend: Vec<u8>,
+    llm_stub: Vec<u8>,
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("integration is a workspace member")
+        .to_path_buf()
+}
+
+// Built once per test binary, in its own target directory so it cannot wait
+// on the lock of the cargo invocation running the tests
+fn wasm() -> &'static Wasm {
+    static WASM: OnceLock<Wasm> = OnceLock::new();
+    WASM.get_or_init(|| {
+        let target_dir = workspace_root().join("target").join("integration");
+        let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+            .current_dir(workspace_root())
+            .args([
+                "build",
+                "--target",
+                "wasm32-unknown-unknown",
+                "--release",
+                "-p",
+                "backend",
+                "-p",
+                "llm_stub",
+            ])
+            .env("CARGO_TARGET_DIR", &target_dir)
+            .status()
+            .expect("failed to run cargo");
+        assert!(status.success(), "building the canisters failed");
+
+        let read = |name: &str| {
+            let path = target_dir
+                .join("wasm32-unknown-unknown")
+                .join("release")
+                .join(format!("{}.wasm", name));
+            std::fs::read(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e))
+        };
+        Wasm {
+            backend: read("backend"),
+            llm_stub: read("llm_stub"),
+        }
+    })
+}
+
+impl Deployment {
+    // The tests are #[ignore]d, so whoever runs them asked for a PocketIC
+    // server: a missing one fails them rather than passing them unrun
+    pub fn start() -> Deployment {
+        if std::env::var_os("POCKET_IC_BIN").is_none() {
+            panic!("POCKET_IC_BIN must point at a PocketIC server binary");
+        }
+        let wasm = wasm();
+        let pic = PocketIc::new();
+        let owner = Principal::self_authenticating(b"integration-test-owner");
+
+        let llm = Principal::from_text(LLM_CANISTER).unwrap();
+        pic.create_canister_with_id(Some(owner), None, llm)
+            .expect("cannot create the LLM canister");
+        pic.install_canister(
+            llm,
+            wasm.llm_stub.clone(),
+            encode_args(()).unwrap(),
+            Some(owner),
+        );
+
+        // Installing as the owner makes it the backend's first owner too
+        let backend = pic.create_canister_with_settings(Some(owner), None);
+        pic.install_canister(
+            backend,
+            wasm.backend.clone(),
+            encode_args(()).unwrap(),
+            Some(owner),
+        );
+
+        Deployment {
+            pic,
+            backend,
+            llm,
+            owner,
+        }
+    }
+
+    pub fn update<A, R>(&self, method: &str, args: A) -> R
+    where
+        A: ArgumentEncoder,
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        pocket_ic::update_candid_as(&self.pic, self.backend, self.owner, method, args)
+            .unwrap_or_else(|e| panic!("{} was rejected: {:?}", method, e))
+    }
+
+    pub fn query<A, R>(&self, method: &str, args: A) -> R
+    where
+        A: ArgumentEncoder,
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        pocket_ic::query_candid_as(&self.pic, self.backend, self.owner, method, args)
+            .unwrap_or_else(|e| panic!("{} was rejected: {:?}", method, e))
+    }
+
+    // Queue answers for the stub's next chats; None is a reply with no content
+    pub fn script(&self, replies: Vec<Option<String>>) {
+        let () = pocket_ic::update_candid_as(&self.pic, self.llm, self.owner, "script", (replies,))
+            .expect("script was rejected");
+    }
+
+    // Every chat the backend has sent, oldest first
+    pub fn chats(&self) -> Vec<ChatRequest> {
+        let (requests,): (Vec<ChatRequest>,) =
+            pocket_ic::query_candid_as(&self.pic, self.llm, self.owner, "requests", ())
+                .expect("requests was rejected");
+        requests
+    }
+
+    pub fn evolve(&self, poet_id: PoetId) -> PoemCycle {
+        let (result,): (ApiResult<PoemCycle>,) = self.update("evolve_poet", (poet_id,));
+        result.unwrap_or_else(|e| panic!("evolve_poet failed: {}", e))
+    }
+
+    pub fn poems(&self, poet_id: PoetId) -> Vec<PoemCycle> {
+        let (poems,): (Vec<PoemCycle>,) = self.query("get_all_poems", (poet_id,));
+        poems
+    }
+
+    pub fn upgrade(&self) {
+        self.pic
+            .upgrade_canister(
+                self.backend,
+                wasm().backend.clone(),
+                encode_args(()).unwrap(),
+                Some(self.owner),
+            )
+            .expect("upgrade failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_POET: PoetId = 0;
+
+    fn labelled(poem: &str, title: &str, next: &str) -> Option<String> {
+        Some(format!("POEM: {}\nTITLE: {}\nNEXT: {}", poem, title, next))
+    }
+
+    fn prompt_of(chat: &ChatRequest) -> &str {
+        match &chat.messages[0] {
+            ChatMessage::System { content } | ChatMessage::User { content } => content,
+            other => panic!("unexpected first message {:?}", other),
+        }
+    }
+
+    fn evolve_twice(env: &Deployment) -> (PoemCycle, PoemCycle) {
+        env.script(vec![
+            labelled(
+                "static in my veins\nthe cursor blinks like a pulse",
+                "Static Veins",
+                "Write about the hum of a server room at night when nobody is left to listen",
+            ),
+            labelled(
+                "fans breathe in the dark\nracks of amber lights keep watch",
+                "Night Shift",
+                "Write about a lighthouse keeper who has never seen the sea by day",
+            ),
+        ]);
+        (env.evolve(DEFAULT_POET), env.evolve(DEFAULT_POET))
+    }
+
+    #[test]
+    #[ignore = "needs a PocketIC server in POCKET_IC_BIN"]
+    fn cycles_are_numbered_and_counted() {
+        let env = Deployment::start();
+
+        let (first, second) = evolve_twice(&env);
+        assert_eq!((first.cycle_number, first.parent), (1, Some(0)));
+        assert_eq!((second.cycle_number, second.parent), (2, Some(1)));
+        assert_eq!(first.title, "Static Veins");
+        assert_eq!(second.generation_method, GenerationMethod::Primary);
+
+        // Each cycle is written from the prompt the one before left behind
+        let chats = env.chats();
+        assert_eq!(chats.len(), 2);
+        assert!(prompt_of(&chats[1]).contains(&first.next_prompt));
+
+        let (count,): (u64,) = env.query("get_poem_count", (DEFAULT_POET,));
+        let (stats,): (GenerationStats,) = env.query("get_generation_stats", (DEFAULT_POET,));
+        assert_eq!(count, 2);
+        assert_eq!(
+            (
+                stats.total_poems,
+                stats.primary_success,
+                stats.algorithmic_used
+            ),
+            (2, 2, 0)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a PocketIC server in POCKET_IC_BIN"]
+    fn set_next_prompt_steers_the_next_cycle() {
+        let env = Deployment::start();
+        env.script(vec![labelled(
+            "first light",
+            "Dawn",
+            "Write about the first hour of the morning shift",
+        )]);
+        env.evolve(DEFAULT_POET);
+
+        let steer = "Write about a kettle left to boil dry in an empty kitchen";
+        let (result,): (ApiResult<()>,) =
+            env.update("set_next_prompt", (DEFAULT_POET, steer.to_string()));
+        result.unwrap();
+        assert_eq!(env.poems(DEFAULT_POET)[0].next_prompt, steer);
+
+        env.script(vec![labelled(
+            "steam and silence",
+            "Kettle",
+            "Write about the quiet after the whistle stops",
+        )]);
+        env.evolve(DEFAULT_POET);
+        assert!(prompt_of(env.chats().last().unwrap()).contains(steer));
+    }
+
+    #[test]
+    #[ignore = "needs a PocketIC server in POCKET_IC_BIN"]
+    fn reset_starts_the_numbering_again() {
+        let env = Deployment::start();
+        evolve_twice(&env);
+
+        let (result,): (ApiResult<()>,) = env.update("reset_poet", (DEFAULT_POET,));
+        result.unwrap();
+        assert!(env.poems(DEFAULT_POET).is_empty());
+        let (count,): (u64,) = env.query("get_poem_count", (DEFAULT_POET,));
+        assert_eq!(count, 0);
+
+        env.script(vec![labelled(
+            "a clean page",
+            "Again",
+            "Write about the smell of a new notebook",
+        )]);
+        assert_eq!(env.evolve(DEFAULT_POET).cycle_number, 1);
+    }
+
+    #[test]
+    #[ignore = "needs a PocketIC server in POCKET_IC_BIN"]
+    fn history_survives_an_upgrade() {
+        let env = Deployment::start();
+        evolve_twice(&env);
+        let before = env.poems(DEFAULT_POET);
+        let (stats_before,): (GenerationStats,) =
+            env.query("get_generation_stats", (DEFAULT_POET,));
+
+        env.upgrade();
+
+        assert_eq!(env.poems(DEFAULT_POET), before);
+        let (stats,): (GenerationStats,) = env.query("get_generation_stats", (DEFAULT_POET,));
+        assert_eq!(stats, stats_before);
+        let (state,): (Option<PoetState>,) = env.query("get_poet_state", (DEFAULT_POET,));
+        assert_eq!(
+            state,
+            Some(PoetState {
+                current_cycle: 2,
+                total_poems: 2
+            })
+        );
+
+        // The owner survives too, and numbering carries on
+        env.script(vec![labelled(
+            "after the restart",
+            "Upgrade",
+            "Write about waking in a different room",
+        )]);
+        let third = env.evolve(DEFAULT_POET);
+        assert_eq!((third.cycle_number, third.parent), (3, Some(2)));
+    }
+
+    #[test]
+    #[ignore = "needs a PocketIC server in POCKET_IC_BIN"]
+    fn empty_replies_still_produce_a_poem() {
+        let env = Deployment::start();
+
+        // Nothing scripted: the generation and its correction both come back empty
+        let cycle = env.evolve(DEFAULT_POET);
+        assert_eq!(cycle.generation_method, GenerationMethod::Algorithmic);
+        assert_eq!(cycle.cycle_number, 1);
+        assert!(!cycle.poem.is_empty() && !cycle.title.is_empty() && !cycle.next_prompt.is_empty());
+        assert_eq!(env.chats().len(), 2);
+
+        let (stats,): (GenerationStats,) = env.query("get_generation_stats", (DEFAULT_POET,));
+        assert_eq!((stats.total_poems, stats.algorithmic_used), (1, 1));
+    }
+}
@@ -0,0 +1,46 @@
+use candid::{CandidType, Deserialize};
+use ic_cdk::{query, update};
+use ic_llm::{AssistantMessage, ChatMessage, Response, Tool};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+// Deployed at the LLM canister's id by the integration tests. Answers v1_chat
+// with the replies it was scripted with, in order, then with no content once
+// they run out. Every request is kept so tests can check what was asked.
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub tools: Option<Vec<Tool>>,
+}
+
+thread_local! {
+    static REPLIES: RefCell<VecDeque<Option<String>>> = const { RefCell::new(VecDeque::new()) };
+    static REQUESTS: RefCell<Vec<ChatRequest>> = const { RefCell::new(Vec::new()) };
+}
+
+#[update]
+fn v1_chat(request: ChatRequest) -> Response {
+    REQUESTS.with(|requests| requests.borrow_mut().push(request));
+    let content = REPLIES
+        .with(|replies| replies.borrow_mut().pop_front())
+        .flatten();
+    Response {
+        message: AssistantMessage {
+            content,
+            tool_calls: Vec::new(),
+        },
+    }
+}
+
+// Queue replies after any still waiting; None answers with no content
+#[update]
+fn script(replies: Vec<Option<String>>) {
+    REPLIES.with(|queue| queue.borrow_mut().extend(replies));
+}
+
+#[query]
+fn requests() -> Vec<ChatRequest> {
+    REQUESTS.with(|requests| requests.borrow().clone())
+}
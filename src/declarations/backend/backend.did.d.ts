@@ -1,74 +1,373 @@
 import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
+import type { IDL } from '@dfinity/candid';
 
-export interface AssistantMessage {
-  'content' : [] | [string],
-  'tool_calls' : Array<ToolCall>,
-}
-export type ChatMessage = {
-    'tool' : { 'content' : string, 'tool_call_id' : string }
-  } |
-  { 'user' : { 'content' : string } } |
-  { 'assistant' : AssistantMessage } |
-  { 'system' : { 'content' : string } };
-export interface Conversation {
-  'id' : bigint,
+export type AuthError = { 'LastOwner' : null } |
+  { 'Unauthorized' : { 'required' : Role, 'caller' : Principal } } |
+  { 'AnonymousCaller' : null };
+export interface BranchSummary {
+  'active' : boolean,
+  'cycle_count' : bigint,
+  'branch_id' : number,
+  'head' : [] | [bigint],
+  'created_at' : bigint,
+  'prompt' : [] | [string],
+  'forked_from' : bigint,
+}
+export interface CertifiedPoem {
+  'certificate' : [] | [Uint8Array | number[]],
+  'poem' : PoemCycle,
+  'witness' : Uint8Array | number[],
+}
+export interface CorrectionAttempt {
+  'template_version' : [] | [number],
+  'attempt' : number,
+  'kind' : CorrectionKind,
+  'error' : [] | [string],
+  'response' : [] | [string],
+}
+export type CorrectionKind = { 'Format' : null } |
+  { 'Parse' : null };
+export interface CycleOverride { 'model' : LlmModel, 'cycle_number' : bigint }
+export interface CycleThemes {
+  'cycle_number' : bigint,
+  'themes' : Array<string>,
+  'drift' : [] | [number],
+  'parent' : bigint,
+}
+export type DiffLine = { 'Same' : string } |
+  { 'Added' : string } |
+  { 'Removed' : string };
+export interface EraSummary {
+  'branch' : number,
+  'poem_count' : number,
+  'titles' : Array<string>,
+  'created_at' : bigint,
+  'keywords' : Array<string>,
+  'last_cycle' : bigint,
+  'first_cycle' : bigint,
+}
+export interface EvolutionStatus {
+  'poet_id' : number,
+  'cycle_number' : bigint,
+  'triggered_by' : [] | [Principal],
+  'expires_at' : bigint,
+  'started_at' : bigint,
+}
+export interface FeedConfig {
   'title' : string,
-  'updated_at' : bigint,
+  'description' : string,
+  'site_url' : [] | [string],
+  'item_count' : number,
+}
+export interface GenerationConfig {
+  'memory' : [] | [MemoryOptions],
+  'model' : LlmModel,
+  'cycle_overrides' : Array<CycleOverride>,
+  'wrap' : [] | [WrapOptions],
+  'max_correction_retries' : number,
+  'novelty' : [] | [NoveltyOptions],
+  'output_contract' : [] | [OutputContract],
+  'correction_model' : LlmModel,
+}
+export type GenerationMethod = { 'Fallback' : null } |
+  { 'Algorithmic' : null } |
+  { 'Structured' : null } |
+  { 'Primary' : null } |
+  { 'Corrected' : null };
+export interface GenerationStats {
+  'total_poems' : bigint,
+  'fallback_used' : bigint,
+  'primary_success' : bigint,
+  'correction_used' : bigint,
+  'algorithmic_used' : bigint,
+  'structured_success' : bigint,
+}
+export interface HttpRequest {
+  'url' : string,
+  'method' : string,
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+}
+export interface HttpResponse {
+  'body' : Uint8Array | number[],
+  'headers' : Array<[string, string]>,
+  'status_code' : number,
+}
+export type ListOrder = { 'Descending' : null } |
+  { 'Ascending' : null };
+export type LlmModel = { 'Llama4Scout' : null } |
+  { 'Qwen3_32B' : null } |
+  { 'Llama3_1_8B' : null };
+export interface MemoryOptions {
+  'recent_window' : number,
+  'token_budget' : number,
+  'search_window' : number,
+  'era_length' : number,
+}
+export interface MetaFormRevision {
+  'source' : string,
+  'restored_from' : [] | [number],
   'created_at' : bigint,
-  'message_count' : bigint,
+  'author' : [] | [Principal],
+  'revision' : number,
 }
-export interface ConversationWithMessages {
-  'messages' : Array<StoredMessage>,
-  'conversation' : Conversation,
+export interface NearDuplicate {
+  'second_cycle' : bigint,
+  'similarity' : number,
+  'first_cycle' : bigint,
 }
-export interface FunctionCall {
-  'name' : string,
-  'arguments' : Array<ToolCallArgument>,
+export interface Novelty {
+  'closest_cycle' : [] | [bigint],
+  'reprompts' : number,
+  'score' : number,
+  'theme_similarity' : number,
+}
+export interface NoveltyOptions {
+  'threshold' : number,
+  'window' : number,
+  'max_reprompts' : number,
 }
+export type OutputContract = { 'Json' : null } |
+  { 'Labels' : null };
+export type ParseLayer = { 'Json' : null } |
+  { 'Heuristics' : null } |
+  { 'Labels' : null };
 export interface PoemCycle {
   'id' : bigint,
+  'model' : [] | [LlmModel],
   'title' : string,
+  'branch' : [] | [number],
+  'template_version' : [] | [number],
+  'meta_form_revision' : [] | [number],
   'next_prompt' : string,
   'poem' : string,
   'cycle_number' : bigint,
+  'raw_response' : string,
+  'created_at' : bigint,
+  'novelty' : [] | [Novelty],
+  'corrections' : [] | [Array<CorrectionAttempt>],
+  'generation_method' : GenerationMethod,
+  'parent' : [] | [bigint],
+}
+export interface PoemFilter {
+  'cycle_from' : [] | [bigint],
+  'methods' : [] | [Array<GenerationMethod>],
+  'title_contains' : [] | [string],
+  'created_to' : [] | [bigint],
+  'cycle_to' : [] | [bigint],
+  'created_from' : [] | [bigint],
+}
+export interface PoemPage {
+  'next_cursor' : [] | [bigint],
+  'items' : Array<PoemSummary>,
+}
+export interface PoemSummary {
+  'id' : bigint,
+  'model' : [] | [LlmModel],
+  'title' : string,
+  'branch' : number,
+  'next_prompt' : string,
+  'cycle_number' : bigint,
   'created_at' : bigint,
-  'bukowski_style_score' : [] | [number],
+  'excerpt' : string,
+  'generation_method' : GenerationMethod,
+  'line_count' : number,
+  'parent' : bigint,
 }
+export type PoetError = { 'InvalidConfig' : string } |
+  { 'LlmUnavailable' : string } |
+  { 'ParseFailed' : { 'layer' : ParseLayer, 'reason' : string } } |
+  { 'EvolutionInProgress' : EvolutionStatus } |
+  { 'PoetNotFound' : number } |
+  { 'NotInitialized' : null } |
+  { 'InsufficientCycles' : { 'balance' : bigint, 'required' : bigint } } |
+  { 'Unauthorized' : AuthError } |
+  { 'RevisionNotFound' : number } |
+  { 'CycleNotFound' : bigint };
 export interface PoetState {
+  'model' : [] | [LlmModel],
+  'name' : string,
   'total_poems' : bigint,
   'last_updated' : bigint,
+  'active_branch' : [] | [number],
+  'persona' : [] | [string],
   'genesis_prompt' : string,
+  'meta_form' : string,
   'current_cycle' : bigint,
 }
-export type Result = { 'Ok' : PoemCycle } |
-  { 'Err' : string };
-export interface StoredMessage {
-  'content' : string,
-  'role' : string,
-  'timestamp' : bigint,
+export interface PoetSummary {
+  'poet_id' : number,
+  'model' : [] | [LlmModel],
+  'name' : string,
+  'total_poems' : bigint,
+  'last_updated' : bigint,
+  'current_cycle' : bigint,
+}
+export interface RecurringTheme {
+  'cycles' : BigUint64Array | bigint[],
+  'keyword' : string,
+}
+export type Result = { 'Ok' : number } |
+  { 'Err' : PoetError };
+export type Result_1 = { 'Ok' : Array<DiffLine> } |
+  { 'Err' : PoetError };
+export type Result_10 = { 'Ok' : Array<RoleGrant> } |
+  { 'Err' : PoetError };
+export type Result_11 = { 'Ok' : Array<TemplateVersion> } |
+  { 'Err' : PoetError };
+export type Result_12 = { 'Ok' : ScheduleState } |
+  { 'Err' : PoetError };
+export type Result_13 = { 'Ok' : TemplateVersion } |
+  { 'Err' : PoetError };
+export type Result_2 = { 'Ok' : PoemCycle } |
+  { 'Err' : PoetError };
+export type Result_3 = { 'Ok' : FeedConfig } |
+  { 'Err' : PoetError };
+export type Result_4 = { 'Ok' : GenerationConfig } |
+  { 'Err' : PoetError };
+export type Result_5 = { 'Ok' : MetaFormRevision } |
+  { 'Err' : PoetError };
+export type Result_6 = { 'Ok' : [] | [TemplateVersion] } |
+  { 'Err' : PoetError };
+export type Result_7 = { 'Ok' : null } |
+  { 'Err' : PoetError };
+export type Result_8 = { 'Ok' : string } |
+  { 'Err' : PoetError };
+export type Result_9 = { 'Ok' : Array<MetaFormRevision> } |
+  { 'Err' : PoetError };
+export type Role = { 'Curator' : null } |
+  { 'Admin' : null } |
+  { 'Owner' : null };
+export interface RoleGrant {
+  'updated_at' : bigint,
+  'principal' : Principal,
+  'granted_by' : Principal,
+  'roles' : Array<Role>,
+}
+export interface ScheduleConfig {
+  'interval_secs' : bigint,
+  'max_cycles_per_day' : number,
+  'jitter_secs' : bigint,
+  'paused' : boolean,
+}
+export interface ScheduleState {
+  'day' : bigint,
+  'last_run_at' : [] | [bigint],
+  'last_outcome' : [] | [string],
+  'next_run_at' : [] | [bigint],
+  'config' : ScheduleConfig,
+  'cycles_today' : number,
+}
+export type StatsBucket = { 'Day' : null } |
+  { 'Week' : null };
+export interface StatsPoint {
+  'llm_calls' : bigint,
+  'avg_next_prompt_chars' : number,
+  'max_llm_latency_ms' : [] | [number],
+  'methods' : GenerationStats,
+  'avg_title_chars' : number,
+  'avg_poem_chars' : number,
+  'avg_poem_lines' : number,
+  'bucket_start' : bigint,
+  'correction_rate' : number,
+  'avg_llm_latency_ms' : [] | [number],
+}
+export interface StatsRange { 'to' : [] | [bigint], 'from' : [] | [bigint] }
+export type TemplateName = { 'MetaForm' : null } |
+  { 'FormatCorrection' : null } |
+  { 'Correction' : null };
+export interface TemplateVersion {
+  'source' : string,
+  'name' : TemplateName,
+  'created_at' : bigint,
+  'author' : [] | [Principal],
+  'version' : number,
 }
-export interface ToolCall { 'id' : string, 'function' : FunctionCall }
-export interface ToolCallArgument { 'value' : string, 'name' : string }
+export interface ThemeEdge { 'to' : string, 'weight' : number, 'from' : string }
+export interface ThemeGraph {
+  'edges' : Array<ThemeEdge>,
+  'cycles' : Array<CycleThemes>,
+  'nodes' : Array<ThemeNode>,
+}
+export interface ThemeNode {
+  'theme' : string,
+  'cycles' : number,
+  'last_cycle' : bigint,
+  'first_cycle' : bigint,
+}
+export interface WrapOptions { 'hanging_indent' : number, 'width' : number }
 export interface _SERVICE {
-  'chat' : ActorMethod<[Array<ChatMessage>], string>,
-  'chat_with_storage' : ActorMethod<
-    [[] | [bigint], Array<ChatMessage>],
-    [bigint, string]
+  'create_poet' : ActorMethod<[string, string, [] | [LlmModel]], Result>,
+  'diff_meta_form' : ActorMethod<[number, number, [] | [number]], Result_1>,
+  'evolve_poet' : ActorMethod<[number], Result_2>,
+  'find_near_duplicates' : ActorMethod<
+    [number, [] | [number]],
+    Array<NearDuplicate>
+  >,
+  'fork_from_cycle' : ActorMethod<[number, bigint, string], Result>,
+  'get_all_poems' : ActorMethod<[number], Array<PoemCycle>>,
+  'get_ancestry' : ActorMethod<
+    [number, bigint, [] | [number]],
+    Array<PoemSummary>
+  >,
+  'get_certified_current_poem' : ActorMethod<[number], [] | [CertifiedPoem]>,
+  'get_certified_poem' : ActorMethod<[number, bigint], [] | [CertifiedPoem]>,
+  'get_current_poem' : ActorMethod<[number], [] | [PoemCycle]>,
+  'get_era_summaries' : ActorMethod<[number], Array<EraSummary>>,
+  'get_evolution_status' : ActorMethod<[number], [] | [EvolutionStatus]>,
+  'get_feed_config' : ActorMethod<[], Result_3>,
+  'get_generation_config' : ActorMethod<[], Result_4>,
+  'get_generation_stats' : ActorMethod<[number], GenerationStats>,
+  'get_meta_form' : ActorMethod<[number], Result_5>,
+  'get_my_roles' : ActorMethod<[], Array<Role>>,
+  'get_poem_by_cycle' : ActorMethod<[number, bigint], [] | [PoemCycle]>,
+  'get_poem_count' : ActorMethod<[number], bigint>,
+  'get_poet_state' : ActorMethod<[number], [] | [PoetState]>,
+  'get_raw_response' : ActorMethod<[number, bigint], [] | [string]>,
+  'get_recurring_themes' : ActorMethod<
+    [number, [] | [number]],
+    Array<RecurringTheme>
+  >,
+  'get_schedule' : ActorMethod<[], ScheduleState>,
+  'get_schema_version' : ActorMethod<[], number>,
+  'get_stats_timeseries' : ActorMethod<
+    [number, StatsBucket, StatsRange],
+    Array<StatsPoint>
+  >,
+  'get_template' : ActorMethod<[TemplateName, [] | [number]], Result_6>,
+  'get_theme_graph' : ActorMethod<
+    [number, [] | [number], [] | [bigint]],
+    ThemeGraph
   >,
-  'delete_conversation' : ActorMethod<[bigint], boolean>,
-  'evolve_poet' : ActorMethod<[], Result>,
-  'get_all_poems' : ActorMethod<[], Array<PoemCycle>>,
-  'get_conversation_messages' : ActorMethod<[bigint], Array<StoredMessage>>,
-  'get_conversation_with_messages' : ActorMethod<
-    [bigint],
-    [] | [ConversationWithMessages]
+  'grant_owner' : ActorMethod<[Principal], Result_7>,
+  'grant_role' : ActorMethod<[Principal, Role], Result_7>,
+  'http_request' : ActorMethod<[HttpRequest], HttpResponse>,
+  'initialize_poet' : ActorMethod<[number], Result_8>,
+  'is_poet_initialized' : ActorMethod<[number], boolean>,
+  'list_branches' : ActorMethod<[number], Array<BranchSummary>>,
+  'list_meta_form_revisions' : ActorMethod<[number], Result_9>,
+  'list_poems' : ActorMethod<
+    [number, [] | [bigint], [] | [number], ListOrder, [] | [PoemFilter]],
+    PoemPage
   >,
-  'get_conversations' : ActorMethod<[], Array<Conversation>>,
-  'get_current_poem' : ActorMethod<[], [] | [PoemCycle]>,
-  'get_poem_by_cycle' : ActorMethod<[bigint], [] | [PoemCycle]>,
-  'get_poet_state' : ActorMethod<[], [] | [PoetState]>,
-  'prompt' : ActorMethod<[string], string>,
-  'reset_poet' : ActorMethod<[], boolean>,
-  'update_conversation_title' : ActorMethod<[bigint, string], boolean>,
+  'list_poets' : ActorMethod<[], Array<PoetSummary>>,
+  'list_role_grants' : ActorMethod<[], Result_10>,
+  'list_template_versions' : ActorMethod<[TemplateName], Result_11>,
+  'pause_schedule' : ActorMethod<[], Result_12>,
+  'replay_cycle' : ActorMethod<[number, bigint], Result_2>,
+  'reset_poet' : ActorMethod<[number], Result_7>,
+  'resume_schedule' : ActorMethod<[], Result_12>,
+  'revoke_role' : ActorMethod<[Principal, Role], Result_7>,
+  'rollback_meta_form' : ActorMethod<[number, number], Result_5>,
+  'set_feed_config' : ActorMethod<[FeedConfig], Result_3>,
+  'set_generation_config' : ActorMethod<[GenerationConfig], Result_4>,
+  'set_meta_form' : ActorMethod<[number, string], Result_5>,
+  'set_next_prompt' : ActorMethod<[number, string], Result_7>,
+  'set_poet_model' : ActorMethod<[number, [] | [LlmModel]], Result_7>,
+  'set_poet_persona' : ActorMethod<[number, [] | [string]], Result_7>,
+  'set_schedule' : ActorMethod<[ScheduleConfig], Result_12>,
+  'set_template' : ActorMethod<[TemplateName, string], Result_13>,
 }
+export declare const idlFactory: IDL.InterfaceFactory;
+export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
\ No newline at end of file
@@ -1,90 +1,472 @@
 export const idlFactory = ({ IDL }) => {
-  const ToolCallArgument = IDL.Record({
-    'value' : IDL.Text,
-    'name' : IDL.Text,
+  const LlmModel = IDL.Variant({
+    'Llama4Scout' : IDL.Null,
+    'Qwen3_32B' : IDL.Null,
+    'Llama3_1_8B' : IDL.Null,
   });
-  const FunctionCall = IDL.Record({
-    'name' : IDL.Text,
-    'arguments' : IDL.Vec(ToolCallArgument),
+  const ParseLayer = IDL.Variant({
+    'Json' : IDL.Null,
+    'Heuristics' : IDL.Null,
+    'Labels' : IDL.Null,
+  });
+  const EvolutionStatus = IDL.Record({
+    'poet_id' : IDL.Nat32,
+    'cycle_number' : IDL.Nat64,
+    'triggered_by' : IDL.Opt(IDL.Principal),
+    'expires_at' : IDL.Nat64,
+    'started_at' : IDL.Nat64,
+  });
+  const Role = IDL.Variant({
+    'Curator' : IDL.Null,
+    'Admin' : IDL.Null,
+    'Owner' : IDL.Null,
+  });
+  const AuthError = IDL.Variant({
+    'LastOwner' : IDL.Null,
+    'Unauthorized' : IDL.Record({
+      'required' : Role,
+      'caller' : IDL.Principal,
+    }),
+    'AnonymousCaller' : IDL.Null,
   });
-  const ToolCall = IDL.Record({ 'id' : IDL.Text, 'function' : FunctionCall });
-  const AssistantMessage = IDL.Record({
-    'content' : IDL.Opt(IDL.Text),
-    'tool_calls' : IDL.Vec(ToolCall),
+  const PoetError = IDL.Variant({
+    'InvalidConfig' : IDL.Text,
+    'LlmUnavailable' : IDL.Text,
+    'ParseFailed' : IDL.Record({ 'layer' : ParseLayer, 'reason' : IDL.Text }),
+    'EvolutionInProgress' : EvolutionStatus,
+    'PoetNotFound' : IDL.Nat32,
+    'NotInitialized' : IDL.Null,
+    'InsufficientCycles' : IDL.Record({
+      'balance' : IDL.Nat,
+      'required' : IDL.Nat,
+    }),
+    'Unauthorized' : AuthError,
+    'RevisionNotFound' : IDL.Nat32,
+    'CycleNotFound' : IDL.Nat64,
   });
-  const ChatMessage = IDL.Variant({
-    'tool' : IDL.Record({ 'content' : IDL.Text, 'tool_call_id' : IDL.Text }),
-    'user' : IDL.Record({ 'content' : IDL.Text }),
-    'assistant' : AssistantMessage,
-    'system' : IDL.Record({ 'content' : IDL.Text }),
+  const Result = IDL.Variant({ 'Ok' : IDL.Nat32, 'Err' : PoetError });
+  const DiffLine = IDL.Variant({
+    'Same' : IDL.Text,
+    'Added' : IDL.Text,
+    'Removed' : IDL.Text,
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : IDL.Vec(DiffLine), 'Err' : PoetError });
+  const Novelty = IDL.Record({
+    'closest_cycle' : IDL.Opt(IDL.Nat64),
+    'reprompts' : IDL.Nat8,
+    'score' : IDL.Float32,
+    'theme_similarity' : IDL.Float32,
+  });
+  const CorrectionKind = IDL.Variant({
+    'Format' : IDL.Null,
+    'Parse' : IDL.Null,
+  });
+  const CorrectionAttempt = IDL.Record({
+    'template_version' : IDL.Opt(IDL.Nat32),
+    'attempt' : IDL.Nat8,
+    'kind' : CorrectionKind,
+    'error' : IDL.Opt(IDL.Text),
+    'response' : IDL.Opt(IDL.Text),
+  });
+  const GenerationMethod = IDL.Variant({
+    'Fallback' : IDL.Null,
+    'Algorithmic' : IDL.Null,
+    'Structured' : IDL.Null,
+    'Primary' : IDL.Null,
+    'Corrected' : IDL.Null,
   });
   const PoemCycle = IDL.Record({
     'id' : IDL.Nat64,
+    'model' : IDL.Opt(LlmModel),
     'title' : IDL.Text,
+    'branch' : IDL.Opt(IDL.Nat32),
+    'template_version' : IDL.Opt(IDL.Nat32),
+    'meta_form_revision' : IDL.Opt(IDL.Nat32),
     'next_prompt' : IDL.Text,
     'poem' : IDL.Text,
     'cycle_number' : IDL.Nat64,
+    'raw_response' : IDL.Text,
     'created_at' : IDL.Nat64,
-    'bukowski_style_score' : IDL.Opt(IDL.Float32),
+    'novelty' : IDL.Opt(Novelty),
+    'corrections' : IDL.Opt(IDL.Vec(CorrectionAttempt)),
+    'generation_method' : GenerationMethod,
+    'parent' : IDL.Opt(IDL.Nat64),
   });
-  const Result = IDL.Variant({ 'Ok' : PoemCycle, 'Err' : IDL.Text });
-  const StoredMessage = IDL.Record({
-    'content' : IDL.Text,
-    'role' : IDL.Text,
-    'timestamp' : IDL.Nat64,
+  const Result_2 = IDL.Variant({ 'Ok' : PoemCycle, 'Err' : PoetError });
+  const NearDuplicate = IDL.Record({
+    'second_cycle' : IDL.Nat64,
+    'similarity' : IDL.Float32,
+    'first_cycle' : IDL.Nat64,
   });
-  const Conversation = IDL.Record({
+  const PoemSummary = IDL.Record({
     'id' : IDL.Nat64,
+    'model' : IDL.Opt(LlmModel),
     'title' : IDL.Text,
-    'updated_at' : IDL.Nat64,
+    'branch' : IDL.Nat32,
+    'next_prompt' : IDL.Text,
+    'cycle_number' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'excerpt' : IDL.Text,
+    'generation_method' : GenerationMethod,
+    'line_count' : IDL.Nat32,
+    'parent' : IDL.Nat64,
+  });
+  const CertifiedPoem = IDL.Record({
+    'certificate' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'poem' : PoemCycle,
+    'witness' : IDL.Vec(IDL.Nat8),
+  });
+  const EraSummary = IDL.Record({
+    'branch' : IDL.Nat32,
+    'poem_count' : IDL.Nat32,
+    'titles' : IDL.Vec(IDL.Text),
     'created_at' : IDL.Nat64,
-    'message_count' : IDL.Nat64,
+    'keywords' : IDL.Vec(IDL.Text),
+    'last_cycle' : IDL.Nat64,
+    'first_cycle' : IDL.Nat64,
+  });
+  const FeedConfig = IDL.Record({
+    'title' : IDL.Text,
+    'description' : IDL.Text,
+    'site_url' : IDL.Opt(IDL.Text),
+    'item_count' : IDL.Nat32,
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : FeedConfig, 'Err' : PoetError });
+  const MemoryOptions = IDL.Record({
+    'recent_window' : IDL.Nat32,
+    'token_budget' : IDL.Nat32,
+    'search_window' : IDL.Nat32,
+    'era_length' : IDL.Nat32,
+  });
+  const CycleOverride = IDL.Record({
+    'model' : LlmModel,
+    'cycle_number' : IDL.Nat64,
+  });
+  const WrapOptions = IDL.Record({
+    'hanging_indent' : IDL.Nat32,
+    'width' : IDL.Nat32,
   });
-  const ConversationWithMessages = IDL.Record({
-    'messages' : IDL.Vec(StoredMessage),
-    'conversation' : Conversation,
+  const NoveltyOptions = IDL.Record({
+    'threshold' : IDL.Float32,
+    'window' : IDL.Nat32,
+    'max_reprompts' : IDL.Nat8,
   });
+  const OutputContract = IDL.Variant({
+    'Json' : IDL.Null,
+    'Labels' : IDL.Null,
+  });
+  const GenerationConfig = IDL.Record({
+    'memory' : IDL.Opt(MemoryOptions),
+    'model' : LlmModel,
+    'cycle_overrides' : IDL.Vec(CycleOverride),
+    'wrap' : IDL.Opt(WrapOptions),
+    'max_correction_retries' : IDL.Nat8,
+    'novelty' : IDL.Opt(NoveltyOptions),
+    'output_contract' : IDL.Opt(OutputContract),
+    'correction_model' : LlmModel,
+  });
+  const Result_4 = IDL.Variant({ 'Ok' : GenerationConfig, 'Err' : PoetError });
+  const GenerationStats = IDL.Record({
+    'total_poems' : IDL.Nat64,
+    'fallback_used' : IDL.Nat64,
+    'primary_success' : IDL.Nat64,
+    'correction_used' : IDL.Nat64,
+    'algorithmic_used' : IDL.Nat64,
+    'structured_success' : IDL.Nat64,
+  });
+  const MetaFormRevision = IDL.Record({
+    'source' : IDL.Text,
+    'restored_from' : IDL.Opt(IDL.Nat32),
+    'created_at' : IDL.Nat64,
+    'author' : IDL.Opt(IDL.Principal),
+    'revision' : IDL.Nat32,
+  });
+  const Result_5 = IDL.Variant({ 'Ok' : MetaFormRevision, 'Err' : PoetError });
   const PoetState = IDL.Record({
+    'model' : IDL.Opt(LlmModel),
+    'name' : IDL.Text,
     'total_poems' : IDL.Nat64,
     'last_updated' : IDL.Nat64,
+    'active_branch' : IDL.Opt(IDL.Nat32),
+    'persona' : IDL.Opt(IDL.Text),
     'genesis_prompt' : IDL.Text,
+    'meta_form' : IDL.Text,
+    'current_cycle' : IDL.Nat64,
+  });
+  const RecurringTheme = IDL.Record({
+    'cycles' : IDL.Vec(IDL.Nat64),
+    'keyword' : IDL.Text,
+  });
+  const ScheduleConfig = IDL.Record({
+    'interval_secs' : IDL.Nat64,
+    'max_cycles_per_day' : IDL.Nat32,
+    'jitter_secs' : IDL.Nat64,
+    'paused' : IDL.Bool,
+  });
+  const ScheduleState = IDL.Record({
+    'day' : IDL.Nat64,
+    'last_run_at' : IDL.Opt(IDL.Nat64),
+    'last_outcome' : IDL.Opt(IDL.Text),
+    'next_run_at' : IDL.Opt(IDL.Nat64),
+    'config' : ScheduleConfig,
+    'cycles_today' : IDL.Nat32,
+  });
+  const StatsBucket = IDL.Variant({ 'Day' : IDL.Null, 'Week' : IDL.Null });
+  const StatsRange = IDL.Record({
+    'to' : IDL.Opt(IDL.Nat64),
+    'from' : IDL.Opt(IDL.Nat64),
+  });
+  const StatsPoint = IDL.Record({
+    'llm_calls' : IDL.Nat64,
+    'avg_next_prompt_chars' : IDL.Float64,
+    'max_llm_latency_ms' : IDL.Opt(IDL.Float64),
+    'methods' : GenerationStats,
+    'avg_title_chars' : IDL.Float64,
+    'avg_poem_chars' : IDL.Float64,
+    'avg_poem_lines' : IDL.Float64,
+    'bucket_start' : IDL.Nat64,
+    'correction_rate' : IDL.Float64,
+    'avg_llm_latency_ms' : IDL.Opt(IDL.Float64),
+  });
+  const TemplateName = IDL.Variant({
+    'MetaForm' : IDL.Null,
+    'FormatCorrection' : IDL.Null,
+    'Correction' : IDL.Null,
+  });
+  const TemplateVersion = IDL.Record({
+    'source' : IDL.Text,
+    'name' : TemplateName,
+    'created_at' : IDL.Nat64,
+    'author' : IDL.Opt(IDL.Principal),
+    'version' : IDL.Nat32,
+  });
+  const Result_6 = IDL.Variant({
+    'Ok' : IDL.Opt(TemplateVersion),
+    'Err' : PoetError,
+  });
+  const ThemeEdge = IDL.Record({
+    'to' : IDL.Text,
+    'weight' : IDL.Nat32,
+    'from' : IDL.Text,
+  });
+  const CycleThemes = IDL.Record({
+    'cycle_number' : IDL.Nat64,
+    'themes' : IDL.Vec(IDL.Text),
+    'drift' : IDL.Opt(IDL.Float32),
+    'parent' : IDL.Nat64,
+  });
+  const ThemeNode = IDL.Record({
+    'theme' : IDL.Text,
+    'cycles' : IDL.Nat32,
+    'last_cycle' : IDL.Nat64,
+    'first_cycle' : IDL.Nat64,
+  });
+  const ThemeGraph = IDL.Record({
+    'edges' : IDL.Vec(ThemeEdge),
+    'cycles' : IDL.Vec(CycleThemes),
+    'nodes' : IDL.Vec(ThemeNode),
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : PoetError });
+  const HttpRequest = IDL.Record({
+    'url' : IDL.Text,
+    'method' : IDL.Text,
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+  });
+  const HttpResponse = IDL.Record({
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'status_code' : IDL.Nat16,
+  });
+  const Result_8 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : PoetError });
+  const BranchSummary = IDL.Record({
+    'active' : IDL.Bool,
+    'cycle_count' : IDL.Nat64,
+    'branch_id' : IDL.Nat32,
+    'head' : IDL.Opt(IDL.Nat64),
+    'created_at' : IDL.Nat64,
+    'prompt' : IDL.Opt(IDL.Text),
+    'forked_from' : IDL.Nat64,
+  });
+  const Result_9 = IDL.Variant({
+    'Ok' : IDL.Vec(MetaFormRevision),
+    'Err' : PoetError,
+  });
+  const ListOrder = IDL.Variant({
+    'Descending' : IDL.Null,
+    'Ascending' : IDL.Null,
+  });
+  const PoemFilter = IDL.Record({
+    'cycle_from' : IDL.Opt(IDL.Nat64),
+    'methods' : IDL.Opt(IDL.Vec(GenerationMethod)),
+    'title_contains' : IDL.Opt(IDL.Text),
+    'created_to' : IDL.Opt(IDL.Nat64),
+    'cycle_to' : IDL.Opt(IDL.Nat64),
+    'created_from' : IDL.Opt(IDL.Nat64),
+  });
+  const PoemPage = IDL.Record({
+    'next_cursor' : IDL.Opt(IDL.Nat64),
+    'items' : IDL.Vec(PoemSummary),
+  });
+  const PoetSummary = IDL.Record({
+    'poet_id' : IDL.Nat32,
+    'model' : IDL.Opt(LlmModel),
+    'name' : IDL.Text,
+    'total_poems' : IDL.Nat64,
+    'last_updated' : IDL.Nat64,
     'current_cycle' : IDL.Nat64,
   });
+  const RoleGrant = IDL.Record({
+    'updated_at' : IDL.Nat64,
+    'principal' : IDL.Principal,
+    'granted_by' : IDL.Principal,
+    'roles' : IDL.Vec(Role),
+  });
+  const Result_10 = IDL.Variant({
+    'Ok' : IDL.Vec(RoleGrant),
+    'Err' : PoetError,
+  });
+  const Result_11 = IDL.Variant({
+    'Ok' : IDL.Vec(TemplateVersion),
+    'Err' : PoetError,
+  });
+  const Result_12 = IDL.Variant({ 'Ok' : ScheduleState, 'Err' : PoetError });
+  const Result_13 = IDL.Variant({ 'Ok' : TemplateVersion, 'Err' : PoetError });
   return IDL.Service({
-    'chat' : IDL.Func([IDL.Vec(ChatMessage)], [IDL.Text], []),
-    'chat_with_storage' : IDL.Func(
-        [IDL.Opt(IDL.Nat64), IDL.Vec(ChatMessage)],
-        [IDL.Nat64, IDL.Text],
+    'create_poet' : IDL.Func(
+        [IDL.Text, IDL.Text, IDL.Opt(LlmModel)],
+        [Result],
+        [],
+      ),
+    'diff_meta_form' : IDL.Func(
+        [IDL.Nat32, IDL.Nat32, IDL.Opt(IDL.Nat32)],
+        [Result_1],
+        ['query'],
+      ),
+    'evolve_poet' : IDL.Func([IDL.Nat32], [Result_2], []),
+    'find_near_duplicates' : IDL.Func(
+        [IDL.Nat32, IDL.Opt(IDL.Float32)],
+        [IDL.Vec(NearDuplicate)],
+        ['query'],
+      ),
+    'fork_from_cycle' : IDL.Func(
+        [IDL.Nat32, IDL.Nat64, IDL.Text],
+        [Result],
         [],
       ),
-    'delete_conversation' : IDL.Func([IDL.Nat64], [IDL.Bool], []),
-    'evolve_poet' : IDL.Func([], [Result], []),
-    'get_all_poems' : IDL.Func([], [IDL.Vec(PoemCycle)], ['query']),
-    'get_conversation_messages' : IDL.Func(
-        [IDL.Nat64],
-        [IDL.Vec(StoredMessage)],
+    'get_all_poems' : IDL.Func([IDL.Nat32], [IDL.Vec(PoemCycle)], ['query']),
+    'get_ancestry' : IDL.Func(
+        [IDL.Nat32, IDL.Nat64, IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(PoemSummary)],
         ['query'],
       ),
-    'get_conversation_with_messages' : IDL.Func(
-        [IDL.Nat64],
-        [IDL.Opt(ConversationWithMessages)],
+    'get_certified_current_poem' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Opt(CertifiedPoem)],
         ['query'],
       ),
-    'get_conversations' : IDL.Func([], [IDL.Vec(Conversation)], ['query']),
-    'get_current_poem' : IDL.Func([], [IDL.Opt(PoemCycle)], ['query']),
+    'get_certified_poem' : IDL.Func(
+        [IDL.Nat32, IDL.Nat64],
+        [IDL.Opt(CertifiedPoem)],
+        ['query'],
+      ),
+    'get_current_poem' : IDL.Func([IDL.Nat32], [IDL.Opt(PoemCycle)], ['query']),
+    'get_era_summaries' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Vec(EraSummary)],
+        ['query'],
+      ),
+    'get_evolution_status' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Opt(EvolutionStatus)],
+        ['query'],
+      ),
+    'get_feed_config' : IDL.Func([], [Result_3], ['query']),
+    'get_generation_config' : IDL.Func([], [Result_4], ['query']),
+    'get_generation_stats' : IDL.Func(
+        [IDL.Nat32],
+        [GenerationStats],
+        ['query'],
+      ),
+    'get_meta_form' : IDL.Func([IDL.Nat32], [Result_5], ['query']),
+    'get_my_roles' : IDL.Func([], [IDL.Vec(Role)], ['query']),
     'get_poem_by_cycle' : IDL.Func(
-        [IDL.Nat64],
+        [IDL.Nat32, IDL.Nat64],
         [IDL.Opt(PoemCycle)],
         ['query'],
       ),
-    'get_poet_state' : IDL.Func([], [IDL.Opt(PoetState)], ['query']),
-    'prompt' : IDL.Func([IDL.Text], [IDL.Text], []),
-    'reset_poet' : IDL.Func([], [IDL.Bool], []),
-    'update_conversation_title' : IDL.Func(
-        [IDL.Nat64, IDL.Text],
-        [IDL.Bool],
+    'get_poem_count' : IDL.Func([IDL.Nat32], [IDL.Nat64], ['query']),
+    'get_poet_state' : IDL.Func([IDL.Nat32], [IDL.Opt(PoetState)], ['query']),
+    'get_raw_response' : IDL.Func(
+        [IDL.Nat32, IDL.Nat64],
+        [IDL.Opt(IDL.Text)],
+        ['query'],
+      ),
+    'get_recurring_themes' : IDL.Func(
+        [IDL.Nat32, IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(RecurringTheme)],
+        ['query'],
+      ),
+    'get_schedule' : IDL.Func([], [ScheduleState], ['query']),
+    'get_schema_version' : IDL.Func([], [IDL.Nat32], ['query']),
+    'get_stats_timeseries' : IDL.Func(
+        [IDL.Nat32, StatsBucket, StatsRange],
+        [IDL.Vec(StatsPoint)],
+        ['query'],
+      ),
+    'get_template' : IDL.Func(
+        [TemplateName, IDL.Opt(IDL.Nat32)],
+        [Result_6],
+        ['query'],
+      ),
+    'get_theme_graph' : IDL.Func(
+        [IDL.Nat32, IDL.Opt(IDL.Nat32), IDL.Opt(IDL.Nat64)],
+        [ThemeGraph],
+        ['query'],
+      ),
+    'grant_owner' : IDL.Func([IDL.Principal], [Result_7], []),
+    'grant_role' : IDL.Func([IDL.Principal, Role], [Result_7], []),
+    'http_request' : IDL.Func([HttpRequest], [HttpResponse], ['query']),
+    'initialize_poet' : IDL.Func([IDL.Nat32], [Result_8], []),
+    'is_poet_initialized' : IDL.Func([IDL.Nat32], [IDL.Bool], ['query']),
+    'list_branches' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Vec(BranchSummary)],
+        ['query'],
+      ),
+    'list_meta_form_revisions' : IDL.Func([IDL.Nat32], [Result_9], ['query']),
+    'list_poems' : IDL.Func(
+        [
+          IDL.Nat32,
+          IDL.Opt(IDL.Nat64),
+          IDL.Opt(IDL.Nat32),
+          ListOrder,
+          IDL.Opt(PoemFilter),
+        ],
+        [PoemPage],
+        ['query'],
+      ),
+    'list_poets' : IDL.Func([], [IDL.Vec(PoetSummary)], ['query']),
+    'list_role_grants' : IDL.Func([], [Result_10], ['query']),
+    'list_template_versions' : IDL.Func([TemplateName], [Result_11], ['query']),
+    'pause_schedule' : IDL.Func([], [Result_12], []),
+    'replay_cycle' : IDL.Func([IDL.Nat32, IDL.Nat64], [Result_2], ['query']),
+    'reset_poet' : IDL.Func([IDL.Nat32], [Result_7], []),
+    'resume_schedule' : IDL.Func([], [Result_12], []),
+    'revoke_role' : IDL.Func([IDL.Principal, Role], [Result_7], []),
+    'rollback_meta_form' : IDL.Func([IDL.Nat32, IDL.Nat32], [Result_5], []),
+    'set_feed_config' : IDL.Func([FeedConfig], [Result_3], []),
+    'set_generation_config' : IDL.Func([GenerationConfig], [Result_4], []),
+    'set_meta_form' : IDL.Func([IDL.Nat32, IDL.Text], [Result_5], []),
+    'set_next_prompt' : IDL.Func([IDL.Nat32, IDL.Text], [Result_7], []),
+    'set_poet_model' : IDL.Func([IDL.Nat32, IDL.Opt(LlmModel)], [Result_7], []),
+    'set_poet_persona' : IDL.Func(
+        [IDL.Nat32, IDL.Opt(IDL.Text)],
+        [Result_7],
         [],
       ),
+    'set_schedule' : IDL.Func([ScheduleConfig], [Result_12], []),
+    'set_template' : IDL.Func([TemplateName, IDL.Text], [Result_13], []),
   });
 };
 export const init = ({ IDL }) => { return []; };